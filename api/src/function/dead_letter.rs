@@ -0,0 +1,114 @@
+use derive_more::derive::Display;
+use serde::{Deserialize, Serialize};
+
+use crate::types::{Id, TimeStamp};
+
+use super::execution::{ExecutionId, ExecutionResult, Trigger};
+
+/// A request invoked in the background, kept as received so that it can be invoked again
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AsyncRequest {
+    /// `name`, `name@alias` or `name@revision`
+    pub function: String,
+    #[serde(default)]
+    pub path: Option<String>,
+    #[serde(default)]
+    pub query: Option<String>,
+    pub method: String,
+    /// Headers whose values aren't text are left out
+    #[serde(default)]
+    pub headers: Vec<(String, String)>,
+    /// Base64 encoded so that any body can be kept
+    #[serde(default, with = "base64_body")]
+    pub body: Vec<u8>,
+    #[serde(default)]
+    pub trigger: Trigger,
+}
+
+impl AsyncRequest {
+    /// The function's name without the alias or revision
+    pub fn function_name(&self) -> &str {
+        self.function
+            .split_once('@')
+            .map_or(self.function.as_str(), |(name, _)| name)
+    }
+}
+
+mod base64_body {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(body: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(body))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        STANDARD
+            .decode(String::deserialize(deserializer)?)
+            .map_err(D::Error::custom)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Display, Debug)]
+pub struct DeadLetterId(Id);
+
+impl DeadLetterId {
+    pub fn id(&self) -> &Id {
+        &self.0
+    }
+}
+
+/// An asynchronous request whose attempts all failed, with the outcome of the last one
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DeadLetter {
+    id: DeadLetterId,
+    create_time: TimeStamp,
+    request: AsyncRequest,
+    attempts: u32,
+    /// Unset when the gateway turned the last attempt away before creating an execution
+    execution: Option<ExecutionId>,
+    /// Unset when the worker didn't report the last execution's result
+    result: Option<ExecutionResult>,
+    error: String,
+}
+
+impl DeadLetter {
+    pub fn new(
+        request: AsyncRequest,
+        attempts: u32,
+        execution: Option<ExecutionId>,
+        result: Option<ExecutionResult>,
+        error: String,
+    ) -> Self {
+        DeadLetter {
+            id: DeadLetterId(Id::new()),
+            create_time: TimeStamp::now(),
+            request,
+            attempts,
+            execution,
+            result,
+            error,
+        }
+    }
+    pub fn id(&self) -> &DeadLetterId {
+        &self.id
+    }
+    pub fn create_time(&self) -> &TimeStamp {
+        &self.create_time
+    }
+    pub fn request(&self) -> &AsyncRequest {
+        &self.request
+    }
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+    pub fn execution(&self) -> Option<&ExecutionId> {
+        self.execution.as_ref()
+    }
+    pub fn result(&self) -> Option<&ExecutionResult> {
+        self.result.as_ref()
+    }
+    pub fn error(&self) -> &str {
+        &self.error
+    }
+}
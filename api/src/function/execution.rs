@@ -1,14 +1,55 @@
+use derive_more::derive::Display;
+use serde::{Deserialize, Serialize};
+
 use crate::{
+    pipeline::PipelineExecutionId,
     types::{ExitKind, Id, JsonData, TimeStamp},
     worker::WorkerId,
+    workflow::WorkflowExecutionId,
 };
 
-use super::registration::FunctionId;
+use super::registration::{FunctionId, RevisionNumber};
+
+/// Header carrying the execution id from the gateway to the worker and back to the caller
+pub const EXECUTION_ID_HEADER: &str = "x-wasi-faas-execution-id";
+/// Header carrying the revision of the function the gateway picked for an execution
+pub const REVISION_HEADER: &str = "x-wasi-faas-revision";
+/// Header carrying the id of the function the gateway resolved for an execution, a function registered again under
+/// the same name gets a new id
+pub const FUNCTION_ID_HEADER: &str = "x-wasi-faas-function-id";
+/// Header asking the gateway to accept a request and invoke the function in the background when set to `async`
+pub const INVOCATION_TYPE_HEADER: &str = "x-wasi-faas-invocation-type";
 
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Input(JsonData);
 
+impl Input {
+    pub fn data(&self) -> &JsonData {
+        &self.0
+    }
+}
+
+impl From<JsonData> for Input {
+    fn from(data: JsonData) -> Self {
+        Input(data)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Output(JsonData);
 
+impl Output {
+    pub fn data(&self) -> &JsonData {
+        &self.0
+    }
+}
+
+impl From<JsonData> for Output {
+    fn from(data: JsonData) -> Self {
+        Output(data)
+    }
+}
+
 impl TryFrom<Vec<u8>> for Output {
     type Error = serde_json::Error;
     fn try_from(value: Vec<u8>) -> Result<Self, Self::Error> {
@@ -16,16 +57,99 @@ impl TryFrom<Vec<u8>> for Output {
     }
 }
 
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Display, Debug)]
 pub struct ExecutionRequestId(Id);
 
+impl ExecutionRequestId {
+    pub fn id(&self) -> &Id {
+        &self.0
+    }
+}
+
+/// What caused an execution
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Trigger {
+    /// A request to the gateway
+    #[default]
+    Http,
+    /// A run of a cron trigger, for the time it was scheduled at
+    Cron {
+        trigger: String,
+        scheduled_time: TimeStamp,
+    },
+    /// A delivery to a webhook, with its id when the webhook deduplicates deliveries
+    Webhook {
+        webhook: String,
+        delivery: Option<String>,
+    },
+    /// A step of a pipeline execution, counted from 0
+    Pipeline {
+        pipeline: String,
+        execution: PipelineExecutionId,
+        step: usize,
+    },
+    /// A call of a workflow execution, by the path of its step
+    Workflow {
+        workflow: String,
+        execution: WorkflowExecutionId,
+        step: String,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct ExecutionRequest {
     id: ExecutionRequestId,
     create_time: TimeStamp,
-    input: Input,
+    /// Unset for functions which receive the raw HTTP request
+    input: Option<Input>,
     target_function: FunctionId,
+    /// The revision of the function serving the request
+    revision: RevisionNumber,
+    #[serde(default)]
+    trigger: Trigger,
 }
 
+impl ExecutionRequest {
+    pub fn new(
+        target_function: FunctionId,
+        revision: RevisionNumber,
+        input: Option<Input>,
+        trigger: Trigger,
+    ) -> Self {
+        ExecutionRequest {
+            id: ExecutionRequestId(Id::new()),
+            create_time: TimeStamp::now(),
+            input,
+            target_function,
+            revision,
+            trigger,
+        }
+    }
+    pub fn id(&self) -> &ExecutionRequestId {
+        &self.id
+    }
+    pub fn create_time(&self) -> &TimeStamp {
+        &self.create_time
+    }
+    pub fn input(&self) -> Option<&Input> {
+        self.input.as_ref()
+    }
+    pub fn target_function(&self) -> &FunctionId {
+        &self.target_function
+    }
+    pub fn revision(&self) -> RevisionNumber {
+        self.revision
+    }
+    pub fn trigger(&self) -> &Trigger {
+        &self.trigger
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Default, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
 pub enum ExecutionStatus {
+    #[default]
     Created,
     Assigned,
     Started,
@@ -33,9 +157,38 @@ pub enum ExecutionStatus {
     Unknown,
 }
 
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Display, Debug)]
 pub struct ExecutionResultId(Id);
 
-// TODO: it'd be nice to have some way of getting logs
+impl ExecutionResultId {
+    pub fn id(&self) -> &Id {
+        &self.0
+    }
+}
+
+/// A line of output recorded by the worker while the function ran
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LogEntry {
+    time: TimeStamp,
+    message: String,
+}
+
+impl LogEntry {
+    pub fn new(message: String) -> Self {
+        LogEntry {
+            time: TimeStamp::now(),
+            message,
+        }
+    }
+    pub fn time(&self) -> &TimeStamp {
+        &self.time
+    }
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct ExecutionResult {
     id: ExecutionResultId,
     create_time: TimeStamp,
@@ -43,13 +196,129 @@ pub struct ExecutionResult {
     exit: ExitKind,
     worker: WorkerId,
     complete_time: Option<TimeStamp>,
+    #[serde(default)]
+    logs: Vec<LogEntry>,
+    /// Why the execution failed, including a successful run whose output breaks the function's declared output
+    #[serde(default)]
+    error: Option<String>,
 }
 
+impl ExecutionResult {
+    /// Result of an execution which started at `create_time` and has just completed
+    pub fn new(
+        create_time: TimeStamp,
+        outcome: Result<Option<Output>, String>,
+        exit: ExitKind,
+        worker: WorkerId,
+        logs: Vec<LogEntry>,
+    ) -> Self {
+        let (output_data, error) = match outcome {
+            Ok(output) => (output, None),
+            Err(error) => (None, Some(error)),
+        };
+        ExecutionResult {
+            id: ExecutionResultId(Id::new()),
+            create_time,
+            output_data,
+            exit,
+            worker,
+            complete_time: Some(TimeStamp::now()),
+            logs,
+            error,
+        }
+    }
+    pub fn id(&self) -> &ExecutionResultId {
+        &self.id
+    }
+    pub fn create_time(&self) -> &TimeStamp {
+        &self.create_time
+    }
+    pub fn output_data(&self) -> Option<&Output> {
+        self.output_data.as_ref()
+    }
+    pub fn exit(&self) -> &ExitKind {
+        &self.exit
+    }
+    pub fn worker(&self) -> &WorkerId {
+        &self.worker
+    }
+    pub fn complete_time(&self) -> Option<&TimeStamp> {
+        self.complete_time.as_ref()
+    }
+    pub fn logs(&self) -> &[LogEntry] {
+        &self.logs
+    }
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Display, Debug)]
 pub struct ExecutionId(Id);
 
+impl ExecutionId {
+    pub fn new() -> Self {
+        ExecutionId(Id::new())
+    }
+    pub fn parse(s: &str) -> Result<Self, uuid::Error> {
+        Ok(ExecutionId(Id::parse(s)?))
+    }
+    pub fn id(&self) -> &Id {
+        &self.0
+    }
+}
+
+impl Default for ExecutionId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Execution {
     id: ExecutionId,
     request: ExecutionRequest,
     result: Option<ExecutionResult>,
     status: ExecutionStatus,
+    /// Where the execution falls among those whose result the control plane has stored, in the order it stored them
+    #[serde(default)]
+    sequence: Option<u64>,
+}
+
+impl Execution {
+    pub fn new(request: ExecutionRequest) -> Self {
+        Execution {
+            id: ExecutionId::new(),
+            request,
+            result: None,
+            status: ExecutionStatus::Created,
+            sequence: None,
+        }
+    }
+    pub fn id(&self) -> &ExecutionId {
+        &self.id
+    }
+    pub fn request(&self) -> &ExecutionRequest {
+        &self.request
+    }
+    pub fn result(&self) -> Option<&ExecutionResult> {
+        self.result.as_ref()
+    }
+    pub fn status(&self) -> &ExecutionStatus {
+        &self.status
+    }
+    pub fn update_status(&mut self, status: ExecutionStatus) {
+        self.status = status;
+    }
+    pub fn sequence(&self) -> Option<u64> {
+        self.sequence
+    }
+    pub fn complete(&mut self, result: ExecutionResult) {
+        self.result = Some(result);
+        self.status = ExecutionStatus::Completed;
+    }
+    /// Record where the control plane stored the execution's result among the others
+    pub fn set_sequence(&mut self, sequence: u64) {
+        self.sequence = Some(sequence);
+    }
 }
@@ -1,2 +1,4 @@
+pub mod dead_letter;
 pub mod execution;
 pub mod registration;
+pub mod retry;
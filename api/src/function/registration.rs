@@ -1,27 +1,380 @@
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+
 use derive_more::derive::Display;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
-use crate::types::{BlobAddress, Id, TimeStamp};
+use crate::{
+    function::retry::RetryPolicy,
+    types::{BlobAddress, Id, JsonData, TimeStamp},
+};
 
-#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[serde(from = "InputKindRepr")]
 pub enum InputKind {
     None,
     List(Box<InputKind>),
-    Object,
+    /// An object with at least the given fields, other fields aren't checked
+    Object(BTreeMap<String, InputKind>),
     String,
     Number,
 }
 
-#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
-enum Runtime {
+/// What an `InputKind` is deserialized from, which includes the plain `"Object"` saved before objects declared their
+/// fields
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum InputKindRepr {
+    Current(Kind),
+    Legacy(LegacyKind),
+}
+
+#[derive(Deserialize)]
+#[serde(rename = "InputKind")]
+enum Kind {
+    None,
+    List(Box<InputKind>),
+    Object(BTreeMap<String, InputKind>),
+    String,
+    Number,
+}
+
+#[derive(Deserialize)]
+#[serde(rename = "InputKind")]
+enum LegacyKind {
+    /// Any object
+    Object,
+}
+
+impl From<InputKindRepr> for InputKind {
+    fn from(repr: InputKindRepr) -> Self {
+        match repr {
+            InputKindRepr::Current(Kind::None) => InputKind::None,
+            InputKindRepr::Current(Kind::List(kind)) => InputKind::List(kind),
+            InputKindRepr::Current(Kind::Object(fields)) => InputKind::Object(fields),
+            InputKindRepr::Current(Kind::String) => InputKind::String,
+            InputKindRepr::Current(Kind::Number) => InputKind::Number,
+            InputKindRepr::Legacy(LegacyKind::Object) => InputKind::Object(BTreeMap::new()),
+        }
+    }
+}
+
+/// Outputs are described the same way as inputs
+pub type OutputKind = InputKind;
+
+/// Where and why a value doesn't match its declared kind or schema, `path` is a JSON pointer to the offending element
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct ValidationError {
+    path: String,
+    message: String,
+}
+
+impl std::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{}: {}", self.path, self.message)
+        }
+    }
+}
+
+impl ValidationError {
+    pub fn new(path: String, message: String) -> Self {
+        ValidationError { path, message }
+    }
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl InputKind {
+    pub fn validate(&self, value: &Value) -> Result<(), ValidationError> {
+        self.validate_at(value, &mut String::new())
+    }
+
+    fn validate_at(&self, value: &Value, path: &mut String) -> Result<(), ValidationError> {
+        let matches = match (self, value) {
+            (InputKind::None, Value::Null)
+            | (InputKind::String, Value::String(_))
+            | (InputKind::Number, Value::Number(_)) => true,
+            (InputKind::List(kind), Value::Array(items)) => {
+                for (i, item) in items.iter().enumerate() {
+                    kind.validate_child(item, path, &i.to_string())?;
+                }
+                true
+            }
+            (InputKind::Object(fields), Value::Object(object)) => {
+                for (name, kind) in fields {
+                    // escape the field name as a JSON pointer reference token
+                    let token = name.replace('~', "~0").replace('/', "~1");
+                    match object.get(name) {
+                        Some(field) => kind.validate_child(field, path, &token)?,
+                        None => {
+                            return Err(ValidationError::new(
+                                format!("{path}/{token}"),
+                                format!("missing field, expected {}", kind.describe()),
+                            ))
+                        }
+                    }
+                }
+                true
+            }
+            _ => false,
+        };
+        if matches {
+            Ok(())
+        } else {
+            Err(ValidationError::new(
+                path.clone(),
+                format!("expected {} but got {value}", self.describe()),
+            ))
+        }
+    }
+
+    fn validate_child(
+        &self,
+        value: &Value,
+        path: &mut String,
+        token: &str,
+    ) -> Result<(), ValidationError> {
+        let len = path.len();
+        path.push('/');
+        path.push_str(token);
+        self.validate_at(value, path)?;
+        path.truncate(len);
+        Ok(())
+    }
+
+    fn describe(&self) -> &'static str {
+        match self {
+            InputKind::None => "null",
+            InputKind::List(_) => "a list",
+            InputKind::Object(_) => "an object",
+            InputKind::String => "a string",
+            InputKind::Number => "a number",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Runtime {
+    /// A core wasm module targeting WASI preview 1, invoked through an exported function taking numeric params
     Wasm,
-    // TODO: figure out how to support other runtimes
+    /// A component targeting WASI 0.2 which implements the `handler` world in `worker/wit`
+    Component,
+    /// A component targeting WASI 0.2 which exports `wasi:http/incoming-handler`, it receives the gateway request as is
+    HttpHandler,
 }
 
-#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Display)]
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Display, Debug)]
 pub struct FunctionId(Id);
 
-#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
+impl FunctionId {
+    pub fn parse(s: &str) -> Result<Self, uuid::Error> {
+        Ok(FunctionId(Id::parse(s)?))
+    }
+}
+
+/// Used for outbound requests when the function doesn't set its own timeout
+const DEFAULT_EGRESS_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A host a function may send outbound HTTP requests to. `*.example.com` matches any subdomain of `example.com` and
+/// any port is allowed when `port` is unset.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct AllowedHost {
+    host: String,
+    port: Option<u16>,
+}
+
+impl AllowedHost {
+    pub fn new(host: impl Into<String>, port: Option<u16>) -> Self {
+        AllowedHost {
+            host: host.into(),
+            port,
+        }
+    }
+    pub fn matches(&self, host: &str, port: u16) -> bool {
+        if self.port.is_some_and(|allowed| allowed != port) {
+            return false;
+        }
+        match self.host.strip_prefix("*.") {
+            Some(domain) => host
+                .len()
+                .checked_sub(domain.len() + 1)
+                .is_some_and(|split| {
+                    let host = host.as_bytes();
+                    host[split] == b'.' && host[split + 1..].eq_ignore_ascii_case(domain.as_bytes())
+                }),
+            None => host.eq_ignore_ascii_case(&self.host),
+        }
+    }
+}
+
+/// Outbound HTTP a function may make, functions have no network access unless they declare allowed hosts
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord, Default, Debug)]
+pub struct EgressPolicy {
+    #[serde(default)]
+    allow: Vec<AllowedHost>,
+    /// Timeout applied to each outbound request, in milliseconds
+    #[serde(default)]
+    timeout_ms: Option<u64>,
+}
+
+impl EgressPolicy {
+    pub fn new(allow: Vec<AllowedHost>, timeout: Option<Duration>) -> Self {
+        EgressPolicy {
+            allow,
+            timeout_ms: timeout.map(|timeout| timeout.as_millis() as u64),
+        }
+    }
+    pub fn allow(&self) -> &[AllowedHost] {
+        &self.allow
+    }
+    pub fn allows(&self, host: &str, port: u16) -> bool {
+        self.allow.iter().any(|allowed| allowed.matches(host, port))
+    }
+    pub fn timeout(&self) -> Duration {
+        self.timeout_ms
+            .map(Duration::from_millis)
+            .unwrap_or(DEFAULT_EGRESS_TIMEOUT)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Display, Debug)]
+pub struct RevisionNumber(u32);
+
+impl RevisionNumber {
+    pub fn parse(s: &str) -> Result<Self, std::num::ParseIntError> {
+        Ok(RevisionNumber(s.parse()?))
+    }
+    pub fn next(&self) -> Self {
+        RevisionNumber(self.0 + 1)
+    }
+}
+
+/// An immutable version of a function's code, every upload creates a new one
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct Revision {
+    number: RevisionNumber,
+    blob_address: BlobAddress,
+    create_time: TimeStamp,
+    note: String,
+}
+
+impl Revision {
+    pub fn number(&self) -> RevisionNumber {
+        self.number
+    }
+    pub fn blob_address(&self) -> &BlobAddress {
+        &self.blob_address
+    }
+    pub fn create_time(&self) -> &TimeStamp {
+        &self.create_time
+    }
+    pub fn note(&self) -> &str {
+        &self.note
+    }
+}
+
+/// Alias which always resolves to the newest revision of a function and can't be set
+pub const LATEST_ALIAS: &str = "latest";
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct WeightedRevision {
+    pub revision: RevisionNumber,
+    pub weight: u32,
+}
+
+/// How the gateway assigns requests to the revisions of a split
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Default, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum SplitMode {
+    /// Every request is assigned independently
+    #[default]
+    Random,
+    /// Requests with the same value of the header are assigned to the same revision, requests without it randomly
+    Sticky { header: String },
+}
+
+/// Shift all traffic to a revision once any other revision of the split fails too often
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct AutoRollback {
+    pub to: RevisionNumber,
+    /// Fraction of failed executions, between 0 and 1, above which a revision is rolled back
+    pub max_failure_rate: f64,
+    /// Executions a revision needs to have completed before its failure rate is trusted
+    #[serde(default = "default_min_executions")]
+    pub min_executions: u32,
+}
+
+fn default_min_executions() -> u32 {
+    10
+}
+
+/// Route a function's requests to a weighted set of its revisions, e.g. 95/5 between a stable revision and a canary,
+/// instead of only its current revision
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct TrafficSplit {
+    pub revisions: Vec<WeightedRevision>,
+    #[serde(default)]
+    pub mode: SplitMode,
+    #[serde(default)]
+    pub auto_rollback: Option<AutoRollback>,
+}
+
+impl TrafficSplit {
+    /// Pick the revision at `point` along the weights, wrapping around their total so that any number can be used
+    pub fn pick(&self, point: u64) -> RevisionNumber {
+        let total = self
+            .revisions
+            .iter()
+            .map(|r| u64::from(r.weight))
+            .sum::<u64>();
+        let mut point = point % total.max(1);
+        for target in &self.revisions {
+            if point < u64::from(target.weight) {
+                return target.revision;
+            }
+            point -= u64::from(target.weight);
+        }
+        self.revisions[0].revision
+    }
+}
+
+/// What a client submits to register a function, the control plane assigns the id and create time
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FunctionSpec {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub runtime: Runtime,
+    pub input_type: InputKind,
+    /// A JSON Schema document the input is validated against on top of `input_type`
+    #[serde(default)]
+    pub input_schema: Option<JsonData>,
+    /// The output isn't checked when unset
+    #[serde(default)]
+    pub output_type: Option<OutputKind>,
+    /// A JSON Schema document the output is validated against on top of `output_type`
+    #[serde(default)]
+    pub output_schema: Option<JsonData>,
+    /// The code of the function's first revision
+    pub blob_address: BlobAddress,
+    /// Author note recorded on the first revision
+    #[serde(default)]
+    pub note: String,
+    #[serde(default)]
+    pub egress: EgressPolicy,
+    /// How asynchronous invocations are retried, a single attempt being made when unset
+    #[serde(default)]
+    pub retry: Option<RetryPolicy>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
 pub struct Function {
     id: FunctionId,
     name: String,
@@ -29,37 +382,578 @@ pub struct Function {
     create_time: TimeStamp,
     runtime: Runtime,
     input_type: InputKind,
-    blob_address: BlobAddress,
+    #[serde(default)]
+    input_schema: Option<Schema>,
+    #[serde(default)]
+    output_type: Option<OutputKind>,
+    #[serde(default)]
+    output_schema: Option<Schema>,
+    #[serde(default)]
+    egress: EgressPolicy,
+    #[serde(default)]
+    retry: Option<RetryPolicy>,
+    revisions: Vec<Revision>,
+    current_revision: RevisionNumber,
+    #[serde(default)]
+    traffic: Option<TrafficSplit>,
+    /// Names such as `prod` or `staging` pointing at revisions
+    #[serde(default)]
+    aliases: BTreeMap<String, RevisionNumber>,
+}
+
+impl Function {
+    pub fn new(spec: FunctionSpec) -> Self {
+        let first = Revision {
+            number: RevisionNumber(1),
+            blob_address: spec.blob_address,
+            create_time: TimeStamp::now(),
+            note: spec.note,
+        };
+        Function {
+            id: FunctionId(Id::new()),
+            name: spec.name,
+            description: spec.description,
+            create_time: TimeStamp::now(),
+            runtime: spec.runtime,
+            input_type: spec.input_type,
+            input_schema: spec.input_schema.map(Schema::from),
+            output_type: spec.output_type,
+            output_schema: spec.output_schema.map(Schema::from),
+            egress: spec.egress,
+            retry: spec.retry,
+            current_revision: first.number,
+            revisions: vec![first],
+            traffic: None,
+            aliases: BTreeMap::new(),
+        }
+    }
+    pub fn id(&self) -> &FunctionId {
+        &self.id
+    }
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+    pub fn create_time(&self) -> &TimeStamp {
+        &self.create_time
+    }
+    pub fn runtime(&self) -> Runtime {
+        self.runtime
+    }
+    pub fn input_type(&self) -> &InputKind {
+        &self.input_type
+    }
+    pub fn input_schema(&self) -> Option<&JsonData> {
+        self.input_schema.as_ref().map(|schema| &schema.document)
+    }
+    pub fn output_type(&self) -> Option<&OutputKind> {
+        self.output_type.as_ref()
+    }
+    pub fn output_schema(&self) -> Option<&JsonData> {
+        self.output_schema.as_ref().map(|schema| &schema.document)
+    }
+    pub fn egress(&self) -> &EgressPolicy {
+        &self.egress
+    }
+    pub fn retry(&self) -> Option<&RetryPolicy> {
+        self.retry.as_ref()
+    }
+    pub fn set_retry(&mut self, retry: Option<RetryPolicy>) {
+        self.retry = retry;
+    }
+    pub fn revisions(&self) -> &[Revision] {
+        &self.revisions
+    }
+    pub fn revision(&self, number: RevisionNumber) -> Option<&Revision> {
+        self.revisions.iter().find(|r| r.number == number)
+    }
+    /// The revision serving requests
+    pub fn current_revision(&self) -> &Revision {
+        self.revision(self.current_revision)
+            .expect("the current revision is one of the function's revisions")
+    }
+    /// Add a revision with the next number and make it current
+    pub fn add_revision(&mut self, blob_address: BlobAddress, note: String) -> &Revision {
+        let number = self
+            .revisions
+            .last()
+            .map_or(RevisionNumber(1), |last| last.number.next());
+        self.revisions.push(Revision {
+            number,
+            blob_address,
+            create_time: TimeStamp::now(),
+            note,
+        });
+        self.current_revision = number;
+        self.current_revision()
+    }
+    /// Make an existing revision current, e.g. to roll back a bad upload
+    pub fn set_current_revision(&mut self, number: RevisionNumber) -> Option<&Revision> {
+        self.revision(number)?;
+        self.current_revision = number;
+        Some(self.current_revision())
+    }
+    pub fn aliases(&self) -> &BTreeMap<String, RevisionNumber> {
+        &self.aliases
+    }
+    /// Point an alias at a revision, replacing its previous revision if it already exists.
+    ///
+    /// Aliases start with a lowercase letter so that they can't be confused with revision numbers.
+    pub fn set_alias(&mut self, alias: &str, revision: RevisionNumber) -> Result<(), String> {
+        let valid = alias.starts_with(|c: char| c.is_ascii_lowercase())
+            && alias
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+        if !valid {
+            return Err(format!(
+                "alias {alias} must start with a lowercase letter followed by lowercase letters, digits, - or _"
+            ));
+        }
+        if alias == LATEST_ALIAS {
+            return Err(format!(
+                "{LATEST_ALIAS} always points at the newest revision"
+            ));
+        }
+        if self.revision(revision).is_none() {
+            return Err(format!("revision {revision} doesn't exist"));
+        }
+        self.aliases.insert(alias.to_string(), revision);
+        Ok(())
+    }
+    pub fn remove_alias(&mut self, alias: &str) -> Option<RevisionNumber> {
+        self.aliases.remove(alias)
+    }
+    /// Resolve an alias or a revision number to one of the function's revisions
+    pub fn resolve(&self, target: &str) -> Option<RevisionNumber> {
+        if target == LATEST_ALIAS {
+            return self.revisions.last().map(|r| r.number);
+        }
+        if let Some(revision) = self.aliases.get(target) {
+            return Some(*revision);
+        }
+        let revision = RevisionNumber::parse(target).ok()?;
+        self.revision(revision).map(|r| r.number)
+    }
+    /// The split of the function's traffic between revisions, when unset every request goes to the current revision
+    pub fn traffic(&self) -> Option<&TrafficSplit> {
+        self.traffic.as_ref()
+    }
+    /// Split traffic between revisions, or stop splitting with `None`
+    pub fn set_traffic(&mut self, traffic: Option<TrafficSplit>) -> Result<(), String> {
+        if let Some(split) = &traffic {
+            self.check_traffic(split)?;
+        }
+        self.traffic = traffic;
+        Ok(())
+    }
+    /// Check the split only references the function's revisions and gives at least one of them a weight
+    pub fn check_traffic(&self, split: &TrafficSplit) -> Result<(), String> {
+        if split.revisions.iter().all(|r| r.weight == 0) {
+            return Err("a traffic split needs at least one revision with a weight".to_string());
+        }
+        let referenced = split
+            .revisions
+            .iter()
+            .map(|r| r.revision)
+            .chain(split.auto_rollback.map(|rollback| rollback.to));
+        for revision in referenced {
+            if self.revision(revision).is_none() {
+                return Err(format!("revision {revision} doesn't exist"));
+            }
+        }
+        if let Some(rollback) = split.auto_rollback {
+            if !(0.0..=1.0).contains(&rollback.max_failure_rate) {
+                return Err("max_failure_rate must be between 0 and 1".to_string());
+            }
+        }
+        Ok(())
+    }
+    /// Check an input against the function's input type and then its JSON Schema if it has one
+    pub fn validate_input(&self, input: &Value) -> Result<(), ValidationError> {
+        self.input_type.validate(input)?;
+        match &self.input_schema {
+            Some(schema) => schema.validate(input),
+            None => Ok(()),
+        }
+    }
+    /// Check an output against the function's output type and then its JSON Schema if it has one
+    pub fn validate_output(&self, output: &Value) -> Result<(), ValidationError> {
+        if let Some(output_type) = &self.output_type {
+            output_type.validate(output)?;
+        }
+        match &self.output_schema {
+            Some(schema) => schema.validate(output),
+            None => Ok(()),
+        }
+    }
+}
+
+impl FunctionSpec {
+    /// Reject specs which can't be used to validate inputs and outputs
+    pub fn check(&self) -> Result<(), String> {
+        if let Some(schema) = &self.input_schema {
+            jsonschema::validator_for(schema.value())
+                .map_err(|e| format!("invalid input schema: {e}"))?;
+        }
+        if let Some(schema) = &self.output_schema {
+            jsonschema::validator_for(schema.value())
+                .map_err(|e| format!("invalid output schema: {e}"))?;
+        }
+        Ok(())
+    }
+}
+
+/// A JSON Schema document compiled once when the function is registered or loaded, its clones sharing the compiled
+/// validator
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(from = "JsonData", into = "JsonData")]
+struct Schema {
+    document: JsonData,
+    validator: Arc<Result<jsonschema::Validator, String>>,
+}
+
+impl Schema {
+    fn validate(&self, value: &Value) -> Result<(), ValidationError> {
+        let validator = self
+            .validator
+            .as_ref()
+            .as_ref()
+            .map_err(|e| ValidationError::new(String::new(), format!("invalid schema: {e}")))?;
+        validator
+            .validate(value)
+            .map_err(|e| ValidationError::new(e.instance_path().to_string(), e.to_string()))
+    }
+}
+
+impl From<JsonData> for Schema {
+    fn from(document: JsonData) -> Self {
+        let validator = jsonschema::validator_for(document.value()).map_err(|e| e.to_string());
+        Schema {
+            document,
+            validator: Arc::new(validator),
+        }
+    }
+}
+
+impl From<Schema> for JsonData {
+    fn from(schema: Schema) -> Self {
+        schema.document
+    }
+}
+
+impl PartialEq for Schema {
+    fn eq(&self, other: &Self) -> bool {
+        self.document == other.document
+    }
+}
+
+impl std::fmt::Debug for Schema {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.document.fmt(f)
+    }
 }
 
-#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
+/// The first segment of a gateway path, under `/api`
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord, Display, Debug)]
+#[serde(try_from = "String")]
 pub struct Root(String);
 
-#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
+impl Root {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        if !is_segment(s) {
+            return Err(format!(
+                "path root {s:?} must be a single segment of letters, digits, -, _ or ."
+            ));
+        }
+        Ok(Root(s.to_string()))
+    }
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for Root {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Root::parse(&s)
+    }
+}
+
+/// The segments of a gateway path following its root, possibly none
+#[derive(
+    Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord, Default, Display, Debug,
+)]
+#[serde(try_from = "String")]
 pub struct SubPath(String);
 
-#[derive(Serialize, Deserialize)]
+impl SubPath {
+    /// Parse segments separated by `/`, ignoring a leading or trailing `/`
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let trimmed = s.trim_matches('/');
+        if !trimmed.is_empty() && !trimmed.split('/').all(is_segment) {
+            return Err(format!(
+                "sub path {s:?} must be segments of letters, digits, -, _ or . separated by /"
+            ));
+        }
+        Ok(SubPath(trimmed.to_string()))
+    }
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+    /// The rest of `path` below the sub path, `None` if the path isn't the sub path or below it
+    pub fn strip<'a>(&self, path: &'a str) -> Option<&'a str> {
+        if self.0.is_empty() {
+            return Some(path);
+        }
+        let rest = path.strip_prefix(self.0.as_str())?;
+        match rest.strip_prefix('/') {
+            Some(rest) => Some(rest),
+            None if rest.is_empty() => Some(rest),
+            None => None,
+        }
+    }
+}
+
+impl TryFrom<String> for SubPath {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        SubPath::parse(&s)
+    }
+}
+
+fn is_segment(s: &str) -> bool {
+    !s.is_empty()
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/// A gateway path routed to a function: requests to `/api/{root}/{sub_path}` and below are served by the function with
+/// the rest of their path
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
 pub struct PathEntry {
     root: Root,
+    #[serde(default)]
     sub_path: SubPath,
-    function: Function,
+    /// The name of the function serving the path
+    function: String,
+    /// Serve the revision behind this alias of the function instead of its current revision
+    #[serde(default)]
+    alias: Option<String>,
+    /// Split the path's traffic between revisions of the function instead, whatever the function's own split
+    #[serde(default)]
+    traffic: Option<TrafficSplit>,
 }
 
 impl PathEntry {
-    pub fn new(root: Root, sub_path: SubPath, function: Function) -> Self {
+    pub fn new(root: Root, sub_path: SubPath, function: String) -> Self {
         PathEntry {
             root,
             sub_path,
             function,
+            alias: None,
+            traffic: None,
         }
     }
+    pub fn with_alias(mut self, alias: String) -> Self {
+        self.alias = Some(alias);
+        self
+    }
+    pub fn with_traffic(mut self, traffic: TrafficSplit) -> Self {
+        self.traffic = Some(traffic);
+        self
+    }
     pub fn root(&self) -> &Root {
         &self.root
     }
     pub fn sub_path(&self) -> &SubPath {
         &self.sub_path
     }
-    pub fn function(&self) -> &Function {
+    /// The path under `/api`, `root` or `root/sub_path`
+    pub fn path(&self) -> String {
+        if self.sub_path.0.is_empty() {
+            return self.root.0.clone();
+        }
+        format!("{}/{}", self.root, self.sub_path)
+    }
+    pub fn function(&self) -> &str {
         &self.function
     }
+    pub fn alias(&self) -> Option<&str> {
+        self.alias.as_deref()
+    }
+    pub fn traffic(&self) -> Option<&TrafficSplit> {
+        self.traffic.as_ref()
+    }
+    /// What the gateway invokes for the entry when it has no split, `name` or `name@alias`
+    pub fn target(&self) -> String {
+        match &self.alias {
+            Some(alias) => format!("{}@{alias}", self.function),
+            None => self.function.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn input_validation() {
+        let kind = InputKind::Object(BTreeMap::from([
+            ("name".to_string(), InputKind::String),
+            (
+                "a/b".to_string(),
+                InputKind::List(Box::new(InputKind::Number)),
+            ),
+        ]));
+        assert!(kind
+            .validate(&json!({"name": "Ada", "a/b": [1, 2], "extra": true}))
+            .is_ok());
+        let error = kind
+            .validate(&json!({"name": "Ada", "a/b": [1, "2"]}))
+            .unwrap_err();
+        assert_eq!(error.path(), "/a~1b/1");
+        let error = kind.validate(&json!({"a/b": []})).unwrap_err();
+        assert_eq!(error.path(), "/name");
+        let error = kind.validate(&json!([])).unwrap_err();
+        assert_eq!(error.path(), "");
+    }
+
+    #[test]
+    fn input_kind_serialization() {
+        let kind = InputKind::List(Box::new(InputKind::Object(BTreeMap::from([(
+            "name".to_string(),
+            InputKind::String,
+        )]))));
+        let json = serde_json::to_value(&kind).unwrap();
+        assert_eq!(json, json!({"List": {"Object": {"name": "String"}}}));
+        assert_eq!(serde_json::from_value::<InputKind>(json).unwrap(), kind);
+        // saved before objects declared their fields
+        assert_eq!(
+            serde_json::from_value::<InputKind>(json!({"List": "Object"})).unwrap(),
+            InputKind::List(Box::new(InputKind::Object(BTreeMap::new())))
+        );
+        assert!(serde_json::from_value::<InputKind>(json!("Float")).is_err());
+    }
+
+    #[test]
+    fn schema_validation() {
+        let schema = json!({"type": "object", "required": ["name"]});
+        let function = Function::new(FunctionSpec {
+            name: "greet".to_string(),
+            description: String::new(),
+            runtime: Runtime::Component,
+            input_type: InputKind::Object(BTreeMap::new()),
+            input_schema: Some(JsonData::from(schema.clone())),
+            output_type: None,
+            output_schema: None,
+            blob_address: BlobAddress::from("greet.wasm".to_string()),
+            note: String::new(),
+            egress: EgressPolicy::default(),
+            retry: None,
+        });
+        // the schema is saved as it was registered and compiled again when loaded
+        let json = serde_json::to_value(&function).unwrap();
+        assert_eq!(json["input_schema"], schema);
+        let loaded: Function = serde_json::from_value(json).unwrap();
+        assert_eq!(loaded, function);
+        for function in [&function, &loaded.clone()] {
+            assert!(function.validate_input(&json!({"name": "Ada"})).is_ok());
+            assert!(function.validate_input(&json!({"age": 36})).is_err());
+        }
+    }
+
+    #[test]
+    fn path_entries() {
+        let entry: PathEntry = serde_json::from_value(
+            json!({"root": "shop", "sub_path": "/cart/items/", "function": "add", "alias": "prod"}),
+        )
+        .unwrap();
+        assert_eq!(entry.path(), "shop/cart/items");
+        assert_eq!(entry.target(), "add@prod");
+        assert_eq!(entry.sub_path().strip("cart/items/1"), Some("1"));
+        assert_eq!(entry.sub_path().strip("cart/itemsx"), None);
+        for invalid in [
+            json!({"root": "shop/cart", "function": "add"}),
+            json!({"root": "add@prod", "function": "add"}),
+            json!({"root": "shop", "sub_path": "cart//items", "function": "add"}),
+        ] {
+            assert!(serde_json::from_value::<PathEntry>(invalid).is_err());
+        }
+    }
+
+    #[test]
+    fn traffic_split() {
+        let split = TrafficSplit {
+            revisions: vec![
+                WeightedRevision {
+                    revision: RevisionNumber(1),
+                    weight: 95,
+                },
+                WeightedRevision {
+                    revision: RevisionNumber(2),
+                    weight: 5,
+                },
+            ],
+            mode: SplitMode::Random,
+            auto_rollback: None,
+        };
+        let canary = (0..1000)
+            .filter(|point| split.pick(*point) == RevisionNumber(2))
+            .count();
+        assert_eq!(canary, 50);
+        assert_eq!(split.pick(94), RevisionNumber(1));
+        assert_eq!(split.pick(95), RevisionNumber(2));
+        assert_eq!(split.pick(u64::MAX), split.pick(u64::MAX % 100));
+    }
+
+    #[test]
+    fn aliases() {
+        let mut function = Function::new(FunctionSpec {
+            name: "add".to_string(),
+            description: String::new(),
+            runtime: Runtime::Wasm,
+            input_type: InputKind::None,
+            input_schema: None,
+            output_type: None,
+            output_schema: None,
+            blob_address: BlobAddress::from("add.wasm".to_string()),
+            note: String::new(),
+            egress: EgressPolicy::default(),
+            retry: None,
+        });
+        function.add_revision(BlobAddress::from("add2.wasm".to_string()), String::new());
+        assert!(function.set_alias("prod", RevisionNumber(1)).is_ok());
+        assert!(function.set_alias("staging", RevisionNumber(3)).is_err());
+        assert!(function.set_alias("latest", RevisionNumber(1)).is_err());
+        assert!(function.set_alias("2", RevisionNumber(1)).is_err());
+        assert_eq!(function.resolve("prod"), Some(RevisionNumber(1)));
+        assert_eq!(function.resolve("latest"), Some(RevisionNumber(2)));
+        assert_eq!(function.resolve("1"), Some(RevisionNumber(1)));
+        assert_eq!(function.resolve("3"), None);
+        assert_eq!(function.resolve("staging"), None);
+    }
+
+    #[test]
+    fn egress_allowlist() {
+        let policy = EgressPolicy::new(
+            vec![
+                AllowedHost::new("api.example.com", Some(443)),
+                AllowedHost::new("*.internal", None),
+            ],
+            None,
+        );
+        assert!(policy.allows("api.example.com", 443));
+        assert!(policy.allows("API.example.com", 443));
+        assert!(!policy.allows("api.example.com", 80));
+        assert!(!policy.allows("example.com", 443));
+        assert!(policy.allows("db.internal", 5432));
+        assert!(!policy.allows("internal", 80));
+        assert!(!policy.allows("evilinternal", 80));
+        assert!(!EgressPolicy::default().allows("api.example.com", 443));
+    }
 }
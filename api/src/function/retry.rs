@@ -0,0 +1,97 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::types::ExitKind;
+
+/// The exits of a failed execution which can be retried, named after the `ExitKind`s they stand for
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RetryableExit {
+    /// A failure with any exit code
+    Failure,
+    TimeOut,
+}
+
+/// How often a failed call is attempted again, with an exponential backoff between attempts
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct RetryPolicy {
+    /// Attempts including the first one
+    pub max_attempts: u32,
+    #[serde(default = "default_initial_backoff_ms")]
+    pub initial_backoff_ms: u64,
+    #[serde(default = "default_backoff_multiplier")]
+    pub backoff_multiplier: f64,
+    #[serde(default = "default_max_backoff_ms")]
+    pub max_backoff_ms: u64,
+    /// Executions which exited otherwise aren't retried, calls which failed before an execution reported its exit are
+    #[serde(default = "default_retryable")]
+    pub retryable: Vec<RetryableExit>,
+}
+
+fn default_initial_backoff_ms() -> u64 {
+    1000
+}
+
+fn default_backoff_multiplier() -> f64 {
+    2.0
+}
+
+fn default_max_backoff_ms() -> u64 {
+    60_000
+}
+
+fn default_retryable() -> Vec<RetryableExit> {
+    vec![RetryableExit::Failure, RetryableExit::TimeOut]
+}
+
+impl Default for RetryPolicy {
+    /// A single attempt
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 1,
+            initial_backoff_ms: default_initial_backoff_ms(),
+            backoff_multiplier: default_backoff_multiplier(),
+            max_backoff_ms: default_max_backoff_ms(),
+            retryable: default_retryable(),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// The delay before the attempt following the `attempt`th one, counted from 1
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let delay =
+            self.initial_backoff_ms as f64 * self.backoff_multiplier.max(1.0).powi(exponent);
+        Duration::from_millis(delay.min(self.max_backoff_ms as f64) as u64)
+    }
+    /// Whether a failed attempt which exited so is retried, `None` standing for an attempt without a reported exit
+    pub fn retries(&self, exit: Option<&ExitKind>) -> bool {
+        match exit {
+            None => true,
+            Some(ExitKind::Success) => false,
+            Some(ExitKind::Failure { .. }) => self.retryable.contains(&RetryableExit::Failure),
+            Some(ExitKind::TimeOut) => self.retryable.contains(&RetryableExit::TimeOut),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_and_retryable_exits() {
+        let policy: RetryPolicy = serde_json::from_str(
+            r#"{"max_attempts": 5, "initial_backoff_ms": 100, "max_backoff_ms": 500, "retryable": ["TimeOut"]}"#,
+        )
+        .unwrap();
+        let backoffs = (1..5).map(|attempt| policy.backoff(attempt).as_millis());
+        assert_eq!(backoffs.collect::<Vec<_>>(), [100, 200, 400, 500]);
+        assert!(policy.retries(None));
+        assert!(policy.retries(Some(&ExitKind::TimeOut)));
+        assert!(!policy.retries(Some(&ExitKind::Failure { exit_code: 1 })));
+        assert!(!policy.retries(Some(&ExitKind::Success)));
+        assert!(RetryPolicy::default().retries(Some(&ExitKind::Failure { exit_code: 1 })));
+    }
+}
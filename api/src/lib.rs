@@ -1,3 +1,6 @@
 pub mod function;
+pub mod pipeline;
+pub mod trigger;
 pub mod types;
 pub mod worker;
+pub mod workflow;
@@ -0,0 +1,250 @@
+use derive_more::derive::Display;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    function::execution::{ExecutionId, ExecutionResult, Input, Output},
+    types::{Id, JsonData, TimeStamp},
+};
+
+/// Placeholder for the previous step's output in the input of a step
+pub const OUTPUT_PLACEHOLDER: &str = "$output";
+
+/// A function called by a pipeline with the output of the previous step, or the pipeline's input for the first step
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct PipelineStep {
+    /// `name`, `name@alias` or `name@revision`
+    pub function: String,
+    /// The step's input with `"$output"` standing for the previous output wherever it appears, e.g. `["$output", 3]`,
+    /// the previous output being the input as is when unset
+    #[serde(default)]
+    pub input: Option<JsonData>,
+}
+
+impl PipelineStep {
+    pub fn input(&self, previous: &Value) -> Value {
+        match &self.input {
+            Some(template) => substitute(template.value(), previous),
+            None => previous.clone(),
+        }
+    }
+}
+
+/// Replace every `"$output"` string of the template by the output
+pub fn substitute(template: &Value, output: &Value) -> Value {
+    match template {
+        Value::String(s) if s == OUTPUT_PLACEHOLDER => output.clone(),
+        Value::Array(values) => {
+            Value::Array(values.iter().map(|v| substitute(v, output)).collect())
+        }
+        Value::Object(fields) => Value::Object(
+            fields
+                .iter()
+                .map(|(k, v)| (k.clone(), substitute(v, output)))
+                .collect(),
+        ),
+        value => value.clone(),
+    }
+}
+
+/// What a client submits to register a pipeline
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PipelineSpec {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub steps: Vec<PipelineStep>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Display, Debug)]
+pub struct PipelineId(Id);
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Pipeline {
+    id: PipelineId,
+    name: String,
+    description: String,
+    steps: Vec<PipelineStep>,
+    create_time: TimeStamp,
+}
+
+impl Pipeline {
+    pub fn new(spec: PipelineSpec) -> Self {
+        Pipeline {
+            id: PipelineId(Id::new()),
+            name: spec.name,
+            description: spec.description,
+            steps: spec.steps,
+            create_time: TimeStamp::now(),
+        }
+    }
+    pub fn id(&self) -> &PipelineId {
+        &self.id
+    }
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+    pub fn steps(&self) -> &[PipelineStep] {
+        &self.steps
+    }
+    pub fn create_time(&self) -> &TimeStamp {
+        &self.create_time
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Display, Debug)]
+pub struct PipelineExecutionId(Id);
+
+impl PipelineExecutionId {
+    pub fn id(&self) -> &Id {
+        &self.0
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum PipelineStatus {
+    #[default]
+    Running,
+    Succeeded,
+    /// A step failed and the following ones weren't run
+    Failed,
+}
+
+/// The execution of a pipeline's step
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct StepExecution {
+    function: String,
+    /// Unset when the gateway turned the step away before creating an execution
+    execution: Option<ExecutionId>,
+    /// Unset when the worker didn't report the execution's result
+    result: Option<ExecutionResult>,
+    /// Why the step failed
+    error: Option<String>,
+}
+
+impl StepExecution {
+    pub fn new(
+        function: String,
+        execution: Option<ExecutionId>,
+        result: Option<ExecutionResult>,
+        error: Option<String>,
+    ) -> Self {
+        StepExecution {
+            function,
+            execution,
+            result,
+            error,
+        }
+    }
+    pub fn function(&self) -> &str {
+        &self.function
+    }
+    pub fn execution(&self) -> Option<&ExecutionId> {
+        self.execution.as_ref()
+    }
+    pub fn result(&self) -> Option<&ExecutionResult> {
+        self.result.as_ref()
+    }
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+}
+
+/// A run of a pipeline, its steps being recorded as they complete
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PipelineExecution {
+    id: PipelineExecutionId,
+    pipeline: String,
+    create_time: TimeStamp,
+    complete_time: Option<TimeStamp>,
+    status: PipelineStatus,
+    input: Input,
+    /// The output of the last step once every step has succeeded
+    output: Option<Output>,
+    steps: Vec<StepExecution>,
+    error: Option<String>,
+}
+
+impl PipelineExecution {
+    pub fn new(pipeline: &str, input: Input) -> Self {
+        PipelineExecution {
+            id: PipelineExecutionId(Id::new()),
+            pipeline: pipeline.to_string(),
+            create_time: TimeStamp::now(),
+            complete_time: None,
+            status: PipelineStatus::Running,
+            input,
+            output: None,
+            steps: vec![],
+            error: None,
+        }
+    }
+    pub fn id(&self) -> &PipelineExecutionId {
+        &self.id
+    }
+    pub fn pipeline(&self) -> &str {
+        &self.pipeline
+    }
+    pub fn create_time(&self) -> &TimeStamp {
+        &self.create_time
+    }
+    pub fn complete_time(&self) -> Option<&TimeStamp> {
+        self.complete_time.as_ref()
+    }
+    pub fn status(&self) -> PipelineStatus {
+        self.status
+    }
+    pub fn input(&self) -> &Input {
+        &self.input
+    }
+    pub fn output(&self) -> Option<&Output> {
+        self.output.as_ref()
+    }
+    pub fn steps(&self) -> &[StepExecution] {
+        &self.steps
+    }
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+    pub fn push_step(&mut self, step: StepExecution) {
+        self.steps.push(step);
+    }
+    pub fn succeed(&mut self, output: Output) {
+        self.output = Some(output);
+        self.status = PipelineStatus::Succeeded;
+        self.complete_time = Some(TimeStamp::now());
+    }
+    pub fn fail(&mut self, error: String) {
+        self.error = Some(error);
+        self.status = PipelineStatus::Failed;
+        self.complete_time = Some(TimeStamp::now());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn substitutes_the_previous_output() {
+        let step = |input: Option<Value>| PipelineStep {
+            function: "mul".to_string(),
+            input: input.map(JsonData::from),
+        };
+        assert_eq!(step(None).input(&json!(3)), json!(3));
+        assert_eq!(
+            step(Some(json!(["$output", 2]))).input(&json!(3)),
+            json!([3, 2])
+        );
+        assert_eq!(
+            step(Some(json!({"sum": "$output", "name": "$other"}))).input(&json!([1])),
+            json!({"sum": [1], "name": "$other"})
+        );
+    }
+}
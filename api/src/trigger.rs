@@ -0,0 +1,328 @@
+use derive_more::derive::Display;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    function::execution::ExecutionId,
+    types::{Id, JsonData, TimeStamp},
+};
+
+/// What a run of a cron trigger does while the previous run of the same trigger is still executing
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum OverlapPolicy {
+    /// The run is dropped
+    #[default]
+    Skip,
+    /// The run starts once the previous ones have completed
+    Queue,
+    /// The run starts right away
+    Allow,
+}
+
+/// What a client submits to invoke a function on a schedule
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CronSpec {
+    pub name: String,
+    /// `name`, `name@alias` or `name@revision` of the function to invoke
+    pub function: String,
+    /// Cron expression with an optional leading seconds field, e.g. `0 9 * * MON-FRI` or `0 9 * * 1-5`, days of the
+    /// week being numbered from 0 for Sunday (7 is Sunday too)
+    pub schedule: String,
+    /// IANA name of the timezone the schedule is evaluated in
+    #[serde(default = "default_timezone")]
+    pub timezone: String,
+    /// The function's input on every run, `null` when unset
+    #[serde(default)]
+    pub input: Option<JsonData>,
+    #[serde(default)]
+    pub overlap: OverlapPolicy,
+    /// How many of the runs missed while the control plane was down are started when it comes back, the most recent
+    /// ones first
+    #[serde(default = "default_catch_up")]
+    pub catch_up: u32,
+}
+
+fn default_timezone() -> String {
+    "UTC".to_string()
+}
+
+fn default_catch_up() -> u32 {
+    1
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CronTrigger {
+    name: String,
+    function: String,
+    schedule: String,
+    timezone: String,
+    input: Option<JsonData>,
+    overlap: OverlapPolicy,
+    catch_up: u32,
+    create_time: TimeStamp,
+    /// The scheduled time of the latest run, runs scheduled after it are still due
+    #[serde(default)]
+    last_scheduled: Option<TimeStamp>,
+    #[serde(default)]
+    next_run: Option<TimeStamp>,
+}
+
+impl CronTrigger {
+    pub fn new(spec: CronSpec, next_run: Option<TimeStamp>) -> Self {
+        CronTrigger {
+            name: spec.name,
+            function: spec.function,
+            schedule: spec.schedule,
+            timezone: spec.timezone,
+            input: spec.input,
+            overlap: spec.overlap,
+            catch_up: spec.catch_up,
+            create_time: TimeStamp::now(),
+            last_scheduled: None,
+            next_run,
+        }
+    }
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn function(&self) -> &str {
+        &self.function
+    }
+    pub fn schedule(&self) -> &str {
+        &self.schedule
+    }
+    pub fn timezone(&self) -> &str {
+        &self.timezone
+    }
+    pub fn input(&self) -> Option<&JsonData> {
+        self.input.as_ref()
+    }
+    pub fn overlap(&self) -> OverlapPolicy {
+        self.overlap
+    }
+    pub fn catch_up(&self) -> u32 {
+        self.catch_up
+    }
+    pub fn create_time(&self) -> &TimeStamp {
+        &self.create_time
+    }
+    pub fn last_scheduled(&self) -> Option<&TimeStamp> {
+        self.last_scheduled.as_ref()
+    }
+    pub fn next_run(&self) -> Option<&TimeStamp> {
+        self.next_run.as_ref()
+    }
+    /// Record that the runs up to `last_scheduled` are no longer due
+    pub fn advance(&mut self, last_scheduled: TimeStamp, next_run: Option<TimeStamp>) {
+        self.last_scheduled = Some(last_scheduled);
+        self.next_run = next_run;
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum CronRunStatus {
+    /// Waiting for the previous run under the `queue` overlap policy
+    Queued,
+    Running,
+    Succeeded,
+    /// The function failed and its retries gave up
+    Failed,
+    /// Dropped by the overlap policy
+    Skipped,
+}
+
+/// A run of a cron trigger, recorded as it falls due
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CronRun {
+    scheduled_time: TimeStamp,
+    status: CronRunStatus,
+    start_time: Option<TimeStamp>,
+    complete_time: Option<TimeStamp>,
+    /// The executions of the run's attempts, attempts the gateway turned away having none
+    executions: Vec<ExecutionId>,
+}
+
+impl CronRun {
+    pub fn new(scheduled_time: TimeStamp, status: CronRunStatus) -> Self {
+        CronRun {
+            scheduled_time,
+            status,
+            start_time: None,
+            complete_time: None,
+            executions: Vec::new(),
+        }
+    }
+    pub fn scheduled_time(&self) -> &TimeStamp {
+        &self.scheduled_time
+    }
+    pub fn status(&self) -> CronRunStatus {
+        self.status
+    }
+    pub fn start_time(&self) -> Option<&TimeStamp> {
+        self.start_time.as_ref()
+    }
+    pub fn complete_time(&self) -> Option<&TimeStamp> {
+        self.complete_time.as_ref()
+    }
+    pub fn executions(&self) -> &[ExecutionId] {
+        &self.executions
+    }
+    /// Whether the run was started or waiting to be when the control plane stopped
+    pub fn is_pending(&self) -> bool {
+        matches!(self.status, CronRunStatus::Queued | CronRunStatus::Running)
+    }
+    pub fn start(&mut self) {
+        self.status = CronRunStatus::Running;
+        self.start_time = Some(TimeStamp::now());
+    }
+    pub fn complete(&mut self, succeeded: bool, executions: Vec<ExecutionId>) {
+        self.status = if succeeded {
+            CronRunStatus::Succeeded
+        } else {
+            CronRunStatus::Failed
+        };
+        self.complete_time = Some(TimeStamp::now());
+        self.executions = executions;
+    }
+}
+
+/// The hash function of a webhook's HMAC signature
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum HmacAlgorithm {
+    Sha1,
+    #[default]
+    Sha256,
+    Sha512,
+}
+
+impl HmacAlgorithm {
+    /// The name prefixing GitHub style signatures, e.g. `sha256=<hex>`
+    pub fn name(&self) -> &'static str {
+        match self {
+            HmacAlgorithm::Sha1 => "sha1",
+            HmacAlgorithm::Sha256 => "sha256",
+            HmacAlgorithm::Sha512 => "sha512",
+        }
+    }
+}
+
+/// How the sender lays out the signature in its header
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum SignatureFormat {
+    /// The hex HMAC of the body, optionally prefixed by the name of the webhook's algorithm as in `sha256=<hex>`
+    #[default]
+    Github,
+    /// `t=<unix time>,v1=<hex>` where the HMAC covers `<unix time>.<body>`, deliveries older than `tolerance_secs`
+    /// being rejected to prevent replays
+    Stripe {
+        #[serde(default = "default_tolerance_secs")]
+        tolerance_secs: u64,
+    },
+}
+
+fn default_tolerance_secs() -> u64 {
+    300
+}
+
+/// The HMAC signature a webhook's sender adds to its deliveries with a shared secret
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct WebhookSignature {
+    pub secret: String,
+    /// e.g. `x-hub-signature-256` or `stripe-signature`
+    pub header: String,
+    #[serde(default)]
+    pub algorithm: HmacAlgorithm,
+    #[serde(default)]
+    pub format: SignatureFormat,
+}
+
+/// Where the unique id of a delivery is read from, redeliveries of an id being acknowledged without invoking the
+/// function again
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryId {
+    /// A header such as `x-github-delivery`
+    Header(String),
+    /// A JSON pointer into the body such as `/id`
+    Field(String),
+}
+
+/// What a client submits to invoke a function from a third party's webhook
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct WebhookSpec {
+    pub name: String,
+    /// `name`, `name@alias` or `name@revision` of the function to invoke
+    pub function: String,
+    /// Deliveries are accepted without verification when unset
+    #[serde(default)]
+    pub signature: Option<WebhookSignature>,
+    /// Deliveries aren't deduplicated when unset
+    #[serde(default)]
+    pub delivery_id: Option<DeliveryId>,
+}
+
+/// Identifies a webhook in the URL it receives deliveries on, which is hard to guess
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Display, Debug)]
+pub struct WebhookId(Id);
+
+impl WebhookId {
+    pub fn id(&self) -> &Id {
+        &self.0
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Webhook {
+    id: WebhookId,
+    name: String,
+    function: String,
+    signature: Option<WebhookSignature>,
+    delivery_id: Option<DeliveryId>,
+    create_time: TimeStamp,
+}
+
+impl Webhook {
+    pub fn new(spec: WebhookSpec) -> Self {
+        Webhook {
+            id: WebhookId(Id::new()),
+            name: spec.name,
+            function: spec.function,
+            signature: spec.signature,
+            delivery_id: spec.delivery_id,
+            create_time: TimeStamp::now(),
+        }
+    }
+    pub fn id(&self) -> &WebhookId {
+        &self.id
+    }
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn function(&self) -> &str {
+        &self.function
+    }
+    pub fn signature(&self) -> Option<&WebhookSignature> {
+        self.signature.as_ref()
+    }
+    pub fn delivery_id(&self) -> Option<&DeliveryId> {
+        self.delivery_id.as_ref()
+    }
+    pub fn create_time(&self) -> &TimeStamp {
+        &self.create_time
+    }
+    /// The webhook without its secret, as returned by the API
+    pub fn redacted(&self) -> Self {
+        self.with_secret(String::new())
+    }
+    /// The webhook with its secret replaced, e.g. by its encrypted form when saved
+    pub fn with_secret(&self, secret: String) -> Self {
+        let mut webhook = self.clone();
+        if let Some(signature) = &mut webhook.signature {
+            signature.secret = secret;
+        }
+        webhook
+    }
+}
@@ -6,6 +6,12 @@ use uuid::Uuid;
 #[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Display, Debug)]
 pub struct Id(#[serde(serialize_with = "uuid::serde::simple::serialize")] Uuid);
 
+impl Default for Id {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Id {
     pub fn new() -> Self {
         Id(Uuid::new_v4())
@@ -18,6 +24,18 @@ impl Id {
 #[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Display, Debug)]
 pub struct JsonData(Value);
 
+impl JsonData {
+    pub fn value(&self) -> &Value {
+        &self.0
+    }
+}
+
+impl From<Value> for JsonData {
+    fn from(value: Value) -> Self {
+        JsonData(value)
+    }
+}
+
 impl TryFrom<Vec<u8>> for JsonData {
     type Error = serde_json::Error;
     fn try_from(value: Vec<u8>) -> Result<Self, Self::Error> {
@@ -32,9 +50,31 @@ pub enum ExitKind {
     TimeOut,
 }
 
-#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord, Display, Debug)]
 pub struct BlobAddress(String);
 
+const SHA256_PREFIX: &str = "sha256:";
+
+impl BlobAddress {
+    /// Address of a blob uploaded to the control plane, stored under the hex sha256 digest of its content
+    pub fn sha256(digest: &str) -> Self {
+        BlobAddress(format!("{SHA256_PREFIX}{digest}"))
+    }
+    /// The digest of a blob uploaded to the control plane, other addresses are paths local to the worker
+    pub fn digest(&self) -> Option<&str> {
+        self.0.strip_prefix(SHA256_PREFIX)
+    }
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for BlobAddress {
+    fn from(s: String) -> Self {
+        BlobAddress(s)
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord, Display, Debug)]
 pub struct TimeStamp(chrono::DateTime<chrono::Utc>);
 
@@ -42,4 +82,17 @@ impl TimeStamp {
     pub fn now() -> Self {
         TimeStamp(chrono::Utc::now())
     }
+    /// Time elapsed since this timestamp, zero if it is in the future
+    pub fn elapsed(&self) -> std::time::Duration {
+        (chrono::Utc::now() - self.0).to_std().unwrap_or_default()
+    }
+    pub fn as_datetime(&self) -> &chrono::DateTime<chrono::Utc> {
+        &self.0
+    }
+}
+
+impl From<chrono::DateTime<chrono::Utc>> for TimeStamp {
+    fn from(time: chrono::DateTime<chrono::Utc>) -> Self {
+        TimeStamp(time)
+    }
 }
@@ -1,14 +1,25 @@
+use std::collections::BTreeMap;
+
 use derive_more::derive::Display;
 use serde::{Deserialize, Serialize};
 
-use crate::types::{Id, TimeStamp};
+use crate::{
+    function::registration::{RevisionNumber, Runtime},
+    types::{Id, TimeStamp},
+};
+
+/// Header set by a worker turning an execution away because it is at capacity, the gateway tries another worker
+pub const WORKER_FULL_HEADER: &str = "x-wasi-faas-worker-full";
 
 #[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
 pub enum WorkerStatus {
     #[default]
     Available,
+    /// Running as many executions as it can, no new ones are sent to it until it has capacity again
     Occupied,
+    /// Finishing its in-flight executions before shutting down, no new ones are sent to it
+    Draining,
     Disabled,
     Unknown,
 }
@@ -38,6 +49,9 @@ pub struct Worker {
     status: WorkerStatus,
     create_time: TimeStamp,
     last_heartbeat: TimeStamp,
+    /// What the worker reported with its last heartbeat, workers which haven't reported yet have none
+    #[serde(default)]
+    report: Option<WorkerReport>,
 }
 
 impl Worker {
@@ -48,6 +62,7 @@ impl Worker {
             status: WorkerStatus::Available,
             create_time: TimeStamp::now(),
             last_heartbeat: TimeStamp::now(),
+            report: None,
         }
     }
     pub fn id(&self) -> &WorkerId {
@@ -68,4 +83,128 @@ impl Worker {
     pub fn address(&self) -> &WorkerAddress {
         &self.address
     }
+    pub fn last_heartbeat(&self) -> &TimeStamp {
+        &self.last_heartbeat
+    }
+    pub fn report(&self) -> Option<&WorkerReport> {
+        self.report.as_ref()
+    }
+    /// Record a heartbeat carrying the worker's report, an available worker becomes occupied when it is full and
+    /// the other way around
+    pub fn update_report(&mut self, report: WorkerReport) {
+        match self.status {
+            WorkerStatus::Available | WorkerStatus::Occupied => {
+                self.status = if report.is_full() {
+                    WorkerStatus::Occupied
+                } else {
+                    WorkerStatus::Available
+                };
+            }
+            _ => {}
+        }
+        self.report = Some(report);
+        self.touch();
+    }
+}
+
+/// The state and capabilities of a worker, sent with every heartbeat
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct WorkerReport {
+    /// Revisions loaded by the worker, by function name
+    functions: BTreeMap<String, Vec<RevisionNumber>>,
+    in_flight: u32,
+    /// How many executions the worker runs at once, unbounded if not set
+    capacity: Option<u32>,
+    /// Resident memory of the worker process, where the platform reports it
+    memory_bytes: Option<u64>,
+    wasmtime_version: String,
+    runtimes: Vec<Runtime>,
+}
+
+impl WorkerReport {
+    pub fn new(
+        functions: BTreeMap<String, Vec<RevisionNumber>>,
+        in_flight: u32,
+        capacity: Option<u32>,
+        memory_bytes: Option<u64>,
+        wasmtime_version: String,
+        runtimes: Vec<Runtime>,
+    ) -> Self {
+        WorkerReport {
+            functions,
+            in_flight,
+            capacity,
+            memory_bytes,
+            wasmtime_version,
+            runtimes,
+        }
+    }
+    pub fn functions(&self) -> &BTreeMap<String, Vec<RevisionNumber>> {
+        &self.functions
+    }
+    pub fn has_loaded(&self, function: &str, revision: RevisionNumber) -> bool {
+        self.functions
+            .get(function)
+            .is_some_and(|revisions| revisions.contains(&revision))
+    }
+    pub fn in_flight(&self) -> u32 {
+        self.in_flight
+    }
+    pub fn capacity(&self) -> Option<u32> {
+        self.capacity
+    }
+    pub fn memory_bytes(&self) -> Option<u64> {
+        self.memory_bytes
+    }
+    pub fn wasmtime_version(&self) -> &str {
+        &self.wasmtime_version
+    }
+    pub fn runtimes(&self) -> &[Runtime] {
+        &self.runtimes
+    }
+    pub fn is_full(&self) -> bool {
+        self.capacity
+            .is_some_and(|capacity| self.in_flight >= capacity)
+    }
+    /// Share of the capacity in use, unbounded workers are loaded by their in-flight count
+    pub fn load(&self) -> f64 {
+        match self.capacity {
+            Some(capacity) if capacity > 0 => f64::from(self.in_flight) / f64::from(capacity),
+            _ => f64::from(self.in_flight),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(in_flight: u32, capacity: Option<u32>) -> WorkerReport {
+        WorkerReport::new(
+            BTreeMap::new(),
+            in_flight,
+            capacity,
+            None,
+            "24.0.0".to_string(),
+            vec![],
+        )
+    }
+
+    #[test]
+    fn full_workers_are_occupied() {
+        assert!(!report(1, Some(2)).is_full());
+        assert!(report(2, Some(2)).is_full());
+        assert!(!report(100, None).is_full());
+
+        let mut worker = Worker::new("127.0.0.1:3001".to_string().into());
+        worker.update_report(report(2, Some(2)));
+        assert_eq!(worker.status(), &WorkerStatus::Occupied);
+        worker.update_report(report(1, Some(2)));
+        assert_eq!(worker.status(), &WorkerStatus::Available);
+        // a draining worker stays draining whatever its load
+        worker.update_status(WorkerStatus::Draining);
+        worker.update_report(report(0, Some(2)));
+        assert_eq!(worker.status(), &WorkerStatus::Draining);
+        assert_eq!(worker.report(), Some(&report(0, Some(2))));
+    }
 }
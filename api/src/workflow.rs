@@ -0,0 +1,314 @@
+use std::collections::BTreeMap;
+
+use derive_more::derive::Display;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    function::{
+        execution::{Input, Output},
+        retry::RetryPolicy,
+    },
+    pipeline::StepExecution,
+    types::{Id, JsonData, TimeStamp},
+};
+
+/// What a condition checks the value at its path for
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Test {
+    Equals(JsonData),
+    NotEquals(JsonData),
+    GreaterThan(f64),
+    LessThan(f64),
+}
+
+/// A check on a step's input, e.g. `{"path": "/status", "equals": "ok"}`
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Condition {
+    /// JSON pointer to the value checked, the whole input when empty
+    #[serde(default)]
+    pub path: String,
+    #[serde(flatten)]
+    pub test: Test,
+}
+
+impl Condition {
+    /// Whether the value at the path passes the test, a missing value never does
+    pub fn holds(&self, input: &Value) -> bool {
+        let Some(value) = input.pointer(&self.path) else {
+            return false;
+        };
+        match &self.test {
+            Test::Equals(expected) => value == expected.value(),
+            Test::NotEquals(expected) => value != expected.value(),
+            Test::GreaterThan(bound) => value.as_f64().is_some_and(|v| v > *bound),
+            Test::LessThan(bound) => value.as_f64().is_some_and(|v| v < *bound),
+        }
+    }
+}
+
+/// The steps run when the condition holds
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Choice {
+    pub condition: Condition,
+    pub steps: Vec<WorkflowStep>,
+}
+
+/// A step of a workflow, its output being the input of the next step
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum WorkflowStep {
+    /// Call a function, as a pipeline step does, attempting it again as the retry policy allows when it fails
+    Call {
+        /// `name`, `name@alias` or `name@revision`
+        function: String,
+        /// The function's input with `"$output"` standing for the step's input, which is passed as is when unset
+        #[serde(default)]
+        input: Option<JsonData>,
+        #[serde(default)]
+        retry: Option<RetryPolicy>,
+    },
+    /// Run every branch with the step's input at the same time, the output being the list of their outputs
+    Parallel { branches: Vec<Vec<WorkflowStep>> },
+    /// Run the steps for every element of the list input at the same time, at most `max_concurrency` at once (16 by
+    /// default), the output being the list of their outputs
+    Map {
+        steps: Vec<WorkflowStep>,
+        #[serde(default)]
+        max_concurrency: Option<usize>,
+    },
+    /// Run the steps of the first choice whose condition holds for the input, or the `default` steps which pass the
+    /// input on as is when empty
+    Choice {
+        choices: Vec<Choice>,
+        #[serde(default)]
+        default: Vec<WorkflowStep>,
+    },
+}
+
+impl WorkflowStep {
+    /// The functions called by the step and the steps nested in it
+    pub fn functions(&self) -> Vec<&str> {
+        match self {
+            WorkflowStep::Call { function, .. } => vec![function.as_str()],
+            WorkflowStep::Parallel { branches } => branches
+                .iter()
+                .flatten()
+                .flat_map(WorkflowStep::functions)
+                .collect(),
+            WorkflowStep::Map { steps, .. } => {
+                steps.iter().flat_map(WorkflowStep::functions).collect()
+            }
+            WorkflowStep::Choice { choices, default } => choices
+                .iter()
+                .flat_map(|choice| &choice.steps)
+                .chain(default)
+                .flat_map(WorkflowStep::functions)
+                .collect(),
+        }
+    }
+}
+
+/// What a client submits to register a workflow
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct WorkflowSpec {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub steps: Vec<WorkflowStep>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Display, Debug)]
+pub struct WorkflowId(Id);
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Workflow {
+    id: WorkflowId,
+    name: String,
+    description: String,
+    steps: Vec<WorkflowStep>,
+    create_time: TimeStamp,
+}
+
+impl Workflow {
+    pub fn new(spec: WorkflowSpec) -> Self {
+        Workflow {
+            id: WorkflowId(Id::new()),
+            name: spec.name,
+            description: spec.description,
+            steps: spec.steps,
+            create_time: TimeStamp::now(),
+        }
+    }
+    pub fn id(&self) -> &WorkflowId {
+        &self.id
+    }
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+    pub fn steps(&self) -> &[WorkflowStep] {
+        &self.steps
+    }
+    pub fn create_time(&self) -> &TimeStamp {
+        &self.create_time
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Display, Debug)]
+pub struct WorkflowExecutionId(Id);
+
+impl WorkflowExecutionId {
+    pub fn id(&self) -> &Id {
+        &self.0
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum WorkflowStatus {
+    #[default]
+    Running,
+    Succeeded,
+    Failed,
+}
+
+/// The attempts of a call step, the output being set once one of them succeeded
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+pub struct StepCall {
+    attempts: Vec<StepExecution>,
+    output: Option<Output>,
+}
+
+impl StepCall {
+    pub fn attempts(&self) -> &[StepExecution] {
+        &self.attempts
+    }
+    pub fn output(&self) -> Option<&Output> {
+        self.output.as_ref()
+    }
+}
+
+/// A run of a workflow, recording its calls as they complete so that it can be resumed without calling them again
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct WorkflowExecution {
+    id: WorkflowExecutionId,
+    workflow: String,
+    create_time: TimeStamp,
+    complete_time: Option<TimeStamp>,
+    status: WorkflowStatus,
+    input: Input,
+    output: Option<Output>,
+    error: Option<String>,
+    /// The workflow's steps when the execution started
+    steps: Vec<WorkflowStep>,
+    /// The calls by the path of their step, e.g. `/1/item/3/0` for the first step mapped over the fourth element of
+    /// the second step's input
+    calls: BTreeMap<String, StepCall>,
+}
+
+impl WorkflowExecution {
+    pub fn new(workflow: &Workflow, input: Input) -> Self {
+        WorkflowExecution {
+            id: WorkflowExecutionId(Id::new()),
+            workflow: workflow.name().to_string(),
+            create_time: TimeStamp::now(),
+            complete_time: None,
+            status: WorkflowStatus::Running,
+            input,
+            output: None,
+            error: None,
+            steps: workflow.steps().to_vec(),
+            calls: BTreeMap::new(),
+        }
+    }
+    pub fn id(&self) -> &WorkflowExecutionId {
+        &self.id
+    }
+    pub fn workflow(&self) -> &str {
+        &self.workflow
+    }
+    pub fn create_time(&self) -> &TimeStamp {
+        &self.create_time
+    }
+    pub fn complete_time(&self) -> Option<&TimeStamp> {
+        self.complete_time.as_ref()
+    }
+    pub fn status(&self) -> WorkflowStatus {
+        self.status
+    }
+    pub fn input(&self) -> &Input {
+        &self.input
+    }
+    pub fn output(&self) -> Option<&Output> {
+        self.output.as_ref()
+    }
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+    pub fn steps(&self) -> &[WorkflowStep] {
+        &self.steps
+    }
+    pub fn calls(&self) -> &BTreeMap<String, StepCall> {
+        &self.calls
+    }
+    /// Record an attempt of the call at the path, with its output when it succeeded
+    pub fn record_attempt(&mut self, path: &str, attempt: StepExecution, output: Option<Output>) {
+        let call = self.calls.entry(path.to_string()).or_default();
+        call.attempts.push(attempt);
+        call.output = output;
+    }
+    pub fn succeed(&mut self, output: Output) {
+        self.output = Some(output);
+        self.status = WorkflowStatus::Succeeded;
+        self.complete_time = Some(TimeStamp::now());
+    }
+    pub fn fail(&mut self, error: String) {
+        self.error = Some(error);
+        self.status = WorkflowStatus::Failed;
+        self.complete_time = Some(TimeStamp::now());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn steps() {
+        let steps: Vec<WorkflowStep> = serde_json::from_value(json!([
+            { "parallel": { "branches": [[{ "call": { "function": "add" } }], []] } },
+            { "map": { "steps": [{ "call": { "function": "mul", "input": ["$output", 2] } }] } },
+            { "choice": {
+                "choices": [{ "condition": { "path": "/0", "greater_than": 10 }, "steps": [
+                    { "call": { "function": "sub", "retry": { "max_attempts": 3 } } }
+                ] }],
+                "default": [{ "call": { "function": "div" } }]
+            } }
+        ]))
+        .unwrap();
+        let functions = steps
+            .iter()
+            .flat_map(WorkflowStep::functions)
+            .collect::<Vec<_>>();
+        assert_eq!(functions, ["add", "mul", "sub", "div"]);
+
+        let WorkflowStep::Choice { choices, .. } = &steps[2] else {
+            panic!("expected a choice");
+        };
+        assert!(choices[0].condition.holds(&json!([11, 2])));
+        assert!(!choices[0].condition.holds(&json!([10, 2])));
+        assert!(!choices[0].condition.holds(&json!([])));
+        let equals = Condition {
+            path: "/status".to_string(),
+            test: Test::Equals(json!("ok").into()),
+        };
+        assert!(equals.holds(&json!({ "status": "ok" })));
+        assert!(!equals.holds(&json!({ "status": "failed" })));
+    }
+}
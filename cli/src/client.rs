@@ -0,0 +1,127 @@
+use api::{
+    function::{
+        execution::{Execution, ExecutionId, EXECUTION_ID_HEADER},
+        registration::{Function, FunctionId, FunctionSpec, PathEntry, Revision},
+    },
+    types::BlobAddress,
+    worker::{Worker, WorkerId},
+};
+use reqwest::{RequestBuilder, StatusCode};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+/// Client for the control-plane REST API
+pub struct Client {
+    http: reqwest::Client,
+    control_plane_address: String,
+}
+
+/// The response of the gateway to an invocation
+pub struct Invocation {
+    pub status: StatusCode,
+    pub execution: Option<ExecutionId>,
+    pub body: String,
+}
+
+impl Client {
+    pub fn new(control_plane_address: String) -> Self {
+        Client {
+            http: reqwest::Client::new(),
+            control_plane_address: control_plane_address.trim_end_matches('/').to_string(),
+        }
+    }
+
+    pub async fn upload(&self, wasm: Vec<u8>) -> anyhow::Result<BlobAddress> {
+        self.send(self.http.post(self.url("/blobs")).body(wasm))
+            .await
+    }
+    pub async fn register(&self, spec: &FunctionSpec) -> anyhow::Result<Function> {
+        self.send(self.http.post(self.url("/functions")).json(spec))
+            .await
+    }
+    /// Route a gateway path to a function
+    pub async fn register_path(&self, entry: &PathEntry) -> anyhow::Result<PathEntry> {
+        self.send(self.http.post(self.url("/paths")).json(entry))
+            .await
+    }
+    /// Upload the wasm as a new revision of the function, which becomes its current revision
+    pub async fn deploy(&self, name: &str, wasm: Vec<u8>, note: &str) -> anyhow::Result<Revision> {
+        let request = self
+            .http
+            .post(self.url(&format!("/functions/{name}/revisions")))
+            .query(&[("note", note)])
+            .body(wasm);
+        self.send(request).await
+    }
+    pub async fn functions(&self) -> anyhow::Result<Vec<Function>> {
+        self.send(self.http.get(self.url("/functions"))).await
+    }
+    pub async fn function(&self, name: &str) -> anyhow::Result<Function> {
+        self.send(self.http.get(self.url(&format!("/functions/{name}"))))
+            .await
+    }
+    pub async fn workers(&self) -> anyhow::Result<Vec<Worker>> {
+        self.send(self.http.get(self.url("/workers"))).await
+    }
+    pub async fn worker(&self, id: &WorkerId) -> anyhow::Result<Option<Worker>> {
+        self.send(self.http.get(self.url(&format!("/workers/{id}"))))
+            .await
+    }
+    /// The executions of the function, or of every function, only those whose result the control plane stored after
+    /// the one with sequence number `after` if set
+    pub async fn executions(
+        &self,
+        function: Option<&FunctionId>,
+        after: Option<u64>,
+    ) -> anyhow::Result<Vec<Execution>> {
+        let mut request = self.http.get(self.url("/executions"));
+        if let Some(function) = function {
+            request = request.query(&[("function", function.to_string())]);
+        }
+        if let Some(after) = after {
+            request = request.query(&[("after", after)]);
+        }
+        self.send(request).await
+    }
+    pub async fn execution(&self, id: &ExecutionId) -> anyhow::Result<Execution> {
+        self.send(self.http.get(self.url(&format!("/executions/{id}"))))
+            .await
+    }
+    /// Invoke the function through the gateway, the response is returned whatever its status
+    pub async fn invoke(
+        &self,
+        function: &str,
+        input: Option<&Value>,
+    ) -> anyhow::Result<Invocation> {
+        let mut request = self.http.post(self.url(&format!("/api/{function}")));
+        if let Some(input) = input {
+            request = request.json(input);
+        }
+        let response = request.send().await?;
+        let execution = response
+            .headers()
+            .get(EXECUTION_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| ExecutionId::parse(value).ok());
+        Ok(Invocation {
+            status: response.status(),
+            execution,
+            body: response.text().await?,
+        })
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{path}", self.control_plane_address)
+    }
+
+    /// Send the request and decode its JSON response, failing with the response body on an error status
+    async fn send<T: DeserializeOwned>(&self, request: RequestBuilder) -> anyhow::Result<T> {
+        let response = request.send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            anyhow::bail!("control plane responded with {status}: {body}");
+        }
+        Ok(response.json().await?)
+    }
+}
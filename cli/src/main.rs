@@ -0,0 +1,530 @@
+use std::{io::Read, path::PathBuf, time::Duration};
+
+use api::{
+    function::{
+        execution::{Execution, ExecutionId},
+        registration::{
+            EgressPolicy, Function, FunctionSpec, InputKind, OutputKind, PathEntry, Root, Runtime,
+            SubPath,
+        },
+        retry::RetryPolicy,
+    },
+    types::{BlobAddress, ExitKind, JsonData},
+    worker::{Worker, WorkerId},
+};
+use clap::{Parser, Subcommand, ValueEnum};
+use client::Client;
+use output::{Format, Table};
+use serde_json::{json, Value};
+
+mod client;
+mod output;
+
+/// Deploy, invoke and inspect functions on a wasi-faas control plane
+#[derive(Parser)]
+#[clap(name = "wasi-faas")]
+struct Args {
+    #[clap(long, default_value = "http://localhost:3000", global = true)]
+    control_plane_address: String,
+    #[clap(long, short, value_enum, default_value = "table", global = true)]
+    output: Format,
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Upload a .wasm module or component and print its blob address
+    Upload { wasm: PathBuf },
+    /// Register a function, it is served on /api/<name> and on the path given with --path
+    Register {
+        name: String,
+        #[clap(long, value_enum)]
+        runtime: RuntimeArg,
+        /// The .wasm to upload for the first revision
+        #[clap(long, conflicts_with = "blob", required_unless_present = "blob")]
+        wasm: Option<PathBuf>,
+        /// An uploaded blob or a path under the worker's function directory
+        #[clap(long)]
+        blob: Option<String>,
+        #[clap(long, default_value = "")]
+        description: String,
+        #[clap(long, default_value = "")]
+        note: String,
+        /// The input type as JSON, e.g. '{"List":"Number"}'
+        #[clap(long, value_parser = parse_json::<InputKind>, default_value = "\"None\"")]
+        input_type: InputKind,
+        /// The output type as JSON
+        #[clap(long, value_parser = parse_json::<OutputKind>)]
+        output_type: Option<OutputKind>,
+        /// How asynchronous invocations are retried as JSON, e.g. '{"max_attempts":3}'
+        #[clap(long, value_parser = parse_json::<RetryPolicy>)]
+        retry: Option<RetryPolicy>,
+        /// Also serve the function on /api/<root>/<sub path>, e.g. `shop/cart`, optionally followed by `@<alias>` to
+        /// serve the revision behind the alias rather than the current one
+        #[clap(long)]
+        path: Option<String>,
+    },
+    /// Upload a .wasm as a new revision of a function and make it current
+    Deploy {
+        name: String,
+        wasm: PathBuf,
+        #[clap(long, default_value = "")]
+        note: String,
+    },
+    #[clap(subcommand)]
+    Functions(FunctionsCommand),
+    #[clap(subcommand)]
+    Workers(WorkersCommand),
+    /// Invoke a function through the gateway with JSON input, `-` reads the input from stdin
+    Invoke {
+        /// The function name, optionally followed by `@<alias>` or `@<revision>`
+        function: String,
+        input: Option<String>,
+    },
+    #[clap(subcommand)]
+    Executions(ExecutionsCommand),
+    /// Print the logs of a function's executions
+    Logs {
+        function: String,
+        /// Keep printing the logs of new executions
+        #[clap(long, short)]
+        follow: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum FunctionsCommand {
+    List,
+    Describe { name: String },
+}
+
+#[derive(Subcommand)]
+enum WorkersCommand {
+    List,
+    Describe { id: String },
+}
+
+#[derive(Subcommand)]
+enum ExecutionsCommand {
+    List {
+        #[clap(long)]
+        function: Option<String>,
+    },
+    Get {
+        id: String,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum RuntimeArg {
+    Wasm,
+    Component,
+    HttpHandler,
+}
+
+impl From<RuntimeArg> for Runtime {
+    fn from(runtime: RuntimeArg) -> Self {
+        match runtime {
+            RuntimeArg::Wasm => Runtime::Wasm,
+            RuntimeArg::Component => Runtime::Component,
+            RuntimeArg::HttpHandler => Runtime::HttpHandler,
+        }
+    }
+}
+
+/// Split `root/sub/path@alias` into the path's root, the segments below it and the alias if any
+fn parse_path(path: &str) -> anyhow::Result<(Root, SubPath, Option<&str>)> {
+    let (path, alias) = match path.split_once('@') {
+        Some((path, alias)) => (path, Some(alias)),
+        None => (path, None),
+    };
+    let path = path.trim_matches('/');
+    let (root, sub_path) = path.split_once('/').unwrap_or((path, ""));
+    Ok((
+        Root::parse(root).map_err(anyhow::Error::msg)?,
+        SubPath::parse(sub_path).map_err(anyhow::Error::msg)?,
+        alias,
+    ))
+}
+
+fn parse_json<T: serde::de::DeserializeOwned>(s: &str) -> Result<T, String> {
+    serde_json::from_str(s).map_err(|e| e.to_string())
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let Args {
+        control_plane_address,
+        output,
+        command,
+    } = Args::parse();
+    let client = Client::new(control_plane_address);
+
+    match command {
+        Command::Upload { wasm } => {
+            let address = client.upload(std::fs::read(wasm)?).await?;
+            output::print(output, &address, |address| {
+                let mut table = Table::new(vec!["BLOB ADDRESS"]);
+                table.row(vec![address.to_string()]);
+                table
+            });
+        }
+        Command::Register {
+            name,
+            runtime,
+            wasm,
+            blob,
+            description,
+            note,
+            input_type,
+            output_type,
+            retry,
+            path,
+        } => {
+            // checked before registering so that a bad path doesn't leave the function registered without it
+            let path = path.as_deref().map(parse_path).transpose()?;
+            let blob_address = match (wasm, blob) {
+                (Some(wasm), _) => client.upload(std::fs::read(wasm)?).await?,
+                (None, Some(blob)) => BlobAddress::from(blob),
+                (None, None) => unreachable!("clap requires --wasm or --blob"),
+            };
+            let function = client
+                .register(&FunctionSpec {
+                    name,
+                    description,
+                    runtime: runtime.into(),
+                    input_type,
+                    input_schema: None,
+                    output_type,
+                    output_schema: None,
+                    blob_address,
+                    note,
+                    egress: EgressPolicy::default(),
+                    retry,
+                })
+                .await?;
+            output::print(output, &function, |function| {
+                functions_table(std::slice::from_ref(function))
+            });
+            if let Format::Table = output {
+                println!("\nserved on /api/{}", function.name());
+            }
+            if let Some((root, sub_path, alias)) = path {
+                let mut entry = PathEntry::new(root, sub_path, function.name().to_string());
+                if let Some(alias) = alias {
+                    entry = entry.with_alias(alias.to_string());
+                }
+                let entry = client.register_path(&entry).await?;
+                if let Format::Table = output {
+                    println!("served on /api/{}", entry.path());
+                }
+            }
+        }
+        Command::Deploy { name, wasm, note } => {
+            let revision = client.deploy(&name, std::fs::read(wasm)?, &note).await?;
+            output::print(output, &revision, |revision| {
+                let mut table = Table::new(vec!["REVISION", "BLOB ADDRESS", "CREATED", "NOTE"]);
+                table.row(vec![
+                    revision.number().to_string(),
+                    revision.blob_address().to_string(),
+                    revision.create_time().to_string(),
+                    revision.note().to_string(),
+                ]);
+                table
+            });
+        }
+        Command::Functions(FunctionsCommand::List) => {
+            let functions = client.functions().await?;
+            output::print(output, &functions, |functions| functions_table(functions));
+        }
+        Command::Functions(FunctionsCommand::Describe { name }) => {
+            let function = client.function(&name).await?;
+            if let Format::Table = output {
+                println!(
+                    "{}",
+                    functions_table(std::slice::from_ref(&function)).render()
+                );
+                println!("input: {}", json!(function.input_type()));
+                println!("output: {}\n", json!(function.output_type()));
+            }
+            output::print(output, &function, revisions_table);
+        }
+        Command::Workers(WorkersCommand::List) => {
+            let workers = client.workers().await?;
+            output::print(output, &workers, |workers| workers_table(workers));
+        }
+        Command::Workers(WorkersCommand::Describe { id }) => {
+            let worker = client
+                .worker(&WorkerId::parse(&id)?)
+                .await?
+                .ok_or_else(|| anyhow::anyhow!("worker {id} not found"))?;
+            output::print(output, &worker, |worker| {
+                workers_table(std::slice::from_ref(worker))
+            });
+        }
+        Command::Invoke { function, input } => {
+            let input = match input.as_deref() {
+                None => None,
+                Some("-") => {
+                    let mut input = String::new();
+                    std::io::stdin().read_to_string(&mut input)?;
+                    Some(serde_json::from_str::<Value>(&input)?)
+                }
+                Some(input) => Some(serde_json::from_str::<Value>(input)?),
+            };
+            let invocation = client.invoke(&function, input.as_ref()).await?;
+            let body = serde_json::from_str::<Value>(&invocation.body)
+                .unwrap_or(Value::String(invocation.body));
+            match output {
+                Format::Json => println!(
+                    "{}",
+                    serde_json::to_string_pretty(&json!({
+                        "status": invocation.status.as_u16(),
+                        "execution": invocation.execution,
+                        "output": body,
+                    }))?
+                ),
+                Format::Table => {
+                    match &body {
+                        Value::String(text) => println!("{text}"),
+                        value => println!("{}", serde_json::to_string_pretty(value)?),
+                    }
+                    if let Some(execution) = invocation.execution {
+                        eprintln!("execution {execution}");
+                    }
+                }
+            }
+            if !invocation.status.is_success() {
+                anyhow::bail!("invocation failed with {}", invocation.status);
+            }
+        }
+        Command::Executions(ExecutionsCommand::List { function }) => {
+            let functions = client.functions().await?;
+            let function_id = match function {
+                Some(name) => Some(
+                    *functions
+                        .iter()
+                        .find(|f| f.name() == name)
+                        .ok_or_else(|| anyhow::anyhow!("function {name} not found"))?
+                        .id(),
+                ),
+                None => None,
+            };
+            let mut executions = client.executions(function_id.as_ref(), None).await?;
+            executions.sort_by(|a, b| a.request().create_time().cmp(b.request().create_time()));
+            output::print(output, &executions, |executions| {
+                executions_table(executions, &functions)
+            });
+        }
+        Command::Executions(ExecutionsCommand::Get { id }) => {
+            let execution = client.execution(&ExecutionId::parse(&id)?).await?;
+            let functions = client.functions().await?;
+            output::print(output, &execution, |execution| {
+                describe_execution(execution, &functions)
+            });
+        }
+        Command::Logs { function, follow } => {
+            let function = client.function(&function).await?;
+            // only the executions whose result the control plane stored after the last one printed are fetched again
+            let mut after = None;
+            loop {
+                let mut executions = client.executions(Some(function.id()), after).await?;
+                executions.retain(|e| e.sequence().is_some());
+                executions.sort_by_key(Execution::sequence);
+                for execution in executions {
+                    after = execution.sequence();
+                    print_logs(output, &execution)?;
+                }
+                if !follow {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn functions_table(functions: &[Function]) -> Table {
+    let mut table = Table::new(vec![
+        "NAME",
+        "RUNTIME",
+        "REVISION",
+        "ALIASES",
+        "DESCRIPTION",
+    ]);
+    for function in functions {
+        let aliases = function
+            .aliases()
+            .iter()
+            .map(|(alias, revision)| format!("{alias}={revision}"))
+            .collect::<Vec<_>>();
+        table.row(vec![
+            function.name().to_string(),
+            format!("{:?}", function.runtime()),
+            function.current_revision().number().to_string(),
+            aliases.join(","),
+            function.description().to_string(),
+        ]);
+    }
+    table
+}
+
+fn revisions_table(function: &Function) -> Table {
+    let mut table = Table::new(vec!["REVISION", "BLOB ADDRESS", "CREATED", "NOTE"]);
+    for revision in function.revisions() {
+        let current = if revision.number() == function.current_revision().number() {
+            " (current)"
+        } else {
+            ""
+        };
+        table.row(vec![
+            format!("{}{current}", revision.number()),
+            revision.blob_address().to_string(),
+            revision.create_time().to_string(),
+            revision.note().to_string(),
+        ]);
+    }
+    table
+}
+
+fn workers_table(workers: &[Worker]) -> Table {
+    let mut table = Table::new(vec![
+        "ID",
+        "ADDRESS",
+        "STATUS",
+        "IN FLIGHT",
+        "LOADED",
+        "LAST HEARTBEAT",
+    ]);
+    for worker in workers {
+        // workers report their load with their heartbeats
+        let (in_flight, loaded) = match worker.report() {
+            Some(report) => (
+                match report.capacity() {
+                    Some(capacity) => format!("{}/{capacity}", report.in_flight()),
+                    None => report.in_flight().to_string(),
+                },
+                report
+                    .functions()
+                    .values()
+                    .map(Vec::len)
+                    .sum::<usize>()
+                    .to_string(),
+            ),
+            None => ("-".to_string(), "-".to_string()),
+        };
+        table.row(vec![
+            worker.id().to_string(),
+            worker.address().to_string(),
+            format!("{:?}", worker.status()),
+            in_flight,
+            loaded,
+            format!("{:.0?} ago", worker.last_heartbeat().elapsed()),
+        ]);
+    }
+    table
+}
+
+fn executions_table(executions: &[Execution], functions: &[Function]) -> Table {
+    let mut table = Table::new(vec![
+        "ID", "FUNCTION", "REVISION", "STATUS", "EXIT", "CREATED",
+    ]);
+    for execution in executions {
+        let request = execution.request();
+        let function = functions
+            .iter()
+            .find(|f| f.id() == request.target_function())
+            .map_or_else(
+                || request.target_function().to_string(),
+                |f| f.name().to_string(),
+            );
+        table.row(vec![
+            execution.id().to_string(),
+            function,
+            request.revision().to_string(),
+            format!("{:?}", execution.status()),
+            execution
+                .result()
+                .map(|result| exit(result.exit()))
+                .unwrap_or_default(),
+            request.create_time().to_string(),
+        ]);
+    }
+    table
+}
+
+fn describe_execution(execution: &Execution, functions: &[Function]) -> Table {
+    let mut table = Table::new(vec!["FIELD", "VALUE"]);
+    let summary = executions_table(std::slice::from_ref(execution), functions);
+    for (field, value) in summary.headers().iter().zip(&summary.rows()[0]) {
+        table.row(vec![field.to_lowercase(), value.clone()]);
+    }
+    if let Some(result) = execution.result() {
+        let output = result.output_data().map(|o| o.data().to_string());
+        let completed = result.complete_time().map(ToString::to_string);
+        table.row(vec!["completed".to_string(), completed.unwrap_or_default()]);
+        table.row(vec!["worker".to_string(), result.worker().to_string()]);
+        table.row(vec!["output".to_string(), output.unwrap_or_default()]);
+        table.row(vec![
+            "error".to_string(),
+            result.error().unwrap_or_default().to_string(),
+        ]);
+        for entry in result.logs() {
+            table.row(vec![
+                "log".to_string(),
+                format!("{} {}", entry.time(), entry.message()),
+            ]);
+        }
+    }
+    table
+}
+
+fn exit(exit: &ExitKind) -> String {
+    match exit {
+        ExitKind::Success => "success".to_string(),
+        ExitKind::Failure { exit_code } => format!("failure ({exit_code})"),
+        ExitKind::TimeOut => "timeout".to_string(),
+    }
+}
+
+fn print_logs(format: Format, execution: &Execution) -> anyhow::Result<()> {
+    let Some(result) = execution.result() else {
+        return Ok(());
+    };
+    match format {
+        Format::Json => {
+            // one object per line so that followed logs can be streamed into other tools
+            let line = json!({
+                "execution": execution.id(),
+                "revision": execution.request().revision(),
+                "exit": result.exit(),
+                "logs": result.logs(),
+                "error": result.error(),
+            });
+            println!("{}", serde_json::to_string(&line)?);
+        }
+        Format::Table => {
+            let id = execution.id();
+            for entry in result.logs() {
+                println!("{} {id} {}", entry.time(), entry.message());
+            }
+            let output = result.output_data().map(|o| JsonData::to_string(o.data()));
+            match result.error() {
+                Some(error) => println!(
+                    "{} {id} {} {error}",
+                    result.create_time(),
+                    exit(result.exit())
+                ),
+                None => println!(
+                    "{} {id} {} {}",
+                    result.create_time(),
+                    exit(result.exit()),
+                    output.unwrap_or_default()
+                ),
+            }
+        }
+    }
+    Ok(())
+}
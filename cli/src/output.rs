@@ -0,0 +1,83 @@
+use serde::Serialize;
+
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum Format {
+    Table,
+    Json,
+}
+
+/// Plain text table with columns padded to their widest cell, only the first line of multi-line cells (e.g. errors
+/// with a backtrace) counts towards the width
+pub struct Table {
+    headers: Vec<&'static str>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    pub fn new(headers: Vec<&'static str>) -> Self {
+        Table {
+            headers,
+            rows: Vec::new(),
+        }
+    }
+    pub fn row(&mut self, cells: Vec<String>) {
+        self.rows.push(cells);
+    }
+    pub fn headers(&self) -> &[&'static str] {
+        &self.headers
+    }
+    pub fn rows(&self) -> &[Vec<String>] {
+        &self.rows
+    }
+    pub fn render(&self) -> String {
+        let mut widths = self.headers.iter().map(|h| h.len()).collect::<Vec<_>>();
+        for row in &self.rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                let first_line = cell.lines().next().unwrap_or_default();
+                *width = (*width).max(first_line.chars().count());
+            }
+        }
+        let line = |cells: Vec<&str>| {
+            let padded = cells
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{cell:width$}"))
+                .collect::<Vec<_>>();
+            padded.join("  ").trim_end().to_string()
+        };
+        let mut lines = vec![line(self.headers.clone())];
+        lines.extend(
+            self.rows
+                .iter()
+                .map(|row| line(row.iter().map(String::as_str).collect())),
+        );
+        lines.join("\n")
+    }
+}
+
+/// Print the value as JSON or as the table built from it
+pub fn print<T: Serialize>(format: Format, value: &T, table: impl FnOnce(&T) -> Table) {
+    match format {
+        Format::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(value).expect("API types serialize to JSON")
+        ),
+        Format::Table => println!("{}", table(value).render()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn table_alignment() {
+        let mut table = Table::new(vec!["NAME", "RUNTIME"]);
+        table.row(vec!["add".to_string(), "Wasm".to_string()]);
+        table.row(vec!["greeting".to_string(), "".to_string()]);
+        assert_eq!(
+            table.render(),
+            "NAME      RUNTIME\nadd       Wasm\ngreeting"
+        );
+    }
+}
@@ -1,40 +1,996 @@
-use api::{types::JsonData, worker::WorkerStatus};
+use std::time::{Duration, Instant};
+
+use api::{
+    function::{
+        dead_letter::{AsyncRequest, DeadLetter},
+        execution::{
+            Execution, ExecutionId, ExecutionRequest, ExecutionResult, ExecutionStatus, Input,
+            Trigger, EXECUTION_ID_HEADER, FUNCTION_ID_HEADER, INVOCATION_TYPE_HEADER,
+            REVISION_HEADER,
+        },
+        registration::{Function, RevisionNumber, Runtime, ValidationError},
+    },
+    types::{Id, JsonData},
+    worker::{Worker, WORKER_FULL_HEADER},
+};
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
+    body::{self, Body, Bytes},
+    extract::{Path, RawQuery, State},
+    http::{header, HeaderMap, HeaderName, HeaderValue, Method, StatusCode},
+    response::{IntoResponse, Response},
     Json,
 };
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::task::JoinHandle;
+
+use crate::{
+    dead_letters::DeadLetterStore,
+    executions::ExecutionStore,
+    functions::FunctionStore,
+    metrics,
+    paths::PathStore,
+    pending::{Pending, PendingStore, Refused},
+    placement,
+    queue::Queue,
+    traffic,
+    workers::WorkerStore,
+};
+
+/// How long a call waits for the worker to report the result of an execution it has responded to
+const RESULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Headers which only apply to a single connection and aren't forwarded
+const HOP_BY_HOP: [HeaderName; 8] = [
+    header::CONNECTION,
+    header::HOST,
+    header::CONTENT_LENGTH,
+    header::PROXY_AUTHENTICATE,
+    header::PROXY_AUTHORIZATION,
+    header::TE,
+    header::TRAILER,
+    header::TRANSFER_ENCODING,
+];
+
+/// Credentials of the caller which aren't kept with asynchronous requests, as they are saved and may be dead-lettered
+const CREDENTIALS: [HeaderName; 4] = [
+    header::AUTHORIZATION,
+    header::PROXY_AUTHORIZATION,
+    header::COOKIE,
+    HeaderName::from_static("x-api-key"),
+];
+
+#[derive(Clone)]
+pub struct GatewayState {
+    pub workers: WorkerStore,
+    pub functions: FunctionStore,
+    pub executions: ExecutionStore,
+    pub client: reqwest::Client,
+    /// How long a worker has to start responding to a request, see [`GatewayConfig`](crate::config::GatewayConfig)
+    pub timeout: Duration,
+    pub queue: Queue,
+    pub dead_letters: DeadLetterStore,
+    pub pending: PendingStore,
+    pub paths: PathStore,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FunctionPath {
+    function: String,
+    path: Option<String>,
+}
+
+/// A function invocation, from a request to the gateway or one of the control plane's triggers
+pub struct Invocation {
+    /// `name`, `name@alias` or `name@revision`, the latter two invoking a specific revision
+    pub function: String,
+    pub path: Option<String>,
+    pub query: Option<String>,
+    pub method: Method,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+    pub trigger: Trigger,
+}
 
-use crate::workers::WorkerStore;
+impl Invocation {
+    /// Invoke the function with a JSON input, as a POST to `/api/{function}` would
+    pub fn json(function: &str, input: &Value, trigger: Trigger) -> Self {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/json"),
+        );
+        Invocation {
+            function: function.to_string(),
+            path: None,
+            query: None,
+            method: Method::POST,
+            headers,
+            body: Bytes::from(input.to_string()),
+            trigger,
+        }
+    }
 
-#[tracing::instrument(skip(store))]
+    /// Invoke an asynchronous request again
+    pub fn replay(request: &AsyncRequest) -> Self {
+        let headers = request
+            .headers
+            .iter()
+            .filter_map(|(name, value)| {
+                Some((
+                    HeaderName::try_from(name).ok()?,
+                    HeaderValue::try_from(value).ok()?,
+                ))
+            })
+            .collect();
+        Invocation {
+            function: request.function.clone(),
+            path: request.path.clone(),
+            query: request.query.clone(),
+            method: Method::from_bytes(request.method.as_bytes()).unwrap_or(Method::POST),
+            headers,
+            body: Bytes::from(request.body.clone()),
+            trigger: request.trigger.clone(),
+        }
+    }
+
+    /// The invocation as a request which can be kept and replayed, without the caller's credentials
+    pub fn into_async(self) -> AsyncRequest {
+        let headers = self
+            .headers
+            .iter()
+            .filter(|(name, _)| !CREDENTIALS.contains(name))
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+            .collect();
+        AsyncRequest {
+            function: self.function,
+            path: self.path,
+            query: self.query,
+            method: self.method.to_string(),
+            headers,
+            body: self.body.to_vec(),
+            trigger: self.trigger,
+        }
+    }
+}
+
+/// Relay the request to a worker and its response back to the caller as is, so that functions handling raw HTTP
+/// get the original method, path, headers and body.
+///
+/// Each request is recorded as an `Execution` which the worker completes with its result, the execution id is
+/// returned to the caller in the `x-wasi-faas-execution-id` header.
+///
+/// A request to a registered path is served by the path's function, and the revision picked from its split or behind
+/// its alias if it has either, with the rest of the path below it. Other requests are served by the function named by their first segment.
+///
+/// A request with an `x-wasi-faas-invocation-type: async` header is accepted with a 202 right away and invoked in
+/// the background, see [`spawn_async`]. It is turned away with a 503 when too many requests are pending already.
+#[tracing::instrument(skip(state, headers, body))]
 pub async fn proxy(
-    State(store): State<WorkerStore>,
-    Path(function): Path<String>,
-    Json(payload): Json<JsonData>,
-) -> Result<Json<JsonData>, StatusCode> {
-    let workers = store.list(Some(&WorkerStatus::Available));
-    if workers.is_empty() {
-        return Err(StatusCode::SERVICE_UNAVAILABLE);
-    }
-    let worker = workers.iter().next().unwrap();
-    tracing::info!("proxying request to worker: {}", worker.id());
-    let url = format!("http://{}/execute/{}", worker.address(), function);
-    let client = reqwest::Client::new();
-    let response = client.post(&url).json(&payload).send().await;
-    match response {
+    State(state): State<GatewayState>,
+    Path(FunctionPath { function, path }): Path<FunctionPath>,
+    RawQuery(query): RawQuery,
+    method: Method,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let (function, path) = match state.paths.route(&function, path.as_deref()) {
+        Some((entry, rest)) => match entry.traffic() {
+            // the picked revision is pinned so that an asynchronous request keeps it when retried
+            Some(split) => {
+                let revision = traffic::pick(split, &headers);
+                (format!("{}@{revision}", entry.function()), rest)
+            }
+            None => (entry.target(), rest),
+        },
+        None => (function, path),
+    };
+    let invocation = Invocation {
+        function,
+        path,
+        query,
+        method,
+        headers,
+        body,
+        trigger: Trigger::Http,
+    };
+    let asynchronous = invocation
+        .headers
+        .get(INVOCATION_TYPE_HEADER)
+        .is_some_and(|value| value == "async");
+    if !asynchronous {
+        return invoke(state, invocation).await;
+    }
+    let name = invocation
+        .function
+        .split_once('@')
+        .map_or(invocation.function.as_str(), |(name, _)| name);
+    if state.functions.get_by_name(name).is_none() {
+        return Rejection::NotFound(name.to_string()).into_response();
+    }
+    let pending = Pending {
+        request: invocation.into_async(),
+        replaying: None,
+    };
+    match spawn_async(state, pending) {
+        Ok(_) => StatusCode::ACCEPTED.into_response(),
+        Err(_) => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "too many asynchronous requests pending",
+        )
+            .into_response(),
+    }
+}
+
+/// Admit, queue and dispatch the invocation like a gateway request, responding with the worker's response
+pub async fn invoke(mut state: GatewayState, invocation: Invocation) -> Response {
+    let Invocation {
+        function,
+        path,
+        query,
+        method,
+        headers,
+        body,
+        trigger,
+    } = invocation;
+    let start = Instant::now();
+    // `name@alias` or `name@revision` invokes a specific revision
+    let (function, pinned) = match function.split_once('@') {
+        Some((name, target)) => (name.to_string(), Some(target.to_string())),
+        None => (function, None),
+    };
+    let admitted = admit(
+        &state.functions,
+        &function,
+        pinned.as_deref(),
+        &headers,
+        &body,
+    );
+    let (registered, revision, input) = match admitted {
+        Ok(admitted) => admitted,
+        Err(rejection) => {
+            metrics::record_gateway_request(
+                &function,
+                None,
+                rejection.status().as_u16(),
+                start.elapsed(),
+            );
+            return rejection.into_response();
+        }
+    };
+
+    let deadline = state.queue.deadline();
+    let rank = |workers| placement::rank(workers, &registered, revision);
+    let mut workers = match state
+        .queue
+        .wait(&function, &state.workers, deadline, rank)
+        .await
+    {
+        Ok(workers) => workers,
+        Err(rejection) => {
+            metrics::record_gateway_request(
+                &function,
+                None,
+                StatusCode::SERVICE_UNAVAILABLE.as_u16(),
+                start.elapsed(),
+            );
+            return rejection.into_response();
+        }
+    };
+
+    let execution = Execution::new(ExecutionRequest::new(
+        *registered.id(),
+        revision,
+        input,
+        trigger,
+    ));
+    let execution_id = *execution.id();
+    let execution_header = HeaderValue::try_from(execution_id.to_string())
+        .expect("execution ids are valid header values");
+    state.executions.insert(execution);
+
+    let mut forwarded = forwardable(&headers);
+    if let Some(host) = headers.get(header::HOST) {
+        forwarded.insert("x-forwarded-host", host.clone());
+    }
+    forwarded.insert(EXECUTION_ID_HEADER, execution_header.clone());
+    forwarded.insert(
+        REVISION_HEADER,
+        HeaderValue::try_from(revision.to_string())
+            .expect("revision numbers are valid header values"),
+    );
+    forwarded.insert(
+        FUNCTION_ID_HEADER,
+        HeaderValue::try_from(registered.id().to_string())
+            .expect("function ids are valid header values"),
+    );
+    telemetry::inject_context(&mut forwarded);
+    let mut target = match &path {
+        Some(path) => format!("{function}/{path}"),
+        None => function.clone(),
+    };
+    if let Some(query) = query {
+        target = format!("{target}?{query}");
+    }
+
+    state
+        .executions
+        .update_status(&execution_id, ExecutionStatus::Assigned);
+    let (worker, mut result) = loop {
+        let (worker, result) = dispatch(
+            &mut state,
+            &workers,
+            &function,
+            &target,
+            method.clone(),
+            forwarded.clone(),
+            body.clone(),
+        )
+        .await;
+        let all_full =
+            matches!(&result, Ok(response) if response.headers().contains_key(WORKER_FULL_HEADER));
+        if !all_full {
+            break (worker.clone(), result);
+        }
+        // every worker turned the execution away, it waits in the queue again until the same deadline
+        workers = match state
+            .queue
+            .wait(&function, &state.workers, deadline, rank)
+            .await
+        {
+            Ok(workers) => workers,
+            Err(rejection) => {
+                state
+                    .executions
+                    .update_status(&execution_id, ExecutionStatus::Unknown);
+                break (worker.clone(), Ok(rejection.into_response()));
+            }
+        };
+    };
+    let status = match &mut result {
         Ok(response) => {
-            let status = response.status();
-            if status.is_success() {
-                let body = response.json().await.map_err(|e| {
-                    tracing::error!(error = ?e, "failed to parse response body");
-                    StatusCode::BAD_GATEWAY
-                })?;
-                Ok(Json(body))
+            let headers = response.headers_mut();
+            // only meant for the gateway, a full worker's 503 reaches the client as a plain 503
+            headers.remove(WORKER_FULL_HEADER);
+            headers.insert(EXECUTION_ID_HEADER, execution_header);
+            response.status()
+        }
+        Err(status) => {
+            state
+                .executions
+                .update_status(&execution_id, ExecutionStatus::Unknown);
+            *status
+        }
+    };
+    metrics::record_gateway_request(
+        &function,
+        Some(worker.id()),
+        status.as_u16(),
+        start.elapsed(),
+    );
+    result.unwrap_or_else(IntoResponse::into_response)
+}
+
+/// The outcome of an asynchronous invocation
+pub struct AsyncOutcome {
+    pub succeeded: bool,
+    /// The executions of its attempts, attempts the gateway turned away having none
+    pub executions: Vec<ExecutionId>,
+}
+
+/// Invoke the request until it succeeds or the function's retry policy gives up on it, the request then being kept
+/// in the dead letters with the outcome of its last attempt.
+///
+/// Requests the gateway turns away as invalid, or whose function has been removed, aren't retried.
+pub async fn invoke_async(mut state: GatewayState, request: AsyncRequest) -> AsyncOutcome {
+    let mut attempt = 0;
+    let mut executions = Vec::new();
+    loop {
+        attempt += 1;
+        // the policy is read on every attempt so that a change applies to requests being retried
+        let policy = state
+            .functions
+            .get_by_name(request.function_name())
+            .and_then(|function| function.retry().cloned())
+            .unwrap_or_default();
+        let response = invoke(state.clone(), Invocation::replay(&request)).await;
+        let status = response.status();
+        let execution = execution_id(&response);
+        executions.extend(execution);
+        let body = body::to_bytes(response.into_body(), usize::MAX).await;
+        if status.is_success() && body.is_ok() {
+            metrics::record_async_invocation(request.function_name(), "succeeded");
+            return AsyncOutcome {
+                succeeded: true,
+                executions,
+            };
+        }
+        let result = match &execution {
+            Some(id) => state.executions.wait_result(id, RESULT_TIMEOUT).await,
+            None => None,
+        };
+        let error = match body {
+            Ok(body) => format!("{status}: {}", String::from_utf8_lossy(&body)),
+            Err(e) => format!("failed to read the response: {e}"),
+        };
+        let rejected = execution.is_none() && status.is_client_error();
+        let retried = !rejected
+            && attempt < policy.max_attempts
+            && policy.retries(result.as_ref().map(ExecutionResult::exit));
+        if retried {
+            tracing::info!(
+                function = request.function,
+                attempt,
+                error,
+                "asynchronous invocation failed, retrying"
+            );
+            metrics::record_async_invocation(request.function_name(), "retried");
+            tokio::time::sleep(policy.backoff(attempt)).await;
+            continue;
+        }
+        tracing::warn!(
+            function = request.function,
+            attempt,
+            error,
+            "asynchronous invocation failed, moving it to the dead letters"
+        );
+        metrics::record_async_invocation(request.function_name(), "dead_lettered");
+        state
+            .dead_letters
+            .insert(DeadLetter::new(request, attempt, execution, result, error));
+        return AsyncOutcome {
+            succeeded: false,
+            executions,
+        };
+    }
+}
+
+/// Keep the request with the pending ones and invoke it in the background, see [`invoke_async`]. It stays pending
+/// until it has succeeded or been dead-lettered, so that it is invoked again if the control plane restarts before
+/// then, and the dead letter it replays is only removed once it has.
+pub fn spawn_async(
+    mut state: GatewayState,
+    pending: Pending,
+) -> Result<JoinHandle<AsyncOutcome>, Refused> {
+    let id = state.pending.insert(pending.clone())?;
+    Ok(tokio::spawn(complete_pending(state, id, pending)))
+}
+
+/// Invoke the requests left pending by a previous run again in the background
+pub fn resume_async(state: GatewayState) {
+    for (id, pending) in state.pending.list() {
+        tracing::info!(
+            function = pending.request.function,
+            "resuming asynchronous invocation"
+        );
+        tokio::spawn(complete_pending(state.clone(), id, pending));
+    }
+}
+
+async fn complete_pending(mut state: GatewayState, id: Id, pending: Pending) -> AsyncOutcome {
+    let outcome = invoke_async(state.clone(), pending.request).await;
+    if let Some(letter) = &pending.replaying {
+        state.dead_letters.remove(letter);
+    }
+    state.pending.remove(&id);
+    outcome
+}
+
+/// The execution the gateway created for a request, from the response's `x-wasi-faas-execution-id` header
+fn execution_id(response: &Response) -> Option<ExecutionId> {
+    response
+        .headers()
+        .get(EXECUTION_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| ExecutionId::parse(value).ok())
+}
+
+/// The outcome of calling a function with a JSON input
+pub struct Call {
+    /// The status the gateway responded with
+    pub status: StatusCode,
+    /// Unset when the gateway turned the call away before creating an execution
+    pub execution: Option<ExecutionId>,
+    /// Unset when the worker didn't report the execution's result in time
+    pub result: Option<ExecutionResult>,
+    /// The function's output, or why the call failed
+    pub output: Result<Value, String>,
+}
+
+/// Invoke the function with the JSON input and wait for its output and the result reported by the worker, for the
+/// control plane's own compositions of functions
+pub async fn call(state: GatewayState, function: &str, input: &Value, trigger: Trigger) -> Call {
+    let executions = state.executions.clone();
+    let response = invoke(state, Invocation::json(function, input, trigger)).await;
+    let status = response.status();
+    let execution = execution_id(&response);
+    let body = body::to_bytes(response.into_body(), usize::MAX).await;
+    let result = match &execution {
+        Some(id) => executions.wait_result(id, RESULT_TIMEOUT).await,
+        None => None,
+    };
+    let output = match body {
+        Ok(body) if status.is_success() => {
+            serde_json::from_slice(&body).map_err(|e| format!("invalid JSON output: {e}"))
+        }
+        Ok(body) => Err(format!("{status}: {}", String::from_utf8_lossy(&body))),
+        Err(e) => Err(format!("failed to read the response: {e}")),
+    };
+    Call {
+        status,
+        execution,
+        result,
+        output,
+    }
+}
+
+/// Why a request was turned down before being dispatched to a worker
+enum Rejection {
+    NotFound(String),
+    UnknownRevision { function: String, target: String },
+    InvalidJson(serde_json::Error),
+    InvalidInput(ValidationError),
+}
+
+impl Rejection {
+    fn status(&self) -> StatusCode {
+        match self {
+            Rejection::NotFound(_) | Rejection::UnknownRevision { .. } => StatusCode::NOT_FOUND,
+            Rejection::InvalidJson(_) => StatusCode::BAD_REQUEST,
+            Rejection::InvalidInput(_) => StatusCode::UNPROCESSABLE_ENTITY,
+        }
+    }
+}
+
+impl IntoResponse for Rejection {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        match self {
+            Rejection::NotFound(function) => {
+                (status, format!("function {function} not found")).into_response()
+            }
+            Rejection::UnknownRevision { function, target } => (
+                status,
+                format!("function {function} has no alias or revision {target}"),
+            )
+                .into_response(),
+            Rejection::InvalidJson(e) => {
+                (status, format!("invalid JSON input: {e}")).into_response()
+            }
+            Rejection::InvalidInput(e) => (status, Json(e)).into_response(),
+        }
+    }
+}
+
+/// Look up the function, resolve the revision serving the request and check the request's input against the
+/// function's declared input type and schema, functions which receive the raw HTTP request have no input to check.
+///
+/// The revision is pinned here so that an upload or rollback during dispatch doesn't change what serves the request.
+fn admit(
+    functions: &FunctionStore,
+    function: &str,
+    pinned: Option<&str>,
+    headers: &HeaderMap,
+    body: &Bytes,
+) -> Result<(Function, RevisionNumber, Option<Input>), Rejection> {
+    let registered = functions
+        .get_by_name(function)
+        .ok_or_else(|| Rejection::NotFound(function.to_string()))?;
+    let revision = match pinned {
+        Some(target) => registered
+            .resolve(target)
+            .ok_or_else(|| Rejection::UnknownRevision {
+                function: function.to_string(),
+                target: target.to_string(),
+            })?,
+        None => traffic::pick_revision(&registered, headers),
+    };
+    if registered.runtime() == Runtime::HttpHandler {
+        return Ok((registered, revision, None));
+    }
+    let input = parse_input(body).map_err(Rejection::InvalidJson)?;
+    registered
+        .validate_input(input.data().value())
+        .map_err(|e| {
+            tracing::info!(error = %e, "rejecting invalid input");
+            Rejection::InvalidInput(e)
+        })?;
+    Ok((registered, revision, Some(input)))
+}
+
+/// Send the request to the available workers in order, moving on to the next one only when a worker can't be reached
+/// or is full, full workers being marked as occupied until their next heartbeat
+async fn dispatch<'a>(
+    state: &mut GatewayState,
+    workers: &'a [Worker],
+    function: &str,
+    target: &str,
+    method: Method,
+    headers: HeaderMap,
+    body: Bytes,
+) -> (&'a Worker, Result<Response, StatusCode>) {
+    let mut workers = workers.iter().peekable();
+    loop {
+        let worker = workers.next().expect("at least one worker to dispatch to");
+        tracing::info!("proxying request to worker: {}", worker.id());
+        let url = format!("{}/execute/{target}", worker_url(worker));
+        let sent = state
+            .client
+            .request(method.clone(), &url)
+            .headers(headers.clone())
+            .body(body.clone())
+            .send();
+        // the worker may still be running the execution, so it isn't sent to another one
+        let Ok(sent) = tokio::time::timeout(state.timeout, sent).await else {
+            tracing::error!(worker = %worker.id(), "worker didn't respond in time");
+            return (worker, Err(StatusCode::GATEWAY_TIMEOUT));
+        };
+        let response = match sent {
+            Ok(response) if response.headers().contains_key(WORKER_FULL_HEADER) => {
+                state.workers.mark_occupied(worker.id());
+                if workers.peek().is_none() {
+                    return (worker, relay(response));
+                }
+                tracing::info!(worker = %worker.id(), "worker is full, retrying");
+                metrics::record_gateway_retry(function, worker.id());
+                continue;
+            }
+            Ok(response) => response,
+            Err(e) if workers.peek().is_some() => {
+                tracing::warn!(error = ?e, worker = %worker.id(), "worker unreachable, retrying");
+                metrics::record_gateway_retry(function, worker.id());
+                continue;
+            }
+            Err(e) => {
+                tracing::error!(error = ?e, "failed to reach worker");
+                return (worker, Err(StatusCode::BAD_GATEWAY));
+            }
+        };
+        return (worker, relay(response));
+    }
+}
+
+/// Workers serving HTTPS register their address with the scheme, plain addresses are served over HTTP
+fn worker_url(worker: &Worker) -> String {
+    let address = worker.address().to_string();
+    if address.contains("://") {
+        address
+    } else {
+        format!("http://{address}")
+    }
+}
+
+/// An empty body is treated as `null` so that functions without input can be invoked without one
+fn parse_input(body: &Bytes) -> serde_json::Result<Input> {
+    if body.is_empty() {
+        return Ok(JsonData::from(Value::Null).into());
+    }
+    Ok(serde_json::from_slice::<JsonData>(body)?.into())
+}
+
+fn relay(response: reqwest::Response) -> Result<Response, StatusCode> {
+    let mut builder = Response::builder().status(response.status());
+    if let Some(headers) = builder.headers_mut() {
+        *headers = forwardable(response.headers());
+    }
+    builder
+        .body(Body::from_stream(response.bytes_stream()))
+        .map_err(|e| {
+            tracing::error!(error = ?e, "failed to build response");
+            StatusCode::BAD_GATEWAY
+        })
+}
+
+fn forwardable(headers: &HeaderMap) -> HeaderMap {
+    let mut headers = headers.clone();
+    for name in HOP_BY_HOP {
+        headers.remove(name);
+    }
+    headers
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{
+        collections::BTreeMap,
+        sync::{
+            atomic::{AtomicU32, Ordering},
+            Arc,
+        },
+    };
+
+    use api::{
+        function::{
+            dead_letter::DeadLetterId,
+            execution::{ExecutionResult, Output},
+            registration::{PathEntry, Root, SplitMode, SubPath, TrafficSplit, WeightedRevision},
+            retry::RetryPolicy,
+        },
+        types::{ExitKind, TimeStamp},
+        worker::WorkerId,
+    };
+    use axum::{extract::State, routing::post, Router};
+
+    use super::*;
+    use crate::{functions::samples, state::StateFile};
+
+    /// A gateway to a worker serving `add`, whose first `failures` executions fail
+    pub(crate) async fn gateway(failures: Arc<AtomicU32>) -> GatewayState {
+        let executions = ExecutionStore::new(100);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let worker = Worker::new(listener.local_addr().unwrap().to_string().into());
+        let execute = |State((mut executions, failures, worker)): State<(
+            ExecutionStore,
+            Arc<AtomicU32>,
+            WorkerId,
+        )>,
+                       headers: HeaderMap| async move {
+            let id = ExecutionId::parse(headers[EXECUTION_ID_HEADER].to_str().unwrap()).unwrap();
+            let failed = failures
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+                .is_ok();
+            let (outcome, exit, status) = if failed {
+                (
+                    Err("boom".to_string()),
+                    ExitKind::Failure { exit_code: 1 },
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                )
             } else {
-                Err(status)
+                (
+                    Ok(Some(Output::from(JsonData::from(Value::from(3))))),
+                    ExitKind::Success,
+                    StatusCode::OK,
+                )
+            };
+            let result = ExecutionResult::new(TimeStamp::now(), outcome, exit, worker, vec![]);
+            executions.complete(&id, result);
+            (status, if failed { "boom" } else { "3" })
+        };
+        let app = Router::new()
+            .route("/execute/*target", post(execute))
+            .with_state((executions.clone(), failures, *worker.id()));
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let mut workers = WorkerStore::new();
+        workers.insert(worker);
+        let mut functions = FunctionStore::open(StateFile::memory()).unwrap();
+        let add = samples().into_iter().find(|spec| spec.name == "add");
+        functions.insert(Function::new(add.unwrap()));
+        functions.modify("add", |add| {
+            add.set_retry(Some(RetryPolicy {
+                max_attempts: 3,
+                initial_backoff_ms: 1,
+                ..RetryPolicy::default()
+            }))
+        });
+        GatewayState {
+            queue: Queue::new(&workers, 0, Duration::from_secs(1)),
+            workers,
+            functions,
+            executions,
+            client: reqwest::Client::new(),
+            timeout: Duration::from_secs(5),
+            dead_letters: DeadLetterStore::open(StateFile::memory(), 10).unwrap(),
+            pending: PendingStore::open(StateFile::memory(), 10).unwrap(),
+            paths: PathStore::open(StateFile::memory()).unwrap(),
+        }
+    }
+
+    pub(crate) fn request() -> AsyncRequest {
+        Invocation::json("add", &serde_json::json!([1, 2]), Trigger::Http).into_async()
+    }
+
+    #[tokio::test]
+    async fn retries_until_success() {
+        let state = gateway(Arc::new(AtomicU32::new(2))).await;
+        let outcome = invoke_async(state.clone(), request()).await;
+        assert!(outcome.succeeded);
+        assert_eq!(outcome.executions.len(), 3);
+        assert!(state.dead_letters.list(None).is_empty());
+    }
+
+    #[tokio::test]
+    async fn dead_letters_after_the_last_attempt() {
+        let state = gateway(Arc::new(AtomicU32::new(3))).await;
+        let outcome = invoke_async(state.clone(), request()).await;
+        assert!(!outcome.succeeded);
+        assert_eq!(outcome.executions.len(), 3);
+        let letters = state.dead_letters.list(Some("add"));
+        assert_eq!(letters.len(), 1);
+        assert_eq!(letters[0].attempts(), 3);
+        assert_eq!(letters[0].execution(), outcome.executions.last());
+        assert!(letters[0].result().is_some());
+    }
+
+    #[tokio::test]
+    async fn rejected_requests_are_not_retried() {
+        let state = gateway(Arc::new(AtomicU32::new(0))).await;
+        let mut invalid = request();
+        invalid.body = b"not json".to_vec();
+        let outcome = invoke_async(state.clone(), invalid).await;
+        assert!(!outcome.succeeded);
+        assert!(outcome.executions.is_empty());
+        assert_eq!(state.dead_letters.list(None)[0].attempts(), 1);
+    }
+
+    #[tokio::test]
+    async fn resumed_requests_use_the_saved_retry_policy() {
+        let dir = std::env::temp_dir().join(format!("wasi-faas-gateway-{}", Id::new()));
+        let file = |name: &str| StateFile::open(&dir, name).unwrap();
+        let mut functions = FunctionStore::open(file("functions")).unwrap();
+        let add = samples().into_iter().find(|spec| spec.name == "add");
+        functions.insert(Function::new(add.unwrap()));
+        functions.modify("add", |add| {
+            add.set_retry(Some(RetryPolicy {
+                max_attempts: 2,
+                initial_backoff_ms: 1,
+                ..RetryPolicy::default()
+            }))
+        });
+        // the dead letter a previous run left being replayed
+        let letter = DeadLetter::new(request(), 1, None, None, "failed".to_string());
+        let pending = Pending {
+            request: request(),
+            replaying: Some(*letter.id()),
+        };
+        file("dead_letters")
+            .save(&BTreeMap::from([(*letter.id(), letter)]))
+            .unwrap();
+        file("pending_requests")
+            .save(&BTreeMap::from([(Id::new(), pending)]))
+            .unwrap();
+
+        let mut state = gateway(Arc::new(AtomicU32::new(5))).await;
+        state.functions = FunctionStore::open(file("functions")).unwrap();
+        state.dead_letters = DeadLetterStore::open(file("dead_letters"), 10).unwrap();
+        state.pending = PendingStore::open(file("pending_requests"), 10).unwrap();
+        for (id, pending) in state.pending.list() {
+            assert!(!complete_pending(state.clone(), id, pending).await.succeeded);
+        }
+        let letters = state.dead_letters.list(Some("add"));
+        assert_eq!(letters.len(), 1);
+        assert_eq!(letters[0].attempts(), 2);
+        // the replayed letter and the request are gone once saved in the background
+        loop {
+            let saved = file("dead_letters")
+                .load::<BTreeMap<DeadLetterId, DeadLetter>>()
+                .unwrap();
+            let pending = file("pending_requests")
+                .load::<BTreeMap<Id, Pending>>()
+                .unwrap();
+            if pending.is_empty() && saved.keys().eq([letters[0].id()]) {
+                break;
             }
+            tokio::time::sleep(Duration::from_millis(1)).await;
         }
-        Err(_) => Err(StatusCode::BAD_GATEWAY),
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn routes_registered_paths_to_their_alias() {
+        let mut state = gateway(Arc::new(AtomicU32::new(0))).await;
+        let add = state.functions.get_by_name("add").unwrap();
+        let first = add.current_revision().number();
+        state.functions.modify("add", |add| {
+            add.add_revision(add.current_revision().blob_address().clone(), String::new());
+            add.set_alias("stable", first).unwrap();
+        });
+        let entry = |sub_path: &str, alias: &str| {
+            PathEntry::new(
+                Root::parse("calc").unwrap(),
+                SubPath::parse(sub_path).unwrap(),
+                "add".to_string(),
+            )
+            .with_alias(alias.to_string())
+        };
+        state.paths.insert(entry("sum", "stable"));
+        state.paths.insert(entry("gone", "removed"));
+        let request = |path: &str| {
+            proxy(
+                State(state.clone()),
+                Path(FunctionPath {
+                    function: "calc".to_string(),
+                    path: Some(path.to_string()),
+                }),
+                RawQuery(None),
+                Method::POST,
+                HeaderMap::new(),
+                Bytes::from("[1, 2]"),
+            )
+        };
+
+        assert_eq!(request("sum").await.status(), StatusCode::OK);
+        let executions = state.executions.list(None);
+        assert_eq!(executions.len(), 1);
+        assert_eq!(executions[0].request().target_function(), add.id());
+        assert_eq!(executions[0].request().revision(), first);
+        // the alias is resolved when a request arrives
+        assert_eq!(request("gone").await.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn splits_the_traffic_of_registered_paths() {
+        let mut state = gateway(Arc::new(AtomicU32::new(0))).await;
+        let first = state
+            .functions
+            .get_by_name("add")
+            .unwrap()
+            .current_revision()
+            .number();
+        let second = state
+            .functions
+            .modify("add", |add| {
+                let canary =
+                    add.add_revision(add.current_revision().blob_address().clone(), String::new());
+                let canary = canary.number();
+                add.set_current_revision(first);
+                canary
+            })
+            .unwrap();
+        // every request to the path goes to the canary while the function's own requests stay on its current revision
+        let split = TrafficSplit {
+            revisions: vec![
+                WeightedRevision {
+                    revision: first,
+                    weight: 0,
+                },
+                WeightedRevision {
+                    revision: second,
+                    weight: 1,
+                },
+            ],
+            mode: SplitMode::Random,
+            auto_rollback: None,
+        };
+        state.paths.insert(
+            PathEntry::new(
+                Root::parse("canary").unwrap(),
+                SubPath::default(),
+                "add".to_string(),
+            )
+            .with_traffic(split),
+        );
+        for function in ["canary", "add"] {
+            let response = proxy(
+                State(state.clone()),
+                Path(FunctionPath {
+                    function: function.to_string(),
+                    path: None,
+                }),
+                RawQuery(None),
+                Method::POST,
+                HeaderMap::new(),
+                Bytes::from("[1, 2]"),
+            )
+            .await;
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+        let mut revisions = state
+            .executions
+            .list(None)
+            .iter()
+            .map(|e| e.request().revision())
+            .collect::<Vec<_>>();
+        revisions.sort();
+        assert_eq!(revisions, [first, second]);
+    }
+
+    #[test]
+    fn async_requests_drop_credentials() {
+        let mut invocation = Invocation::json("add", &Value::Null, Trigger::Http);
+        invocation
+            .headers
+            .insert(header::AUTHORIZATION, HeaderValue::from_static("Bearer x"));
+        invocation
+            .headers
+            .insert(header::COOKIE, HeaderValue::from_static("session=x"));
+        let names = invocation
+            .into_async()
+            .headers
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["content-type"]);
     }
 }
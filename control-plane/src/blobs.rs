@@ -0,0 +1,100 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use api::types::BlobAddress;
+use axum::{
+    body::Bytes,
+    extract::{self, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use sha2::{Digest, Sha256};
+
+const WASM_MAGIC: &[u8] = b"\0asm";
+
+/// Content addressed storage for uploaded wasm, blobs are stored as `<dir>/<sha256>.wasm` or kept in memory
+#[derive(Clone)]
+pub struct BlobStore {
+    backend: Backend,
+}
+
+#[derive(Clone)]
+enum Backend {
+    Filesystem(PathBuf),
+    Memory(Arc<Mutex<BTreeMap<String, Vec<u8>>>>),
+}
+
+impl BlobStore {
+    pub fn open(dir: &Path) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        Ok(BlobStore {
+            backend: Backend::Filesystem(dir.to_path_buf()),
+        })
+    }
+    pub fn memory() -> Self {
+        BlobStore {
+            backend: Backend::Memory(Arc::default()),
+        }
+    }
+
+    /// Store the wasm module or component, rejecting anything else
+    pub fn put(&self, wasm: &[u8]) -> Result<BlobAddress, (StatusCode, String)> {
+        if !wasm.starts_with(WASM_MAGIC) {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "not a wasm module or component".to_string(),
+            ));
+        }
+        let digest = format!("{:x}", Sha256::digest(wasm));
+        let dir = match &self.backend {
+            Backend::Filesystem(dir) => dir,
+            Backend::Memory(blobs) => {
+                blobs.lock().unwrap().insert(digest.clone(), wasm.to_vec());
+                return Ok(BlobAddress::sha256(&digest));
+            }
+        };
+        let path = dir.join(format!("{digest}.wasm"));
+        if !path.exists() {
+            // blobs are immutable so a concurrent upload of the same content writes the same bytes
+            let tmp = path.with_extension(format!("tmp.{}", std::process::id()));
+            std::fs::write(&tmp, wasm)
+                .and_then(|()| std::fs::rename(&tmp, &path))
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        }
+        Ok(BlobAddress::sha256(&digest))
+    }
+
+    pub fn get(&self, address: &BlobAddress) -> Option<Vec<u8>> {
+        // only accept digests so that an address can't escape the blob directory
+        let digest = address
+            .digest()
+            .filter(|digest| digest.len() == 64 && digest.bytes().all(|b| b.is_ascii_hexdigit()))?;
+        match &self.backend {
+            Backend::Filesystem(dir) => std::fs::read(dir.join(format!("{digest}.wasm"))).ok(),
+            Backend::Memory(blobs) => blobs.lock().unwrap().get(digest).cloned(),
+        }
+    }
+}
+
+#[tracing::instrument(skip(blobs, wasm))]
+pub async fn upload_blob(
+    State(blobs): State<BlobStore>,
+    wasm: Bytes,
+) -> Result<Json<BlobAddress>, (StatusCode, String)> {
+    blobs.put(&wasm).map(Json)
+}
+
+#[tracing::instrument(skip(blobs))]
+pub async fn get_blob(
+    State(blobs): State<BlobStore>,
+    extract::Path(address): extract::Path<String>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let wasm = blobs
+        .get(&BlobAddress::from(address))
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(([(header::CONTENT_TYPE, "application/wasm")], wasm))
+}
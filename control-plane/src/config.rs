@@ -0,0 +1,124 @@
+use serde::{Deserialize, Serialize};
+use settings::ServerConfig;
+
+/// Prefix of the environment variables overriding the configuration, e.g. `WASI_FAAS_CP_SERVER__LISTEN_ADDRESS`
+pub const ENV_PREFIX: &str = "WASI_FAAS_CP_";
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub storage: StorageConfig,
+    pub gateway: GatewayConfig,
+    pub queue: QueueConfig,
+    pub webhooks: WebhooksConfig,
+    pub limits: LimitsConfig,
+    pub retention: RetentionConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            server: ServerConfig::new("127.0.0.1:3000"),
+            storage: StorageConfig {
+                backend: StorageBackend::Filesystem,
+                dir: "data/blobs".to_string(),
+                state_dir: "data/state".to_string(),
+            },
+            gateway: GatewayConfig {
+                timeout_ms: 30_000,
+                worker_ca: None,
+            },
+            queue: QueueConfig {
+                max_depth: 100,
+                max_wait_ms: 10_000,
+                max_async: 10_000,
+            },
+            webhooks: WebhooksConfig {
+                dedupe_window_ms: 24 * 60 * 60 * 1000,
+                secret_key_file: "data/webhooks.key".to_string(),
+            },
+            limits: LimitsConfig {
+                max_blob_size: 64 * 1024 * 1024,
+                max_request_body: 2 * 1024 * 1024,
+            },
+            retention: RetentionConfig {
+                executions: 10_000,
+                dead_letters: 1_000,
+                workflow_executions: 1_000,
+                pipeline_executions: 1_000,
+            },
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageBackend {
+    /// Blobs are stored under `dir` and triggers under `state_dir`
+    Filesystem,
+    /// Blobs and triggers are lost when the control plane exits, e.g. for tests
+    Memory,
+}
+
+/// Where uploaded wasm and the state kept across restarts are stored
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct StorageConfig {
+    pub backend: StorageBackend,
+    pub dir: String,
+    pub state_dir: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct GatewayConfig {
+    /// How long a worker has to accept a request and to start responding to it, executions outlasting it being turned
+    /// into a 504, while the response's body can take as long as it needs
+    pub timeout_ms: u64,
+    /// PEM file of the certificate authority of workers serving HTTPS with a private certificate
+    pub worker_ca: Option<String>,
+}
+
+/// Requests arriving while no worker is available wait for one in their function's queue
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct QueueConfig {
+    /// Requests waiting per function, 0 turns requests away right away
+    pub max_depth: usize,
+    pub max_wait_ms: u64,
+    /// Asynchronous requests being invoked or retried at once, further ones being turned away
+    pub max_async: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct WebhooksConfig {
+    /// How long the delivery ids of a webhook are remembered to ignore redeliveries
+    pub dedupe_window_ms: u64,
+    /// The key webhook secrets are encrypted with in `storage.state_dir`, generated if it doesn't exist
+    pub secret_key_file: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct LimitsConfig {
+    /// Largest wasm upload in bytes
+    pub max_blob_size: usize,
+    /// Largest request body the gateway accepts in bytes
+    pub max_request_body: usize,
+}
+
+/// How many records are kept in memory, the oldest ones being dropped first
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct RetentionConfig {
+    /// Executions of functions, including the runs of cron triggers and webhooks
+    pub executions: usize,
+    /// Dead letters of asynchronous requests
+    pub dead_letters: usize,
+    /// Completed workflow executions, the running ones being all kept
+    pub workflow_executions: usize,
+    /// Pipeline executions
+    pub pipeline_executions: usize,
+}
@@ -0,0 +1,651 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use api::{
+    function::execution::Trigger,
+    trigger::{CronRun, CronRunStatus, CronSpec, CronTrigger, OverlapPolicy},
+    types::TimeStamp,
+};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use cron::Schedule;
+use serde_json::Value;
+use tokio::time::MissedTickBehavior;
+
+use crate::{
+    api_gateway::{self, GatewayState, Invocation},
+    functions::FunctionStore,
+    metrics,
+    state::StateFile,
+};
+
+/// How often the scheduler looks for due runs, which bounds how late a run starts
+const TICK: Duration = Duration::from_secs(1);
+/// Runs of a trigger with the `queue` overlap policy waiting for the previous run, further runs are skipped
+const MAX_QUEUED_RUNS: usize = 10;
+/// Runs kept per trigger, the oldest ones being dropped first
+const MAX_RUNS: usize = 100;
+
+/// A trigger's cron expression evaluated in its timezone
+pub struct CronSchedule {
+    schedule: Schedule,
+    timezone: Tz,
+}
+
+impl CronSchedule {
+    /// Standard 5 field expressions are accepted as well as ones with a leading seconds field and a trailing year. Days
+    /// of the week are numbered as in standard cron, from 0 for Sunday to 6 for Saturday with 7 for Sunday too.
+    pub fn parse(expression: &str, timezone: &str) -> Result<Self, String> {
+        let mut fields = expression.split_whitespace().collect::<Vec<_>>();
+        if fields.len() == 5 {
+            fields.insert(0, "0");
+        }
+        let day_of_week = match fields.get(5) {
+            Some(field) => day_of_week(field)?,
+            None => return Err(format!("invalid schedule: {expression}")),
+        };
+        fields[5] = &day_of_week;
+        let schedule =
+            Schedule::from_str(&fields.join(" ")).map_err(|e| format!("invalid schedule: {e}"))?;
+        let timezone = timezone
+            .parse::<Tz>()
+            .map_err(|e| format!("invalid timezone: {e}"))?;
+        Ok(CronSchedule { schedule, timezone })
+    }
+    /// The scheduled times strictly after `time`
+    fn after(&self, time: &DateTime<Utc>) -> impl Iterator<Item = DateTime<Utc>> + '_ {
+        self.schedule
+            .after(&time.with_timezone(&self.timezone))
+            .map(|time| time.with_timezone(&Utc))
+    }
+    pub fn next(&self, time: &DateTime<Utc>) -> Option<TimeStamp> {
+        self.after(time).next().map(TimeStamp::from)
+    }
+}
+
+const DAYS: [&str; 7] = ["SUN", "MON", "TUE", "WED", "THU", "FRI", "SAT"];
+
+/// Translate the numbers of a day of the week field from standard cron, where Sunday is 0 or 7, to day names since the
+/// `cron` crate numbers days from 1 for Sunday. Ranges and steps are expanded to the days they cover, named days are
+/// left as is.
+fn day_of_week(field: &str) -> Result<String, String> {
+    let invalid = || format!("invalid day of the week: {field}");
+    let mut days = Vec::new();
+    for element in field.split(',') {
+        let (range, step) = match element.split_once('/') {
+            Some((range, step)) => (range, Some(step.parse::<usize>().map_err(|_| invalid())?)),
+            None => (element, None),
+        };
+        let bounds = match range.split_once('-') {
+            _ if range == "*" || range == "?" => step.map(|_| (0, 6)),
+            Some((start, end)) => match (start.parse::<usize>(), end.parse::<usize>()) {
+                (Ok(start), Ok(end)) => Some((start, end)),
+                (Err(_), Err(_)) => None,
+                _ => return Err(invalid()),
+            },
+            // a single day with a step covers the days from it to Saturday
+            None => range
+                .parse::<usize>()
+                .ok()
+                .map(|start| (start, if step.is_some() { 6 } else { start })),
+        };
+        let Some((start, end)) = bounds else {
+            days.push(element.to_string());
+            continue;
+        };
+        if start > end || end > 7 || step == Some(0) {
+            return Err(invalid());
+        }
+        for day in (start..=end).step_by(step.unwrap_or(1)) {
+            let day = DAYS[day % 7].to_string();
+            if !days.contains(&day) {
+                days.push(day);
+            }
+        }
+    }
+    Ok(days.join(","))
+}
+
+/// The cron triggers and their most recent runs, saved on every change so that their schedules carry on from their
+/// last run after a restart and the runs it interrupted can be started again
+#[derive(Clone)]
+pub struct CronStore {
+    inner: Arc<Mutex<BTreeMap<String, CronTrigger>>>,
+    state: StateFile,
+    runs: Arc<Mutex<BTreeMap<String, VecDeque<CronRun>>>>,
+    runs_state: StateFile,
+}
+
+impl CronStore {
+    pub fn open(state: StateFile, runs_state: StateFile) -> anyhow::Result<Self> {
+        Ok(CronStore {
+            inner: Arc::new(Mutex::new(state.load()?)),
+            state,
+            runs: Arc::new(Mutex::new(runs_state.load()?)),
+            runs_state,
+        })
+    }
+    /// Insert the trigger unless another one is already registered under the same name
+    pub fn insert(&mut self, trigger: CronTrigger) -> Option<CronTrigger> {
+        let mut triggers = self.inner.lock().unwrap();
+        if triggers.contains_key(trigger.name()) {
+            return None;
+        }
+        triggers.insert(trigger.name().to_string(), trigger.clone());
+        self.save(&triggers);
+        Some(trigger)
+    }
+    pub fn list(&self) -> Vec<CronTrigger> {
+        self.inner.lock().unwrap().values().cloned().collect()
+    }
+    pub fn get(&self, name: &str) -> Option<CronTrigger> {
+        self.inner.lock().unwrap().get(name).cloned()
+    }
+    pub fn remove(&mut self, name: &str) -> Option<CronTrigger> {
+        let mut triggers = self.inner.lock().unwrap();
+        let trigger = triggers.remove(name)?;
+        self.save(&triggers);
+        let mut runs = self.runs.lock().unwrap();
+        if runs.remove(name).is_some() {
+            self.save_runs(&runs);
+        }
+        Some(trigger)
+    }
+    /// The trigger's runs, oldest first
+    pub fn runs(&self, name: &str) -> Vec<CronRun> {
+        self.runs
+            .lock()
+            .unwrap()
+            .get(name)
+            .map(|runs| runs.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+    /// The scheduled times of the runs which were queued or running, by trigger
+    pub fn pending_runs(&self) -> Vec<(String, DateTime<Utc>)> {
+        let runs = self.runs.lock().unwrap();
+        runs.iter()
+            .flat_map(|(name, runs)| {
+                runs.iter()
+                    .filter(|run| run.is_pending())
+                    .map(|run| (name.clone(), *run.scheduled_time().as_datetime()))
+            })
+            .collect()
+    }
+    /// Record a run of the trigger, replacing the one scheduled at the same time, only the most recent
+    /// `MAX_RUNS` of the trigger being kept
+    pub fn record_run(&mut self, name: &str, run: CronRun) {
+        let mut runs = self.runs.lock().unwrap();
+        let trigger_runs = runs.entry(name.to_string()).or_default();
+        match trigger_runs
+            .iter_mut()
+            .rev()
+            .find(|r| r.scheduled_time() == run.scheduled_time())
+        {
+            Some(recorded) => *recorded = run,
+            None => trigger_runs.push_back(run),
+        }
+        while trigger_runs.len() > MAX_RUNS {
+            trigger_runs.pop_front();
+        }
+        self.save_runs(&runs);
+    }
+    /// Update the trigger's run scheduled at the given time, unless the trigger was removed in the meantime
+    pub fn update_run(
+        &mut self,
+        name: &str,
+        scheduled: &TimeStamp,
+        update: impl FnOnce(&mut CronRun),
+    ) {
+        let mut runs = self.runs.lock().unwrap();
+        let run = runs.get_mut(name).and_then(|runs| {
+            runs.iter_mut()
+                .rev()
+                .find(|r| r.scheduled_time() == scheduled)
+        });
+        if let Some(run) = run {
+            update(run);
+            self.save_runs(&runs);
+        }
+    }
+    /// Record the latest scheduled run of the trigger, unless it was removed in the meantime
+    pub fn advance(&mut self, name: &str, last_scheduled: TimeStamp, next_run: Option<TimeStamp>) {
+        let mut triggers = self.inner.lock().unwrap();
+        if let Some(trigger) = triggers.get_mut(name) {
+            trigger.advance(last_scheduled, next_run);
+            self.save(&triggers);
+        }
+    }
+    fn save(&self, triggers: &BTreeMap<String, CronTrigger>) {
+        if let Err(e) = self.state.save(triggers) {
+            tracing::error!(error = ?e, "failed to save the cron triggers");
+        }
+    }
+    fn save_runs(&self, runs: &BTreeMap<String, VecDeque<CronRun>>) {
+        if let Err(e) = self.runs_state.save(runs) {
+            tracing::error!(error = ?e, "failed to save the cron runs");
+        }
+    }
+}
+
+fn parse(trigger: &CronTrigger) -> Parsed {
+    let schedule = CronSchedule::parse(trigger.schedule(), trigger.timezone())
+        .inspect_err(|e| tracing::error!(trigger = trigger.name(), error = e, "skipping trigger"))
+        .ok();
+    Parsed {
+        create_time: trigger.create_time().clone(),
+        schedule,
+    }
+}
+
+/// The runs of a trigger which fell due at a tick
+#[derive(Default, Debug)]
+struct Due {
+    start: VecDeque<DateTime<Utc>>,
+    missed: usize,
+    last: Option<DateTime<Utc>>,
+}
+
+/// The runs of the trigger scheduled after its last run and up to `now`. After a restart they were missed while the
+/// control plane was down and only the most recent `catch_up` of them are started.
+fn due(
+    trigger: &CronTrigger,
+    schedule: &CronSchedule,
+    now: &DateTime<Utc>,
+    restarted: bool,
+) -> Due {
+    let from = trigger
+        .last_scheduled()
+        .unwrap_or(trigger.create_time())
+        .as_datetime();
+    let keep = if restarted {
+        trigger.catch_up() as usize
+    } else {
+        usize::MAX
+    };
+    let mut due = Due::default();
+    for time in schedule.after(from).take_while(|time| time <= now) {
+        due.last = Some(time);
+        due.start.push_back(time);
+        if due.start.len() > keep {
+            due.start.pop_front();
+            due.missed += 1;
+        }
+    }
+    due
+}
+
+/// Runs in progress and waiting for them under the `queue` overlap policy
+#[derive(Default)]
+struct Runs {
+    running: usize,
+    queued: VecDeque<DateTime<Utc>>,
+}
+
+/// A trigger's parsed schedule, parsed again if the trigger was replaced by one under the same name
+struct Parsed {
+    create_time: TimeStamp,
+    schedule: Option<CronSchedule>,
+}
+
+/// Invokes the functions of the cron triggers through the gateway as their runs fall due
+#[derive(Clone)]
+pub struct Scheduler {
+    triggers: CronStore,
+    gateway: GatewayState,
+    runs: Arc<Mutex<BTreeMap<String, Runs>>>,
+}
+
+impl Scheduler {
+    pub fn new(triggers: CronStore, gateway: GatewayState) -> Self {
+        Scheduler {
+            triggers,
+            gateway,
+            runs: Arc::default(),
+        }
+    }
+
+    /// Start the due runs every tick. The first one starts the runs a previous control plane left queued or running
+    /// again and catches up on the runs missed while it was down.
+    pub async fn run(mut self) {
+        let mut interval = tokio::time::interval(TICK);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        let mut schedules = BTreeMap::new();
+        interval.tick().await;
+        for (name, scheduled) in self.triggers.pending_runs() {
+            if let Some(trigger) = self.triggers.get(&name) {
+                tracing::info!(
+                    trigger = name,
+                    "starting a run interrupted by a restart again"
+                );
+                self.start(&trigger, scheduled);
+            }
+        }
+        let mut restarted = true;
+        loop {
+            self.tick(&mut schedules, &Utc::now(), std::mem::take(&mut restarted));
+            interval.tick().await;
+        }
+    }
+
+    fn tick(
+        &mut self,
+        schedules: &mut BTreeMap<String, Parsed>,
+        now: &DateTime<Utc>,
+        restarted: bool,
+    ) {
+        let triggers = self.triggers.list();
+        schedules.retain(|name, _| triggers.iter().any(|t| t.name() == name));
+        for trigger in triggers {
+            let parsed = schedules
+                .entry(trigger.name().to_string())
+                .or_insert_with(|| parse(&trigger));
+            if parsed.create_time != *trigger.create_time() {
+                *parsed = parse(&trigger);
+            }
+            let Some(schedule) = &parsed.schedule else {
+                continue;
+            };
+            let due = due(&trigger, schedule, now, restarted);
+            let Some(last) = due.last else {
+                continue;
+            };
+            if due.missed > 0 {
+                tracing::warn!(
+                    trigger = trigger.name(),
+                    missed = due.missed,
+                    "skipping runs missed while the control plane was down"
+                );
+                metrics::record_cron_runs(trigger.name(), "missed", due.missed);
+            }
+            self.triggers
+                .advance(trigger.name(), last.into(), schedule.next(now));
+            for scheduled in due.start {
+                self.start(&trigger, scheduled);
+            }
+        }
+    }
+
+    /// Start the run unless its overlap policy holds it back while the previous run is executing
+    fn start(&mut self, trigger: &CronTrigger, scheduled: DateTime<Utc>) {
+        let held = {
+            let mut runs = self.runs.lock().unwrap();
+            let runs = runs.entry(trigger.name().to_string()).or_default();
+            let held = match trigger.overlap() {
+                _ if runs.running == 0 => None,
+                OverlapPolicy::Skip => {
+                    tracing::info!(
+                        trigger = trigger.name(),
+                        "skipping run, the previous one is still executing"
+                    );
+                    Some(CronRunStatus::Skipped)
+                }
+                OverlapPolicy::Queue if runs.queued.len() < MAX_QUEUED_RUNS => {
+                    runs.queued.push_back(scheduled);
+                    Some(CronRunStatus::Queued)
+                }
+                OverlapPolicy::Queue => {
+                    tracing::warn!(
+                        trigger = trigger.name(),
+                        "skipping run, too many runs are queued"
+                    );
+                    Some(CronRunStatus::Skipped)
+                }
+                OverlapPolicy::Allow => None,
+            };
+            if held.is_none() {
+                runs.running += 1;
+            }
+            held
+        };
+        match held {
+            Some(status) => {
+                let outcome = match status {
+                    CronRunStatus::Queued => "queued",
+                    _ => "skipped",
+                };
+                metrics::record_cron_runs(trigger.name(), outcome, 1);
+                self.triggers
+                    .record_run(trigger.name(), CronRun::new(scheduled.into(), status));
+            }
+            None => {
+                self.triggers.record_run(
+                    trigger.name(),
+                    CronRun::new(scheduled.into(), CronRunStatus::Queued),
+                );
+                tokio::spawn(self.clone().execute(trigger.clone(), scheduled));
+            }
+        }
+    }
+
+    /// Invoke the function for the scheduled run, then for the runs queued behind it unless the trigger was removed
+    async fn execute(self, trigger: CronTrigger, scheduled: DateTime<Utc>) {
+        let mut next = Some(scheduled);
+        while let Some(scheduled) = next {
+            self.invoke(&trigger, scheduled).await;
+            let mut runs = self.runs.lock().unwrap();
+            let runs = runs.entry(trigger.name().to_string()).or_default();
+            if self.triggers.get(trigger.name()).is_none() {
+                runs.queued.clear();
+            }
+            next = runs.queued.pop_front();
+            if next.is_none() {
+                runs.running -= 1;
+            }
+        }
+    }
+
+    async fn invoke(&self, trigger: &CronTrigger, scheduled: DateTime<Utc>) {
+        let mut triggers = self.triggers.clone();
+        let scheduled_time = TimeStamp::from(scheduled);
+        triggers.update_run(trigger.name(), &scheduled_time, CronRun::start);
+        let input = trigger.input().map_or(Value::Null, |i| i.value().clone());
+        let cause = Trigger::Cron {
+            trigger: trigger.name().to_string(),
+            scheduled_time: scheduled_time.clone(),
+        };
+        let invocation = Invocation::json(trigger.function(), &input, cause);
+        // the run lasts until the function has succeeded or its retries have given up, so the overlap policy applies to
+        // the runs due during the retries. Runs aren't pending requests of the gateway since the scheduler restarts the
+        // unfinished ones itself.
+        let outcome =
+            api_gateway::invoke_async(self.gateway.clone(), invocation.into_async()).await;
+        if outcome.succeeded {
+            tracing::info!(trigger = trigger.name(), "cron run completed");
+            metrics::record_cron_runs(trigger.name(), "succeeded", 1);
+        } else {
+            tracing::warn!(trigger = trigger.name(), "cron run failed");
+            metrics::record_cron_runs(trigger.name(), "failed", 1);
+        }
+        triggers.update_run(trigger.name(), &scheduled_time, |run| {
+            run.complete(outcome.succeeded, outcome.executions)
+        });
+    }
+}
+
+#[tracing::instrument(skip(triggers))]
+pub async fn list_cron_triggers(State(triggers): State<CronStore>) -> Json<Vec<CronTrigger>> {
+    Json(triggers.list())
+}
+
+#[tracing::instrument(skip(triggers, functions))]
+pub async fn create_cron_trigger(
+    State((mut triggers, functions)): State<(CronStore, FunctionStore)>,
+    Json(spec): Json<CronSpec>,
+) -> Result<Json<CronTrigger>, (StatusCode, String)> {
+    let schedule = CronSchedule::parse(&spec.schedule, &spec.timezone)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let function = spec
+        .function
+        .split_once('@')
+        .map_or(spec.function.as_str(), |(name, _)| name);
+    if functions.get_by_name(function).is_none() {
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!("function {function} not found"),
+        ));
+    }
+    let name = spec.name.clone();
+    let trigger = CronTrigger::new(spec, schedule.next(&Utc::now()));
+    triggers.insert(trigger).map(Json).ok_or((
+        StatusCode::CONFLICT,
+        format!("cron trigger {name} already exists"),
+    ))
+}
+
+#[tracing::instrument(skip(triggers))]
+pub async fn get_cron_trigger(
+    State(triggers): State<CronStore>,
+    Path(name): Path<String>,
+) -> Result<Json<CronTrigger>, StatusCode> {
+    triggers.get(&name).map(Json).ok_or(StatusCode::NOT_FOUND)
+}
+
+#[tracing::instrument(skip(triggers))]
+pub async fn delete_cron_trigger(
+    State(mut triggers): State<CronStore>,
+    Path(name): Path<String>,
+) -> Result<Json<CronTrigger>, StatusCode> {
+    triggers
+        .remove(&name)
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// The most recent runs of the trigger, oldest first
+#[tracing::instrument(skip(triggers))]
+pub async fn list_cron_runs(
+    State(triggers): State<CronStore>,
+    Path(name): Path<String>,
+) -> Result<Json<Vec<CronRun>>, StatusCode> {
+    triggers.get(&name).ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(triggers.runs(&name)))
+}
+
+#[cfg(test)]
+mod tests {
+    use api::types::JsonData;
+
+    use super::*;
+
+    fn trigger(schedule: &str, catch_up: u32) -> CronTrigger {
+        CronTrigger::new(
+            CronSpec {
+                name: "nightly".to_string(),
+                function: "add".to_string(),
+                schedule: schedule.to_string(),
+                timezone: "Europe/Paris".to_string(),
+                input: Some(JsonData::from(serde_json::json!([1, 2]))),
+                overlap: OverlapPolicy::Skip,
+                catch_up,
+            },
+            None,
+        )
+    }
+
+    #[test]
+    fn catches_up_after_restart() {
+        let mut nightly = trigger("30 2 * * *", 2);
+        let schedule = CronSchedule::parse(nightly.schedule(), nightly.timezone()).unwrap();
+        // 02:30 in Paris is 00:30 UTC in the summer
+        let last = "2024-07-01T00:30:00Z".parse::<DateTime<Utc>>().unwrap();
+        nightly.advance(last.into(), None);
+        let now = "2024-07-05T12:00:00Z".parse::<DateTime<Utc>>().unwrap();
+
+        let running = due(&nightly, &schedule, &now, false);
+        assert_eq!(running.start.len(), 4);
+        assert_eq!(running.missed, 0);
+
+        let restarted = due(&nightly, &schedule, &now, true);
+        assert_eq!(
+            restarted.start,
+            [
+                "2024-07-04T00:30:00Z".parse::<DateTime<Utc>>().unwrap(),
+                "2024-07-05T00:30:00Z".parse().unwrap()
+            ]
+        );
+        assert_eq!(restarted.missed, 2);
+        assert_eq!(restarted.last, restarted.start.back().copied());
+
+        assert!(CronSchedule::parse("0 9 * * MON-FRI", "Mars/Olympus").is_err());
+    }
+
+    #[test]
+    fn standard_days_of_week() {
+        assert_eq!(day_of_week("1-5").unwrap(), "MON,TUE,WED,THU,FRI");
+        assert_eq!(day_of_week("0").unwrap(), "SUN");
+        assert_eq!(day_of_week("7").unwrap(), "SUN");
+        assert_eq!(day_of_week("5-7").unwrap(), "FRI,SAT,SUN");
+        assert_eq!(day_of_week("*/2").unwrap(), "SUN,TUE,THU,SAT");
+        assert_eq!(day_of_week("0,6").unwrap(), "SUN,SAT");
+        assert_eq!(day_of_week("MON-FRI").unwrap(), "MON-FRI");
+        assert_eq!(day_of_week("*").unwrap(), "*");
+        assert!(day_of_week("8").is_err());
+        assert!(day_of_week("5-1").is_err());
+        assert!(day_of_week("1-FRI").is_err());
+
+        // 2024-07-07 is a Sunday
+        let sunday = "2024-07-07T00:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        let weekdays = CronSchedule::parse("0 9 * * 1-5", "UTC").unwrap();
+        let runs = weekdays
+            .after(&sunday)
+            .take(5)
+            .map(|time| time.format("%a").to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(runs, ["Mon", "Tue", "Wed", "Thu", "Fri"]);
+        let sundays = CronSchedule::parse("0 9 * * 0", "UTC").unwrap();
+        assert_eq!(
+            sundays.next(&sunday).unwrap(),
+            "2024-07-07T09:00:00Z"
+                .parse::<DateTime<Utc>>()
+                .unwrap()
+                .into()
+        );
+    }
+
+    #[test]
+    fn runs_persist() {
+        let dir = std::env::temp_dir().join(format!("wasi-faas-cron-{}", api::types::Id::new()));
+        let open = || {
+            CronStore::open(
+                StateFile::open(&dir, "cron").unwrap(),
+                StateFile::open(&dir, "cron_runs").unwrap(),
+            )
+            .unwrap()
+        };
+        let mut store = open();
+        store.insert(trigger("30 2 * * *", 1)).unwrap();
+        let first = "2024-07-01T00:30:00Z".parse::<DateTime<Utc>>().unwrap();
+        let scheduled = |day: usize| first + chrono::Duration::days(day as i64);
+        for day in 0..MAX_RUNS + 2 {
+            store.record_run(
+                "nightly",
+                CronRun::new(scheduled(day).into(), CronRunStatus::Queued),
+            );
+            store.update_run("nightly", &scheduled(day).into(), |run| {
+                run.complete(true, Vec::new())
+            });
+        }
+        let last = scheduled(MAX_RUNS + 2);
+        store.record_run("nightly", CronRun::new(last.into(), CronRunStatus::Queued));
+        store.update_run("nightly", &last.into(), CronRun::start);
+
+        let store = open();
+        let runs = store.runs("nightly");
+        assert_eq!(runs.len(), MAX_RUNS);
+        assert_eq!(runs[0].scheduled_time(), &scheduled(3).into());
+        assert_eq!(runs[MAX_RUNS - 2].status(), CronRunStatus::Succeeded);
+        assert_eq!(runs[MAX_RUNS - 1].status(), CronRunStatus::Running);
+        assert_eq!(store.pending_runs(), [("nightly".to_string(), last)]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,188 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+
+use api::function::dead_letter::{DeadLetter, DeadLetterId};
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api_gateway::{self, GatewayState},
+    pending::{Pending, Refused},
+    state::StateFile,
+};
+
+/// The asynchronous requests whose retries gave up, saved on every change so that they can still be replayed after a
+/// restart. Only the latest `max_retained` are kept, the oldest being dropped first.
+#[derive(Clone)]
+pub struct DeadLetterStore {
+    inner: Arc<Mutex<BTreeMap<DeadLetterId, DeadLetter>>>,
+    state: StateFile,
+    max_retained: usize,
+}
+
+impl DeadLetterStore {
+    pub fn open(state: StateFile, max_retained: usize) -> anyhow::Result<Self> {
+        Ok(DeadLetterStore {
+            inner: Arc::new(Mutex::new(state.load()?)),
+            state,
+            max_retained,
+        })
+    }
+    pub fn insert(&mut self, letter: DeadLetter) {
+        let mut letters = self.inner.lock().unwrap();
+        letters.insert(*letter.id(), letter);
+        while letters.len() > self.max_retained {
+            let oldest = letters
+                .values()
+                .min_by_key(|l| l.create_time().clone())
+                .map(|l| *l.id())
+                .expect("more dead letters than retained");
+            tracing::warn!(id = %oldest, "dropping the oldest dead letter");
+            letters.remove(&oldest);
+        }
+        self.save(&letters);
+    }
+    /// The dead letters, only the function's ones if a function is given
+    pub fn list(&self, function: Option<&str>) -> Vec<DeadLetter> {
+        self.inner
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|l| function.is_none_or(|f| l.request().function_name() == f))
+            .cloned()
+            .collect()
+    }
+    pub fn get(&self, id: &DeadLetterId) -> Option<DeadLetter> {
+        self.inner.lock().unwrap().get(id).cloned()
+    }
+    pub fn remove(&mut self, id: &DeadLetterId) -> Option<DeadLetter> {
+        let mut letters = self.inner.lock().unwrap();
+        let letter = letters.remove(id)?;
+        self.save(&letters);
+        Some(letter)
+    }
+    fn save(&self, letters: &BTreeMap<DeadLetterId, DeadLetter>) {
+        if let Err(e) = self.state.save_in_background(letters) {
+            tracing::error!(error = ?e, "failed to save the dead letters");
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DeadLetterQuery {
+    function: Option<String>,
+}
+
+/// The dead letters, oldest first
+#[tracing::instrument(skip(letters))]
+pub async fn list_dead_letters(
+    State(letters): State<DeadLetterStore>,
+    Query(query): Query<DeadLetterQuery>,
+) -> Json<Vec<DeadLetter>> {
+    let mut letters = letters.list(query.function.as_deref());
+    letters.sort_by(|a, b| a.create_time().cmp(b.create_time()));
+    Json(letters)
+}
+
+#[tracing::instrument(skip(letters))]
+pub async fn get_dead_letter(
+    State(letters): State<DeadLetterStore>,
+    Path(id): Path<DeadLetterId>,
+) -> Result<Json<DeadLetter>, StatusCode> {
+    letters.get(&id).map(Json).ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Invoke the request again in the background with a fresh round of attempts, the dead letter is kept until they are
+/// done and replaced by a new one if they fail too. A dead letter being replayed can't be replayed again until then.
+#[tracing::instrument(skip(gateway))]
+pub async fn replay_dead_letter(
+    State(gateway): State<GatewayState>,
+    Path(id): Path<DeadLetterId>,
+) -> Result<(StatusCode, Json<DeadLetter>), StatusCode> {
+    let letter = gateway.dead_letters.get(&id).ok_or(StatusCode::NOT_FOUND)?;
+    let pending = Pending {
+        request: letter.request().clone(),
+        replaying: Some(id),
+    };
+    match api_gateway::spawn_async(gateway, pending) {
+        Ok(_) => Ok((StatusCode::ACCEPTED, Json(letter))),
+        Err(Refused::Replaying) => Err(StatusCode::CONFLICT),
+        Err(Refused::Full) => Err(StatusCode::SERVICE_UNAVAILABLE),
+    }
+}
+
+#[tracing::instrument(skip(letters))]
+pub async fn discard_dead_letter(
+    State(mut letters): State<DeadLetterStore>,
+    Path(id): Path<DeadLetterId>,
+) -> Result<Json<DeadLetter>, StatusCode> {
+    letters.remove(&id).map(Json).ok_or(StatusCode::NOT_FOUND)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicU32, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use super::*;
+    use crate::api_gateway::tests::{gateway, request};
+
+    #[tokio::test]
+    async fn replays_and_discards() {
+        // both requests fail all three attempts, the replay succeeds
+        let failures = Arc::new(AtomicU32::new(6));
+        let state = gateway(failures.clone()).await;
+        for _ in 0..2 {
+            api_gateway::invoke_async(state.clone(), request()).await;
+        }
+        let letters = state.dead_letters.list(None);
+        assert_eq!(letters.len(), 2);
+
+        let (id, other) = (*letters[0].id(), *letters[1].id());
+        let replayed = replay_dead_letter(State(state.clone()), Path(id)).await;
+        assert_eq!(replayed.unwrap().0, StatusCode::ACCEPTED);
+        // the dead letter is kept while it is replayed, and can't be replayed twice
+        assert!(state.dead_letters.get(&id).is_some());
+        let twice = replay_dead_letter(State(state.clone()), Path(id)).await;
+        assert_eq!(twice.unwrap_err(), StatusCode::CONFLICT);
+        while !state.pending.list().is_empty() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(failures.load(Ordering::SeqCst), 0);
+        assert!(state.dead_letters.get(&id).is_none());
+
+        let discarded = discard_dead_letter(State(state.dead_letters.clone()), Path(other)).await;
+        assert_eq!(discarded.unwrap().id(), &other);
+        assert!(state.dead_letters.list(None).is_empty());
+        let missing = discard_dead_letter(State(state.dead_letters.clone()), Path(other)).await;
+        assert_eq!(missing.unwrap_err(), StatusCode::NOT_FOUND);
+        let missing = replay_dead_letter(State(state.clone()), Path(id)).await;
+        assert_eq!(missing.unwrap_err(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn keeps_the_latest_dead_letters() {
+        let mut letters = DeadLetterStore::open(StateFile::memory(), 2).unwrap();
+        let ids = (0..3)
+            .map(|_| {
+                let letter = DeadLetter::new(request(), 1, None, None, "failed".to_string());
+                let id = *letter.id();
+                letters.insert(letter);
+                id
+            })
+            .collect::<Vec<_>>();
+        assert!(letters.get(&ids[0]).is_none());
+        assert!(letters.get(&ids[1]).is_some() && letters.get(&ids[2]).is_some());
+    }
+}
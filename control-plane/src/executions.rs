@@ -0,0 +1,268 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use api::{
+    function::{
+        execution::{Execution, ExecutionId, ExecutionResult, ExecutionStatus},
+        registration::FunctionId,
+    },
+    types::TimeStamp,
+};
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+
+use crate::{functions::FunctionStore, traffic::RollbackMonitor};
+
+struct Executions {
+    by_id: BTreeMap<ExecutionId, Execution>,
+    /// Ids in the order the executions were inserted, the oldest first
+    order: VecDeque<ExecutionId>,
+    /// The sequence number given to the next result stored
+    next_sequence: u64,
+}
+
+impl Default for Executions {
+    /// Sequence numbers start from the current time in microseconds so that they keep growing across restarts
+    fn default() -> Self {
+        let now = TimeStamp::now().as_datetime().timestamp_micros();
+        Executions {
+            by_id: BTreeMap::new(),
+            order: VecDeque::new(),
+            next_sequence: u64::try_from(now).unwrap_or_default(),
+        }
+    }
+}
+
+/// The most recent executions, the oldest ones being dropped once more than `max_retained` are kept
+#[derive(Clone)]
+pub struct ExecutionStore {
+    inner: Arc<Mutex<Executions>>,
+    max_retained: usize,
+    /// Signalled whenever a worker reports the result of an execution
+    completed: Arc<watch::Sender<()>>,
+}
+
+impl ExecutionStore {
+    pub fn new(max_retained: usize) -> Self {
+        ExecutionStore {
+            inner: Arc::new(Mutex::new(Executions::default())),
+            max_retained: max_retained.max(1),
+            completed: Arc::new(watch::channel(()).0),
+        }
+    }
+    pub fn insert(&mut self, execution: Execution) {
+        let mut executions = self.inner.lock().unwrap();
+        executions.order.push_back(*execution.id());
+        executions.by_id.insert(*execution.id(), execution);
+        while executions.order.len() > self.max_retained {
+            if let Some(oldest) = executions.order.pop_front() {
+                executions.by_id.remove(&oldest);
+            }
+        }
+    }
+    pub fn list(&self, function: Option<&FunctionId>) -> Vec<Execution> {
+        self.inner
+            .lock()
+            .unwrap()
+            .by_id
+            .values()
+            .filter(|e| function.is_none_or(|f| e.request().target_function() == f))
+            .cloned()
+            .collect()
+    }
+    /// The executions whose result was stored after the one with sequence number `after`, in the order they were
+    pub fn completed_after(&self, function: Option<&FunctionId>, after: u64) -> Vec<Execution> {
+        let mut executions = self
+            .inner
+            .lock()
+            .unwrap()
+            .by_id
+            .values()
+            .filter(|e| function.is_none_or(|f| e.request().target_function() == f))
+            .filter(|e| e.sequence().is_some_and(|sequence| sequence > after))
+            .cloned()
+            .collect::<Vec<_>>();
+        executions.sort_by_key(Execution::sequence);
+        executions
+    }
+    pub fn get(&self, id: &ExecutionId) -> Option<Execution> {
+        self.inner.lock().unwrap().by_id.get(id).cloned()
+    }
+    pub fn update_status(&mut self, id: &ExecutionId, status: ExecutionStatus) {
+        if let Some(entry) = self.inner.lock().unwrap().by_id.get_mut(id) {
+            entry.update_status(status);
+        }
+    }
+    pub fn complete(&mut self, id: &ExecutionId, result: ExecutionResult) -> Option<Execution> {
+        let mut executions = self.inner.lock().unwrap();
+        let sequence = executions.next_sequence;
+        let entry = executions.by_id.get_mut(id)?;
+        entry.complete(result);
+        entry.set_sequence(sequence);
+        let completed = entry.clone();
+        executions.next_sequence += 1;
+        self.completed.send_replace(());
+        Some(completed)
+    }
+    /// The result of the execution once the worker has reported it, or `None` if it doesn't within `timeout`
+    pub async fn wait_result(
+        &self,
+        id: &ExecutionId,
+        timeout: Duration,
+    ) -> Option<ExecutionResult> {
+        let mut completed = self.completed.subscribe();
+        let wait = async {
+            loop {
+                if let Some(result) = self.get(id).and_then(|e| e.result().cloned()) {
+                    return Some(result);
+                }
+                completed.changed().await.ok()?;
+            }
+        };
+        tokio::time::timeout(timeout, wait).await.ok().flatten()
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ExecutionQuery {
+    function: Option<FunctionId>,
+    /// Only list the executions whose result was stored after the one with this sequence number, e.g. to follow logs
+    after: Option<u64>,
+}
+
+#[tracing::instrument(skip(store))]
+pub async fn list_executions(
+    State(store): State<ExecutionStore>,
+    Query(query): Query<ExecutionQuery>,
+) -> Json<Vec<Execution>> {
+    Json(match query.after {
+        Some(after) => store.completed_after(query.function.as_ref(), after),
+        None => store.list(query.function.as_ref()),
+    })
+}
+
+#[tracing::instrument(skip(store))]
+pub async fn get_execution(
+    State(store): State<ExecutionStore>,
+    Path(id): Path<ExecutionId>,
+) -> Result<Json<Execution>, StatusCode> {
+    store.get(&id).map(Json).ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Called by workers once an execution has finished, a failing canary revision may be rolled back as a result
+#[tracing::instrument(skip(store, functions, rollbacks, result))]
+pub async fn complete_execution(
+    State((mut store, mut functions, rollbacks)): State<(
+        ExecutionStore,
+        FunctionStore,
+        RollbackMonitor,
+    )>,
+    Path(id): Path<ExecutionId>,
+    Json(result): Json<ExecutionResult>,
+) -> Result<Json<Execution>, StatusCode> {
+    let execution = store.complete(&id, result).ok_or(StatusCode::NOT_FOUND)?;
+    rollbacks.record(&mut functions, &execution);
+    Ok(Json(execution))
+}
+
+#[cfg(test)]
+mod tests {
+    use api::{
+        function::{
+            execution::{ExecutionRequest, Trigger},
+            registration::Function,
+        },
+        types::{ExitKind, Id},
+        worker::WorkerId,
+    };
+
+    use super::*;
+    use crate::functions;
+
+    #[test]
+    fn lists_executions_completed_after() {
+        let function = Function::new(functions::samples().remove(0));
+        let mut store = ExecutionStore::new(10);
+        let started = (0..3)
+            .map(|_| {
+                let execution = Execution::new(ExecutionRequest::new(
+                    *function.id(),
+                    function.current_revision().number(),
+                    None,
+                    Trigger::Http,
+                ));
+                store.insert(execution.clone());
+                execution
+            })
+            .collect::<Vec<_>>();
+        let worker = WorkerId::parse(&Id::new().to_string()).unwrap();
+        // the worker's clock may be behind, results are ordered as the control plane stores them
+        let complete_time = TimeStamp::now();
+        let mut complete = |execution: &Execution| {
+            let result = ExecutionResult::new(
+                complete_time.clone(),
+                Ok(None),
+                ExitKind::Success,
+                worker,
+                vec![],
+            );
+            store.complete(execution.id(), result).unwrap()
+        };
+        let first = complete(&started[2]);
+        let second = complete(&started[0]);
+        assert!(second.sequence() > first.sequence());
+
+        let ids =
+            |executions: Vec<Execution>| executions.iter().map(|e| *e.id()).collect::<Vec<_>>();
+        let after = first.sequence().unwrap() - 1;
+        assert_eq!(
+            ids(store.completed_after(Some(function.id()), after)),
+            [*first.id(), *second.id()]
+        );
+        assert_eq!(
+            ids(store.completed_after(None, first.sequence().unwrap())),
+            [*second.id()]
+        );
+        // running executions haven't completed yet
+        assert!(store
+            .completed_after(None, second.sequence().unwrap())
+            .is_empty());
+    }
+
+    #[test]
+    fn parses_the_after_query() {
+        let uri = "/executions?after=42".parse().unwrap();
+        let Query(query) = Query::<ExecutionQuery>::try_from_uri(&uri).unwrap();
+        assert_eq!(query.after, Some(42));
+    }
+
+    #[test]
+    fn retention() {
+        let function = Function::new(functions::samples().remove(0));
+        let execution = || {
+            Execution::new(ExecutionRequest::new(
+                *function.id(),
+                function.current_revision().number(),
+                None,
+                Trigger::Http,
+            ))
+        };
+        let mut store = ExecutionStore::new(2);
+        let executions = [execution(), execution(), execution()];
+        for execution in &executions {
+            store.insert(execution.clone());
+        }
+        assert!(store.get(executions[0].id()).is_none());
+        assert!(store.get(executions[1].id()).is_some());
+        assert!(store.get(executions[2].id()).is_some());
+        assert_eq!(store.list(Some(function.id())).len(), 2);
+    }
+}
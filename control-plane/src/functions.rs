@@ -0,0 +1,344 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+
+use api::function::{
+    registration::{
+        AllowedHost, EgressPolicy, Function, FunctionId, FunctionSpec, InputKind, OutputKind,
+        Revision, RevisionNumber, Runtime,
+    },
+    retry::RetryPolicy,
+};
+use axum::{
+    body::Bytes,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use serde::Deserialize;
+
+use crate::{blobs::BlobStore, state::StateFile};
+
+/// The registered functions, saved on every change
+#[derive(Clone)]
+pub struct FunctionStore {
+    inner: Arc<Mutex<BTreeMap<FunctionId, Function>>>,
+    state: StateFile,
+}
+
+impl FunctionStore {
+    pub fn open(state: StateFile) -> anyhow::Result<Self> {
+        Ok(FunctionStore {
+            inner: Arc::new(Mutex::new(state.load()?)),
+            state,
+        })
+    }
+    /// Insert the function unless another one is already registered under the same name
+    pub fn insert(&mut self, function: Function) -> Option<Function> {
+        let mut functions = self.inner.lock().unwrap();
+        if functions.values().any(|f| f.name() == function.name()) {
+            return None;
+        }
+        functions.insert(*function.id(), function.clone());
+        self.save(&functions);
+        Some(function)
+    }
+    pub fn list(&self) -> Vec<Function> {
+        self.inner.lock().unwrap().values().cloned().collect()
+    }
+    pub fn get(&self, id: &FunctionId) -> Option<Function> {
+        self.inner.lock().unwrap().get(id).cloned()
+    }
+    pub fn get_by_name(&self, name: &str) -> Option<Function> {
+        self.inner
+            .lock()
+            .unwrap()
+            .values()
+            .find(|f| f.name() == name)
+            .cloned()
+    }
+    /// Update the function in place, returning what `f` returns or `None` if there is no such function
+    pub fn modify<T>(&mut self, name: &str, f: impl FnOnce(&mut Function) -> T) -> Option<T> {
+        let mut functions = self.inner.lock().unwrap();
+        let result = functions.values_mut().find(|f| f.name() == name).map(f)?;
+        self.save(&functions);
+        Some(result)
+    }
+    pub fn remove_by_name(&mut self, name: &str) -> Option<Function> {
+        let mut functions = self.inner.lock().unwrap();
+        let id = *functions.values().find(|f| f.name() == name)?.id();
+        let function = functions.remove(&id);
+        self.save(&functions);
+        function
+    }
+    fn save(&self, functions: &BTreeMap<FunctionId, Function>) {
+        if let Err(e) = self.state.save(functions) {
+            tracing::error!(error = ?e, "failed to save the functions");
+        }
+    }
+}
+
+/// The functions in `functions-sample`, addressed relative to the worker's function directory
+pub fn samples() -> Vec<FunctionSpec> {
+    let numbers = InputKind::List(Box::new(InputKind::Number));
+    let number = Some(InputKind::Number);
+    let field =
+        |name: &str| InputKind::Object(BTreeMap::from([(name.to_string(), InputKind::String)]));
+    let mut fetch = sample("fetch", Runtime::HttpHandler, InputKind::None, None);
+    // fetch can only reach the IANA example domain, never the control plane's admin API
+    fetch.egress = EgressPolicy::new(vec![AllowedHost::new("example.com", Some(80))], None);
+    vec![
+        sample(
+            "hello",
+            Runtime::Wasm,
+            InputKind::None,
+            Some(InputKind::None),
+        ),
+        sample("add", Runtime::Wasm, numbers.clone(), number.clone()),
+        sample("sub", Runtime::Wasm, numbers.clone(), number.clone()),
+        sample("mul", Runtime::Wasm, numbers.clone(), number.clone()),
+        sample("div", Runtime::Wasm, numbers, number),
+        sample(
+            "greet",
+            Runtime::Component,
+            field("name"),
+            Some(field("greeting")),
+        ),
+        sample("echo", Runtime::HttpHandler, InputKind::None, None),
+        fetch,
+    ]
+}
+
+fn sample(
+    name: &str,
+    runtime: Runtime,
+    input_type: InputKind,
+    output_type: Option<OutputKind>,
+) -> FunctionSpec {
+    let target = match runtime {
+        Runtime::Wasm => "wasm32-wasip1",
+        Runtime::Component | Runtime::HttpHandler => "wasm32-wasip2",
+    };
+    FunctionSpec {
+        name: name.to_string(),
+        description: format!("functions-sample/{name}"),
+        runtime,
+        input_type,
+        input_schema: None,
+        output_type,
+        output_schema: None,
+        blob_address: format!("{name}/target/{target}/debug/{name}.wasm").into(),
+        note: String::new(),
+        egress: EgressPolicy::default(),
+        retry: None,
+    }
+}
+
+#[tracing::instrument(skip(store))]
+pub async fn list_functions(State(store): State<FunctionStore>) -> Json<Vec<Function>> {
+    Json(store.list())
+}
+
+#[tracing::instrument(skip(store))]
+pub async fn create_function(
+    State(mut store): State<FunctionStore>,
+    Json(spec): Json<FunctionSpec>,
+) -> Result<Json<Function>, (StatusCode, String)> {
+    spec.check().map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let name = spec.name.clone();
+    store.insert(Function::new(spec)).map(Json).ok_or((
+        StatusCode::CONFLICT,
+        format!("function {name} already exists"),
+    ))
+}
+
+#[tracing::instrument(skip(store))]
+pub async fn get_function(
+    State(store): State<FunctionStore>,
+    Path(name): Path<String>,
+) -> Result<Json<Function>, StatusCode> {
+    store
+        .get_by_name(&name)
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+#[tracing::instrument(skip(store))]
+pub async fn delete_function(
+    State(mut store): State<FunctionStore>,
+    Path(name): Path<String>,
+) -> Result<Json<Function>, StatusCode> {
+    store
+        .remove_by_name(&name)
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+#[tracing::instrument(skip(store))]
+pub async fn list_revisions(
+    State((store, _)): State<(FunctionStore, BlobStore)>,
+    Path(name): Path<String>,
+) -> Result<Json<Vec<Revision>>, StatusCode> {
+    store
+        .get_by_name(&name)
+        .map(|function| Json(function.revisions().to_vec()))
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UploadParams {
+    #[serde(default)]
+    note: String,
+}
+
+/// Upload the wasm for a new revision of the function, which becomes its current revision
+#[tracing::instrument(skip(store, blobs, wasm))]
+pub async fn create_revision(
+    State((mut store, blobs)): State<(FunctionStore, BlobStore)>,
+    Path(name): Path<String>,
+    Query(UploadParams { note }): Query<UploadParams>,
+    wasm: Bytes,
+) -> Result<Json<Revision>, (StatusCode, String)> {
+    let not_found = || (StatusCode::NOT_FOUND, format!("function {name} not found"));
+    store.get_by_name(&name).ok_or_else(not_found)?;
+    let blob_address = blobs.put(&wasm)?;
+    store
+        .modify(&name, |function| {
+            function.add_revision(blob_address, note).clone()
+        })
+        .map(Json)
+        .ok_or_else(not_found)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Rollback {
+    revision: RevisionNumber,
+}
+
+/// Make an earlier (or later) revision of the function current again
+#[tracing::instrument(skip(store))]
+pub async fn rollback(
+    State((mut store, _)): State<(FunctionStore, BlobStore)>,
+    Path(name): Path<String>,
+    Json(Rollback { revision }): Json<Rollback>,
+) -> Result<Json<Revision>, (StatusCode, String)> {
+    store
+        .modify(&name, |function| {
+            function.set_current_revision(revision).cloned()
+        })
+        .ok_or((StatusCode::NOT_FOUND, format!("function {name} not found")))?
+        .map(Json)
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            format!("function {name} has no revision {revision}"),
+        ))
+}
+
+#[tracing::instrument(skip(store))]
+pub async fn list_aliases(
+    State(store): State<FunctionStore>,
+    Path(name): Path<String>,
+) -> Result<Json<BTreeMap<String, RevisionNumber>>, StatusCode> {
+    store
+        .get_by_name(&name)
+        .map(|function| Json(function.aliases().clone()))
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AliasTarget {
+    revision: RevisionNumber,
+}
+
+/// Replace how the function's asynchronous invocations are retried, including the ones being retried
+#[tracing::instrument(skip(store))]
+pub async fn set_retry(
+    State(mut store): State<FunctionStore>,
+    Path(name): Path<String>,
+    Json(policy): Json<RetryPolicy>,
+) -> Result<Json<Function>, StatusCode> {
+    store
+        .modify(&name, |function| {
+            function.set_retry(Some(policy));
+            function.clone()
+        })
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Go back to a single attempt per asynchronous invocation
+#[tracing::instrument(skip(store))]
+pub async fn delete_retry(
+    State(mut store): State<FunctionStore>,
+    Path(name): Path<String>,
+) -> Result<Json<Function>, StatusCode> {
+    store
+        .modify(&name, |function| {
+            function.set_retry(None);
+            function.clone()
+        })
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Point the alias at a revision, creating it if needed, so that promoting a revision is a single update
+#[tracing::instrument(skip(store))]
+pub async fn set_alias(
+    State(mut store): State<FunctionStore>,
+    Path((name, alias)): Path<(String, String)>,
+    Json(AliasTarget { revision }): Json<AliasTarget>,
+) -> Result<Json<BTreeMap<String, RevisionNumber>>, (StatusCode, String)> {
+    store
+        .modify(&name, |function| {
+            function
+                .set_alias(&alias, revision)
+                .map(|()| function.aliases().clone())
+        })
+        .ok_or((StatusCode::NOT_FOUND, format!("function {name} not found")))?
+        .map(Json)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))
+}
+
+#[tracing::instrument(skip(store))]
+pub async fn delete_alias(
+    State(mut store): State<FunctionStore>,
+    Path((name, alias)): Path<(String, String)>,
+) -> Result<Json<BTreeMap<String, RevisionNumber>>, StatusCode> {
+    store
+        .modify(&name, |function| {
+            function
+                .remove_alias(&alias)
+                .map(|_| function.aliases().clone())
+        })
+        .flatten()
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn functions_persist() {
+        let dir =
+            std::env::temp_dir().join(format!("wasi-faas-functions-{}", api::types::Id::new()));
+        let open = || FunctionStore::open(StateFile::open(&dir, "functions").unwrap()).unwrap();
+        let mut store = open();
+        for spec in samples() {
+            store.insert(Function::new(spec));
+        }
+        store.modify("add", |add| add.set_retry(Some(RetryPolicy::default())));
+        store.remove_by_name("div");
+
+        let store = open();
+        assert_eq!(store.list().len(), samples().len() - 1);
+        assert!(store.get_by_name("div").is_none());
+        let add = store.get_by_name("add").unwrap();
+        assert_eq!(store.get(add.id()), Some(add.clone()));
+        assert_eq!(add.retry(), Some(&RetryPolicy::default()));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,336 @@
+use std::{path::Path, time::Duration};
+
+use api_gateway::GatewayState;
+use axum::{
+    extract::DefaultBodyLimit,
+    middleware,
+    routing::{any, delete, get, post, put},
+    Router,
+};
+use blobs::BlobStore;
+use config::{Config, StorageBackend};
+use cron::{CronStore, Scheduler};
+use dead_letters::DeadLetterStore;
+use executions::ExecutionStore;
+use functions::FunctionStore;
+use metrics_exporter_prometheus::PrometheusHandle;
+use paths::PathStore;
+use pending::PendingStore;
+use pipelines::{PipelineExecutionStore, PipelineState, PipelineStore};
+use queue::Queue;
+use state::StateFile;
+use traffic::RollbackMonitor;
+use webhooks::{Deliveries, SecretKey, WebhookState, WebhookStore};
+use workers::WorkerStore;
+use workflows::{WorkflowExecutionStore, WorkflowState, WorkflowStore};
+
+pub mod api_gateway;
+pub mod blobs;
+pub mod config;
+pub mod cron;
+pub mod dead_letters;
+pub mod executions;
+pub mod functions;
+pub mod metrics;
+pub mod paths;
+pub mod pending;
+pub mod pipelines;
+pub mod placement;
+pub mod queue;
+pub mod state;
+pub mod traffic;
+pub mod webhooks;
+pub mod workers;
+pub mod workflows;
+
+/// Open the blob store of the configured storage backend
+pub fn blob_store(config: &Config) -> anyhow::Result<BlobStore> {
+    match config.storage.backend {
+        StorageBackend::Filesystem => BlobStore::open(Path::new(&config.storage.dir)),
+        StorageBackend::Memory => Ok(BlobStore::memory()),
+    }
+}
+
+/// Open the file keeping the named state across restarts with the configured storage backend
+pub fn state_file(config: &Config, name: &str) -> anyhow::Result<StateFile> {
+    match config.storage.backend {
+        StorageBackend::Filesystem => StateFile::open(Path::new(&config.storage.state_dir), name),
+        StorageBackend::Memory => Ok(StateFile::memory()),
+    }
+}
+
+/// The control plane's REST API and gateway serving the given functions, the cron scheduler and the asynchronous requests
+/// and workflow executions left running by a previous run are spawned on the current runtime
+pub fn app(
+    config: &Config,
+    function_store: FunctionStore,
+    blob_store: BlobStore,
+    metrics_handle: PrometheusHandle,
+) -> anyhow::Result<Router> {
+    // the timeout only bounds connecting and the wait for the response's head so that streamed bodies aren't cut off
+    let gateway_timeout = Duration::from_millis(config.gateway.timeout_ms);
+    let client = settings::tls::client_builder(config.gateway.worker_ca.as_deref())?
+        .connect_timeout(gateway_timeout)
+        .build()?;
+    let worker_store = WorkerStore::new();
+    let workers_api = Router::new()
+        .route("/", get(workers::list_workers).post(workers::create_worker))
+        .route(
+            "/:id",
+            get(workers::get_worker)
+                .put(workers::put_worker)
+                .patch(workers::update_worker)
+                .delete(workers::delete_worker),
+        )
+        .route("/:id/heartbeat", post(workers::heartbeat))
+        .with_state(worker_store.clone());
+
+    let functions_api = Router::new()
+        .route(
+            "/",
+            get(functions::list_functions).post(functions::create_function),
+        )
+        .route(
+            "/:name",
+            get(functions::get_function).delete(functions::delete_function),
+        )
+        .route("/:name/aliases", get(functions::list_aliases))
+        .route(
+            "/:name/aliases/:alias",
+            put(functions::set_alias).delete(functions::delete_alias),
+        )
+        .route(
+            "/:name/traffic",
+            put(traffic::set_traffic).delete(traffic::delete_traffic),
+        )
+        .route(
+            "/:name/retry",
+            put(functions::set_retry).delete(functions::delete_retry),
+        )
+        .with_state(function_store.clone())
+        .merge(
+            Router::new()
+                .route(
+                    "/:name/revisions",
+                    get(functions::list_revisions).post(functions::create_revision),
+                )
+                .route("/:name/rollback", post(functions::rollback))
+                .with_state((function_store.clone(), blob_store.clone())),
+        )
+        .layer(DefaultBodyLimit::max(config.limits.max_blob_size));
+
+    let blobs_api = Router::new()
+        .route("/", post(blobs::upload_blob))
+        .route("/:address", get(blobs::get_blob))
+        .layer(DefaultBodyLimit::max(config.limits.max_blob_size))
+        .with_state(blob_store);
+
+    let execution_store = ExecutionStore::new(config.retention.executions);
+    let executions_api = Router::new()
+        .route("/", get(executions::list_executions))
+        .route("/:id", get(executions::get_execution))
+        .with_state(execution_store.clone())
+        .merge(
+            Router::new()
+                .route("/:id/result", put(executions::complete_execution))
+                .with_state((
+                    execution_store.clone(),
+                    function_store.clone(),
+                    RollbackMonitor::new(),
+                )),
+        );
+
+    let path_store = PathStore::open(state_file(config, "paths")?)?;
+    let paths_api = Router::new()
+        .route("/", get(paths::list_paths).post(paths::create_path))
+        .route("/*path", delete(paths::delete_path))
+        .with_state((path_store.clone(), function_store.clone()));
+
+    let gateway_state = GatewayState {
+        workers: worker_store.clone(),
+        functions: function_store.clone(),
+        executions: execution_store.clone(),
+        client,
+        timeout: gateway_timeout,
+        queue: Queue::new(
+            &worker_store,
+            config.queue.max_depth,
+            Duration::from_millis(config.queue.max_wait_ms),
+        ),
+        dead_letters: DeadLetterStore::open(
+            state_file(config, "dead_letters")?,
+            config.retention.dead_letters,
+        )?,
+        pending: PendingStore::open(
+            state_file(config, "pending_requests")?,
+            config.queue.max_async,
+        )?,
+        paths: path_store,
+    };
+    api_gateway::resume_async(gateway_state.clone());
+    // proxy calls to the first available worker in api-gateway
+    let api_gateway = Router::new()
+        .route("/:function", any(api_gateway::proxy))
+        .route("/:function/*path", any(api_gateway::proxy))
+        .layer(DefaultBodyLimit::max(config.limits.max_request_body))
+        .with_state(gateway_state.clone());
+
+    let dead_letters_api = Router::new()
+        .route("/", get(dead_letters::list_dead_letters))
+        .route(
+            "/:id",
+            get(dead_letters::get_dead_letter).delete(dead_letters::discard_dead_letter),
+        )
+        .with_state(gateway_state.dead_letters.clone())
+        .merge(
+            Router::new()
+                .route("/:id/replay", post(dead_letters::replay_dead_letter))
+                .with_state(gateway_state.clone()),
+        );
+
+    let cron_store = CronStore::open(
+        state_file(config, "cron")?,
+        state_file(config, "cron_runs")?,
+    )?;
+    tokio::spawn(Scheduler::new(cron_store.clone(), gateway_state.clone()).run());
+    let triggers_api = Router::new()
+        .route("/cron", get(cron::list_cron_triggers))
+        .route(
+            "/cron/:name",
+            get(cron::get_cron_trigger).delete(cron::delete_cron_trigger),
+        )
+        .route("/cron/:name/runs", get(cron::list_cron_runs))
+        .with_state(cron_store.clone())
+        .merge(
+            Router::new()
+                .route("/cron", post(cron::create_cron_trigger))
+                .with_state((cron_store, function_store.clone())),
+        );
+
+    let secret_key = match config.storage.backend {
+        StorageBackend::Filesystem => SecretKey::open(Path::new(&config.webhooks.secret_key_file))?,
+        StorageBackend::Memory => SecretKey::ephemeral(),
+    };
+    let webhook_store = WebhookStore::open(state_file(config, "webhooks")?, secret_key)?;
+    let webhooks_api = Router::new()
+        .route("/webhooks", get(webhooks::list_webhooks))
+        .route(
+            "/webhooks/:name",
+            get(webhooks::get_webhook).delete(webhooks::delete_webhook),
+        )
+        .with_state(webhook_store.clone())
+        .merge(
+            Router::new()
+                .route("/webhooks", post(webhooks::create_webhook))
+                .with_state((webhook_store.clone(), function_store.clone()))
+                .merge(
+                    Router::new()
+                        .route("/webhooks/:name/runs", get(webhooks::list_webhook_runs))
+                        .with_state((webhook_store.clone(), execution_store)),
+                ),
+        );
+
+    let pipeline_store = PipelineStore::open(state_file(config, "pipelines")?)?;
+    let pipeline_execution_store =
+        PipelineExecutionStore::new(config.retention.pipeline_executions);
+    let pipelines_api = Router::new()
+        .route("/", get(pipelines::list_pipelines))
+        .route(
+            "/:name",
+            get(pipelines::get_pipeline).delete(pipelines::delete_pipeline),
+        )
+        .with_state(pipeline_store.clone())
+        .merge(
+            Router::new()
+                .route("/", post(pipelines::create_pipeline))
+                .with_state((pipeline_store.clone(), function_store.clone())),
+        )
+        .merge(
+            Router::new()
+                .route(
+                    "/:name/executions",
+                    get(pipelines::list_pipeline_executions),
+                )
+                .route(
+                    "/:name/executions/:id",
+                    get(pipelines::get_pipeline_execution),
+                )
+                .with_state(pipeline_execution_store.clone()),
+        )
+        .merge(
+            Router::new()
+                .route("/:name/executions", post(pipelines::execute_pipeline))
+                .layer(DefaultBodyLimit::max(config.limits.max_request_body))
+                .with_state(PipelineState {
+                    pipelines: pipeline_store,
+                    executions: pipeline_execution_store,
+                    gateway: gateway_state.clone(),
+                }),
+        );
+
+    let workflow_state = WorkflowState {
+        workflows: WorkflowStore::open(state_file(config, "workflows")?)?,
+        executions: WorkflowExecutionStore::open(
+            state_file(config, "workflow_executions")?,
+            config.retention.workflow_executions,
+        )?,
+        gateway: gateway_state.clone(),
+    };
+    workflows::resume(&workflow_state);
+    let workflows_api = Router::new()
+        .route("/", get(workflows::list_workflows))
+        .route(
+            "/:name",
+            get(workflows::get_workflow).delete(workflows::delete_workflow),
+        )
+        .with_state(workflow_state.workflows.clone())
+        .merge(
+            Router::new()
+                .route("/", post(workflows::create_workflow))
+                .with_state((workflow_state.workflows.clone(), function_store)),
+        )
+        .merge(
+            Router::new()
+                .route(
+                    "/:name/executions",
+                    get(workflows::list_workflow_executions),
+                )
+                .route(
+                    "/:name/executions/:id",
+                    get(workflows::get_workflow_execution),
+                )
+                .with_state(workflow_state.executions.clone()),
+        )
+        .merge(
+            Router::new()
+                .route("/:name/executions", post(workflows::execute_workflow))
+                .layer(DefaultBodyLimit::max(config.limits.max_request_body))
+                .with_state(workflow_state),
+        );
+    // deliveries are received on an unguessable URL per webhook
+    let hooks = Router::new()
+        .route("/:id", post(webhooks::receive))
+        .layer(DefaultBodyLimit::max(config.limits.max_request_body))
+        .with_state(WebhookState {
+            webhooks: webhook_store,
+            deliveries: Deliveries::new(Duration::from_millis(config.webhooks.dedupe_window_ms)),
+            gateway: gateway_state,
+        });
+
+    // functions are served under their name and under the paths registered for them
+    Ok(Router::new()
+        .nest("/workers", workers_api)
+        .nest("/functions", functions_api)
+        .nest("/paths", paths_api)
+        .nest("/blobs", blobs_api)
+        .nest("/executions", executions_api)
+        .nest("/dead-letters", dead_letters_api)
+        .nest("/triggers", triggers_api.merge(webhooks_api))
+        .nest("/hooks", hooks)
+        .nest("/pipelines", pipelines_api)
+        .nest("/workflows", workflows_api)
+        .nest("/api", api_gateway)
+        .route("/metrics", get(metrics::render))
+        .with_state((metrics_handle, worker_store))
+        .layer(middleware::from_fn(telemetry::propagate)))
+}
@@ -1,40 +1,69 @@
-use axum::{
-    routing::{get, post},
-    Router,
+use std::path::PathBuf;
+
+use api::function::registration::Function;
+use clap::Parser;
+use control_plane::{
+    config::{self, Config},
+    functions,
+    functions::FunctionStore,
+    metrics,
 };
-use workers::WorkerStore;
+use settings::Flags;
 
-mod api_gateway;
-mod workers;
+#[derive(clap::Parser)]
+struct Args {
+    /// TOML configuration file, overridden by `WASI_FAAS_CP_*` environment variables and the flags
+    #[clap(long)]
+    config: Option<PathBuf>,
+    /// Print the effective configuration and exit
+    #[clap(long)]
+    print_config: bool,
+    #[clap(long)]
+    listen_address: Option<String>,
+    /// Override any configuration key, e.g. `--set gateway.timeout_ms=5000`
+    #[clap(long = "set", short = 's', value_name = "KEY=VALUE")]
+    overrides: Vec<String>,
+}
 
 #[tokio::main]
-async fn main() -> Result<(), std::io::Error> {
-    tracing_subscriber::fmt::init();
-    let worker_store = WorkerStore::new();
-    let workers_api = Router::new()
-        .route("/", get(workers::list_workers).post(workers::create_worker))
-        .route(
-            "/:id",
-            get(workers::get_worker)
-                .patch(workers::update_worker)
-                .delete(workers::delete_worker),
-        )
-        .with_state(worker_store.clone());
-
-    // proxy calls to the first available worker in api-gateway
-    let api_gateway = Router::new()
-        .route("/:function", post(api_gateway::proxy))
-        .with_state(worker_store);
+async fn main() -> anyhow::Result<()> {
+    let args = Args::try_parse()?;
+    let mut flags = Flags::default();
+    flags.set("server.listen_address", args.listen_address);
+    for pair in &args.overrides {
+        flags.set_pair(pair)?;
+    }
+    let config: Config = settings::load(args.config.as_deref(), config::ENV_PREFIX, flags)?;
+    if args.print_config {
+        print!("{}", settings::to_toml(&config)?);
+        return Ok(());
+    }
 
-    // TODO: in theory we'd be able to have an api for registering functions and their associated paths but for simplicity we'll just hardcode them
-    let app = Router::new()
-        .nest("/workers", workers_api)
-        .nest("/api", api_gateway);
+    telemetry::init("control-plane")?;
+    let metrics_handle = metrics::install()?;
+    let blob_store = control_plane::blob_store(&config)?;
+    let mut function_store = FunctionStore::open(control_plane::state_file(&config, "functions")?)?;
+    // the samples are registered again only once removed, keeping the revisions and settings saved by a previous run
+    for spec in functions::samples() {
+        if function_store.get_by_name(&spec.name).is_none() {
+            function_store.insert(Function::new(spec));
+        }
+    }
+    let app = control_plane::app(&config, function_store, blob_store, metrics_handle)?;
 
-    let listener = tokio::net::TcpListener::bind("127.0.0.1:3000")
-        .await
-        .unwrap();
-    tracing::info!("listening on {}", listener.local_addr().unwrap());
-    axum::serve(listener, app).await?;
+    let listener = tokio::net::TcpListener::bind(&config.server.listen_address).await?;
+    tracing::info!(
+        "listening on {}://{}",
+        config.server.scheme(),
+        listener.local_addr()?
+    );
+    settings::tls::serve(
+        listener,
+        app,
+        config.server.tls.as_ref(),
+        settings::shutdown_signal(),
+    )
+    .await?;
+    telemetry::shutdown();
     Ok(())
 }
@@ -0,0 +1,187 @@
+use std::time::Duration;
+
+use api::worker::WorkerId;
+use axum::extract::State;
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+
+use crate::workers::WorkerStore;
+
+pub const GATEWAY_REQUESTS: &str = "wasi_faas_gateway_requests_total";
+pub const GATEWAY_REQUEST_DURATION: &str = "wasi_faas_gateway_request_duration_seconds";
+pub const GATEWAY_RETRIES: &str = "wasi_faas_gateway_retries_total";
+pub const WORKER_HEARTBEAT_AGE: &str = "wasi_faas_worker_heartbeat_age_seconds";
+pub const QUEUE_DEPTH: &str = "wasi_faas_gateway_queue_depth";
+pub const QUEUE_WAIT_DURATION: &str = "wasi_faas_gateway_queue_wait_duration_seconds";
+pub const QUEUE_REJECTIONS: &str = "wasi_faas_gateway_queue_rejections_total";
+pub const CRON_RUNS: &str = "wasi_faas_cron_runs_total";
+pub const WEBHOOK_DELIVERIES: &str = "wasi_faas_webhook_deliveries_total";
+pub const PIPELINE_EXECUTIONS: &str = "wasi_faas_pipeline_executions_total";
+pub const PIPELINE_EXECUTION_DURATION: &str = "wasi_faas_pipeline_execution_duration_seconds";
+pub const WORKFLOW_EXECUTIONS: &str = "wasi_faas_workflow_executions_total";
+pub const ASYNC_INVOCATIONS: &str = "wasi_faas_async_invocations_total";
+
+const DURATION_BUCKETS: [f64; 12] = [
+    0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 5.0,
+];
+
+pub fn install() -> anyhow::Result<PrometheusHandle> {
+    Ok(builder()?.install_recorder()?)
+}
+
+fn builder() -> anyhow::Result<PrometheusBuilder> {
+    Ok(PrometheusBuilder::new()
+        .set_buckets_for_metric(Matcher::Suffix("_seconds".to_string()), &DURATION_BUCKETS)?)
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn render(State((handle, store)): State<(PrometheusHandle, WorkerStore)>) -> String {
+    record_heartbeat_ages(&store);
+    handle.run_upkeep();
+    handle.render()
+}
+
+/// Heartbeat age is derived from the store at scrape time rather than tracked on every heartbeat
+fn record_heartbeat_ages(store: &WorkerStore) {
+    for worker in store.list(None) {
+        metrics::gauge!(WORKER_HEARTBEAT_AGE, "worker" => worker.id().to_string())
+            .set(worker.last_heartbeat().elapsed().as_secs_f64());
+    }
+}
+
+pub fn record_gateway_request(
+    function: &str,
+    worker: Option<&WorkerId>,
+    status: u16,
+    elapsed: Duration,
+) {
+    let worker = worker.map(|w| w.to_string()).unwrap_or_default();
+    metrics::counter!(
+        GATEWAY_REQUESTS,
+        "function" => function.to_string(),
+        "worker" => worker.clone(),
+        "status" => status.to_string(),
+    )
+    .increment(1);
+    metrics::histogram!(
+        GATEWAY_REQUEST_DURATION,
+        "function" => function.to_string(),
+        "worker" => worker,
+    )
+    .record(elapsed.as_secs_f64());
+}
+
+pub fn record_gateway_retry(function: &str, worker: &WorkerId) {
+    metrics::counter!(
+        GATEWAY_RETRIES,
+        "function" => function.to_string(),
+        "worker" => worker.to_string(),
+    )
+    .increment(1);
+}
+
+pub fn record_queue_depth(function: &str, depth: usize) {
+    metrics::gauge!(QUEUE_DEPTH, "function" => function.to_string()).set(depth as f64);
+}
+
+/// How long a queued request waited before a worker became available
+pub fn record_queue_wait(function: &str, elapsed: Duration) {
+    metrics::histogram!(QUEUE_WAIT_DURATION, "function" => function.to_string())
+        .record(elapsed.as_secs_f64());
+}
+
+/// A request turned away because the queue was full or no worker became available in time
+pub fn record_queue_rejection(function: &str, reason: &'static str) {
+    metrics::counter!(
+        QUEUE_REJECTIONS,
+        "function" => function.to_string(),
+        "reason" => reason,
+    )
+    .increment(1);
+}
+
+/// Runs of a cron trigger by outcome: `succeeded`, `failed`, `queued`, `skipped` while the previous run executes, or
+/// `missed` while the control plane was down
+pub fn record_cron_runs(trigger: &str, outcome: &'static str, count: usize) {
+    metrics::counter!(
+        CRON_RUNS,
+        "trigger" => trigger.to_string(),
+        "outcome" => outcome,
+    )
+    .increment(count as u64);
+}
+
+/// Deliveries to a webhook by outcome: `accepted`, `rejected` for a bad signature, `duplicate`, then `succeeded` or
+/// `failed` once the function has been invoked
+pub fn record_webhook_delivery(webhook: &str, outcome: &'static str) {
+    metrics::counter!(
+        WEBHOOK_DELIVERIES,
+        "webhook" => webhook.to_string(),
+        "outcome" => outcome,
+    )
+    .increment(1);
+}
+
+pub fn record_pipeline_execution(pipeline: &str, status: &'static str, elapsed: Duration) {
+    metrics::counter!(
+        PIPELINE_EXECUTIONS,
+        "pipeline" => pipeline.to_string(),
+        "status" => status,
+    )
+    .increment(1);
+    metrics::histogram!(PIPELINE_EXECUTION_DURATION, "pipeline" => pipeline.to_string())
+        .record(elapsed.as_secs_f64());
+}
+
+pub fn record_workflow_execution(workflow: &str, status: &'static str) {
+    metrics::counter!(
+        WORKFLOW_EXECUTIONS,
+        "workflow" => workflow.to_string(),
+        "status" => status,
+    )
+    .increment(1);
+}
+
+/// Attempts of asynchronous invocations by outcome: `succeeded`, `retried`, or `dead_lettered` once retries give up
+pub fn record_async_invocation(function: &str, outcome: &'static str) {
+    metrics::counter!(
+        ASYNC_INVOCATIONS,
+        "function" => function.to_string(),
+        "outcome" => outcome,
+    )
+    .increment(1);
+}
+
+#[cfg(test)]
+mod tests {
+    use api::worker::Worker;
+
+    use super::*;
+
+    #[test]
+    fn renders_recorded_metrics() {
+        let recorder = builder().unwrap().build_recorder();
+        let handle = recorder.handle();
+        let mut store = WorkerStore::new();
+        let worker = Worker::new("127.0.0.1:3001".to_string().into());
+        store.insert(worker.clone());
+        metrics::with_local_recorder(&recorder, || {
+            record_gateway_request("add", Some(worker.id()), 200, Duration::from_millis(3));
+            record_gateway_request("add", None, 503, Duration::from_millis(1));
+            record_heartbeat_ages(&store);
+        });
+
+        let rendered = handle.render();
+        let id = worker.id();
+        assert!(rendered.contains(&format!(
+            r#"{GATEWAY_REQUESTS}{{function="add",worker="{id}",status="200"}} 1"#
+        )));
+        assert!(rendered.contains(&format!(
+            r#"{GATEWAY_REQUESTS}{{function="add",worker="",status="503"}} 1"#
+        )));
+        // durations are histograms with the configured buckets
+        assert!(rendered.contains(&format!(
+            r#"{GATEWAY_REQUEST_DURATION}_bucket{{function="add",worker="{id}",le="0.005"}} 1"#
+        )));
+        assert!(rendered.contains(&format!(r#"{WORKER_HEARTBEAT_AGE}{{worker="{id}"}}"#)));
+    }
+}
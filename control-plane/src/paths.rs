@@ -0,0 +1,214 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+
+use api::function::registration::PathEntry;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+
+use crate::{functions::FunctionStore, state::StateFile};
+
+/// The gateway paths routed to functions, keyed by their path under `/api` and saved on every change
+#[derive(Clone)]
+pub struct PathStore {
+    inner: Arc<Mutex<BTreeMap<String, PathEntry>>>,
+    state: StateFile,
+}
+
+impl PathStore {
+    pub fn open(state: StateFile) -> anyhow::Result<Self> {
+        Ok(PathStore {
+            inner: Arc::new(Mutex::new(state.load()?)),
+            state,
+        })
+    }
+    /// Insert the entry unless the path is already routed
+    pub fn insert(&mut self, entry: PathEntry) -> Option<PathEntry> {
+        let mut entries = self.inner.lock().unwrap();
+        if entries.contains_key(&entry.path()) {
+            return None;
+        }
+        entries.insert(entry.path(), entry.clone());
+        self.save(&entries);
+        Some(entry)
+    }
+    pub fn list(&self) -> Vec<PathEntry> {
+        self.inner.lock().unwrap().values().cloned().collect()
+    }
+    pub fn remove(&mut self, path: &str) -> Option<PathEntry> {
+        let mut entries = self.inner.lock().unwrap();
+        let entry = entries.remove(path.trim_matches('/'))?;
+        self.save(&entries);
+        Some(entry)
+    }
+    /// The entry routing the request to `/api/{root}/{path}`, the one with the longest sub path if several match, and
+    /// the rest of the path below it
+    pub fn route(&self, root: &str, path: Option<&str>) -> Option<(PathEntry, Option<String>)> {
+        let path = path.unwrap_or_default().trim_matches('/');
+        self.inner
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|entry| entry.root().as_str() == root)
+            .filter_map(|entry| Some((entry, entry.sub_path().strip(path)?)))
+            .max_by_key(|(entry, _)| entry.sub_path().as_str().len())
+            .map(|(entry, rest)| {
+                (
+                    entry.clone(),
+                    Some(rest.to_string()).filter(|r| !r.is_empty()),
+                )
+            })
+    }
+    fn save(&self, entries: &BTreeMap<String, PathEntry>) {
+        if let Err(e) = self.state.save(entries) {
+            tracing::error!(error = ?e, "failed to save the paths");
+        }
+    }
+}
+
+#[tracing::instrument(skip(store))]
+pub async fn list_paths(
+    State((store, _)): State<(PathStore, FunctionStore)>,
+) -> Json<Vec<PathEntry>> {
+    Json(store.list())
+}
+
+/// Route the path to the function, the alias being resolved when a request arrives so that moving it moves the path.
+///
+/// A path's split can't roll back automatically, only a function's own split is watched for failing revisions.
+#[tracing::instrument(skip(store, functions))]
+pub async fn create_path(
+    State((mut store, functions)): State<(PathStore, FunctionStore)>,
+    Json(entry): Json<PathEntry>,
+) -> Result<Json<PathEntry>, (StatusCode, String)> {
+    let name = entry.function();
+    let function = functions
+        .get_by_name(name)
+        .ok_or((StatusCode::NOT_FOUND, format!("function {name} not found")))?;
+    if let Some(alias) = entry.alias() {
+        function.resolve(alias).ok_or((
+            StatusCode::BAD_REQUEST,
+            format!("function {name} has no alias or revision {alias}"),
+        ))?;
+    }
+    if let Some(split) = entry.traffic() {
+        if entry.alias().is_some() {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "a path has either an alias or a traffic split".to_string(),
+            ));
+        }
+        if split.auto_rollback.is_some() {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "auto_rollback is only supported on a function's own traffic split".to_string(),
+            ));
+        }
+        function
+            .check_traffic(split)
+            .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    }
+    let path = entry.path();
+    store.insert(entry).map(Json).ok_or((
+        StatusCode::CONFLICT,
+        format!("path /api/{path} already exists"),
+    ))
+}
+
+#[tracing::instrument(skip(store))]
+pub async fn delete_path(
+    State((mut store, _)): State<(PathStore, FunctionStore)>,
+    Path(path): Path<String>,
+) -> Result<Json<PathEntry>, StatusCode> {
+    store.remove(&path).map(Json).ok_or(StatusCode::NOT_FOUND)
+}
+
+#[cfg(test)]
+mod tests {
+    use api::function::registration::{
+        AutoRollback, Function, RevisionNumber, Root, SplitMode, SubPath, TrafficSplit,
+        WeightedRevision,
+    };
+
+    use super::*;
+    use crate::functions;
+
+    fn entry(root: &str, sub_path: &str, function: &str) -> PathEntry {
+        PathEntry::new(
+            Root::parse(root).unwrap(),
+            SubPath::parse(sub_path).unwrap(),
+            function.to_string(),
+        )
+    }
+
+    #[test]
+    fn routes_to_the_longest_sub_path() {
+        let mut paths = PathStore::open(StateFile::memory()).unwrap();
+        paths.insert(entry("shop", "", "echo"));
+        paths.insert(entry("shop", "cart", "add").with_alias("prod".to_string()));
+        assert!(paths.insert(entry("shop", "/cart/", "sub")).is_none());
+
+        let routes = paths.clone();
+        let route = |path| {
+            routes
+                .route("shop", path)
+                .map(|(entry, rest)| (entry.target(), rest))
+        };
+        assert_eq!(
+            route(Some("cart/items/1")),
+            Some(("add@prod".to_string(), Some("items/1".to_string())))
+        );
+        assert_eq!(route(Some("cart")), Some(("add@prod".to_string(), None)));
+        // a segment merely starting with the sub path isn't below it
+        assert_eq!(
+            route(Some("carts")),
+            Some(("echo".to_string(), Some("carts".to_string())))
+        );
+        assert_eq!(route(None), Some(("echo".to_string(), None)));
+        assert!(paths.route("add", None).is_none());
+
+        assert!(paths.remove("shop/cart").is_some());
+        assert_eq!(route(Some("cart")).unwrap().0, "echo");
+    }
+
+    #[tokio::test]
+    async fn registers_paths_of_existing_functions() {
+        let mut functions = FunctionStore::open(StateFile::memory()).unwrap();
+        functions.insert(Function::new(functions::samples().remove(1)));
+        let paths = PathStore::open(StateFile::memory()).unwrap();
+        let create = |entry| create_path(State((paths.clone(), functions.clone())), Json(entry));
+
+        assert!(create(entry("calc", "sum", "add")).await.is_ok());
+        let status = |result: Result<_, (StatusCode, _)>| result.unwrap_err().0;
+        assert_eq!(
+            status(create(entry("calc", "sum", "add")).await),
+            StatusCode::CONFLICT
+        );
+        assert_eq!(
+            status(create(entry("calc", "diff", "sub")).await),
+            StatusCode::NOT_FOUND
+        );
+        let aliased = entry("calc", "next", "add").with_alias("prod".to_string());
+        assert_eq!(status(create(aliased).await), StatusCode::BAD_REQUEST);
+        let first = RevisionNumber::parse("1").unwrap();
+        let split = TrafficSplit {
+            revisions: vec![WeightedRevision {
+                revision: first,
+                weight: 1,
+            }],
+            mode: SplitMode::Random,
+            auto_rollback: Some(AutoRollback {
+                to: first,
+                max_failure_rate: 0.5,
+                min_executions: 10,
+            }),
+        };
+        let rolled_back = entry("calc", "next", "add").with_traffic(split);
+        assert_eq!(status(create(rolled_back).await), StatusCode::BAD_REQUEST);
+        assert_eq!(paths.list().len(), 1);
+    }
+}
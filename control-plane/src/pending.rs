@@ -0,0 +1,83 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+
+use api::{
+    function::dead_letter::{AsyncRequest, DeadLetterId},
+    types::Id,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::state::StateFile;
+
+/// An asynchronous request accepted by the gateway and not yet completed or dead-lettered
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Pending {
+    pub request: AsyncRequest,
+    /// The dead letter being replayed, removed once the replay is done
+    #[serde(default)]
+    pub replaying: Option<DeadLetterId>,
+}
+
+/// Why a request wasn't accepted
+#[derive(Debug, PartialEq, Eq)]
+pub enum Refused {
+    /// `max_pending` requests are already pending
+    Full,
+    /// The dead letter is already being replayed
+    Replaying,
+}
+
+/// The asynchronous requests being invoked, saved on every change so that they are invoked again after a restart
+#[derive(Clone)]
+pub struct PendingStore {
+    inner: Arc<Mutex<BTreeMap<Id, Pending>>>,
+    state: StateFile,
+    max_pending: usize,
+}
+
+impl PendingStore {
+    pub fn open(state: StateFile, max_pending: usize) -> anyhow::Result<Self> {
+        Ok(PendingStore {
+            inner: Arc::new(Mutex::new(state.load()?)),
+            state,
+            max_pending,
+        })
+    }
+    pub fn insert(&mut self, pending: Pending) -> Result<Id, Refused> {
+        let mut requests = self.inner.lock().unwrap();
+        if requests.len() >= self.max_pending {
+            return Err(Refused::Full);
+        }
+        if pending.replaying.is_some()
+            && requests.values().any(|p| p.replaying == pending.replaying)
+        {
+            return Err(Refused::Replaying);
+        }
+        let id = Id::new();
+        requests.insert(id, pending);
+        self.save(&requests);
+        Ok(id)
+    }
+    /// The requests left pending by a previous run
+    pub fn list(&self) -> Vec<(Id, Pending)> {
+        self.inner
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(id, pending)| (*id, pending.clone()))
+            .collect()
+    }
+    pub fn remove(&mut self, id: &Id) {
+        let mut requests = self.inner.lock().unwrap();
+        if requests.remove(id).is_some() {
+            self.save(&requests);
+        }
+    }
+    fn save(&self, requests: &BTreeMap<Id, Pending>) {
+        if let Err(e) = self.state.save_in_background(requests) {
+            tracing::error!(error = ?e, "failed to save the pending requests");
+        }
+    }
+}
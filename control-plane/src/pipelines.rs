@@ -0,0 +1,381 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use api::{
+    function::{execution::Trigger, registration::Runtime},
+    pipeline::{Pipeline, PipelineExecution, PipelineExecutionId, PipelineSpec, StepExecution},
+    types::JsonData,
+};
+use axum::{
+    body::Bytes,
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use serde_json::Value;
+use tracing::Instrument;
+
+use crate::{
+    api_gateway::{self, GatewayState},
+    functions::FunctionStore,
+    metrics,
+    state::StateFile,
+};
+
+/// The pipelines, saved on every change
+#[derive(Clone)]
+pub struct PipelineStore {
+    inner: Arc<Mutex<BTreeMap<String, Pipeline>>>,
+    state: StateFile,
+}
+
+impl PipelineStore {
+    pub fn open(state: StateFile) -> anyhow::Result<Self> {
+        Ok(PipelineStore {
+            inner: Arc::new(Mutex::new(state.load()?)),
+            state,
+        })
+    }
+    /// Insert the pipeline unless another one is already registered under the same name
+    pub fn insert(&mut self, pipeline: Pipeline) -> Option<Pipeline> {
+        let mut pipelines = self.inner.lock().unwrap();
+        if pipelines.contains_key(pipeline.name()) {
+            return None;
+        }
+        pipelines.insert(pipeline.name().to_string(), pipeline.clone());
+        self.save(&pipelines);
+        Some(pipeline)
+    }
+    pub fn list(&self) -> Vec<Pipeline> {
+        self.inner.lock().unwrap().values().cloned().collect()
+    }
+    pub fn get(&self, name: &str) -> Option<Pipeline> {
+        self.inner.lock().unwrap().get(name).cloned()
+    }
+    pub fn remove(&mut self, name: &str) -> Option<Pipeline> {
+        let mut pipelines = self.inner.lock().unwrap();
+        let pipeline = pipelines.remove(name)?;
+        self.save(&pipelines);
+        Some(pipeline)
+    }
+    fn save(&self, pipelines: &BTreeMap<String, Pipeline>) {
+        if let Err(e) = self.state.save(pipelines) {
+            tracing::error!(error = ?e, "failed to save the pipelines");
+        }
+    }
+}
+
+#[derive(Default)]
+struct Executions {
+    by_id: BTreeMap<PipelineExecutionId, PipelineExecution>,
+    /// Ids in the order the executions started, the oldest first
+    order: VecDeque<PipelineExecutionId>,
+}
+
+/// The most recent pipeline executions, the oldest ones being dropped once more than `max_retained` are kept
+#[derive(Clone)]
+pub struct PipelineExecutionStore {
+    inner: Arc<Mutex<Executions>>,
+    max_retained: usize,
+}
+
+impl PipelineExecutionStore {
+    pub fn new(max_retained: usize) -> Self {
+        PipelineExecutionStore {
+            inner: Arc::new(Mutex::new(Executions::default())),
+            max_retained: max_retained.max(1),
+        }
+    }
+    /// Insert the execution or replace it with its progress
+    pub fn insert(&mut self, execution: PipelineExecution) {
+        let mut executions = self.inner.lock().unwrap();
+        let id = *execution.id();
+        if executions.by_id.insert(id, execution).is_none() {
+            executions.order.push_back(id);
+        }
+        while executions.order.len() > self.max_retained {
+            if let Some(oldest) = executions.order.pop_front() {
+                executions.by_id.remove(&oldest);
+            }
+        }
+    }
+    pub fn list(&self, pipeline: &str) -> Vec<PipelineExecution> {
+        self.inner
+            .lock()
+            .unwrap()
+            .by_id
+            .values()
+            .filter(|e| e.pipeline() == pipeline)
+            .cloned()
+            .collect()
+    }
+    pub fn get(&self, id: &PipelineExecutionId) -> Option<PipelineExecution> {
+        self.inner.lock().unwrap().by_id.get(id).cloned()
+    }
+}
+
+#[derive(Clone)]
+pub struct PipelineState {
+    pub pipelines: PipelineStore,
+    pub executions: PipelineExecutionStore,
+    pub gateway: GatewayState,
+}
+
+/// Call the steps in order through the gateway, each with the previous step's output, stopping at the first failure
+async fn execute(mut state: PipelineState, pipeline: Pipeline, input: Value) -> PipelineExecution {
+    let start = Instant::now();
+    let mut execution =
+        PipelineExecution::new(pipeline.name(), JsonData::from(input.clone()).into());
+    state.executions.insert(execution.clone());
+    let mut value = input;
+    for (i, step) in pipeline.steps().iter().enumerate() {
+        let trigger = Trigger::Pipeline {
+            pipeline: pipeline.name().to_string(),
+            execution: *execution.id(),
+            step: i,
+        };
+        let input = step.input(&value);
+        let call = api_gateway::call(state.gateway.clone(), &step.function, &input, trigger).await;
+        execution.push_step(StepExecution::new(
+            step.function.clone(),
+            call.execution,
+            call.result,
+            call.output.as_ref().err().cloned(),
+        ));
+        match call.output {
+            Ok(output) => value = output,
+            Err(e) => {
+                tracing::warn!(
+                    pipeline = pipeline.name(),
+                    step = i,
+                    error = e,
+                    "step failed"
+                );
+                execution.fail(format!("step {i} ({}) failed: {e}", step.function));
+                state.executions.insert(execution.clone());
+                metrics::record_pipeline_execution(pipeline.name(), "failed", start.elapsed());
+                return execution;
+            }
+        }
+        state.executions.insert(execution.clone());
+    }
+    execution.succeed(JsonData::from(value).into());
+    state.executions.insert(execution.clone());
+    metrics::record_pipeline_execution(pipeline.name(), "succeeded", start.elapsed());
+    execution
+}
+
+#[tracing::instrument(skip(pipelines))]
+pub async fn list_pipelines(State(pipelines): State<PipelineStore>) -> Json<Vec<Pipeline>> {
+    Json(pipelines.list())
+}
+
+/// Register a pipeline of functions taking and returning JSON
+#[tracing::instrument(skip(pipelines, functions))]
+pub async fn create_pipeline(
+    State((mut pipelines, functions)): State<(PipelineStore, FunctionStore)>,
+    Json(spec): Json<PipelineSpec>,
+) -> Result<Json<Pipeline>, (StatusCode, String)> {
+    if spec.steps.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "a pipeline needs at least one step".to_string(),
+        ));
+    }
+    for step in &spec.steps {
+        let name = step
+            .function
+            .split_once('@')
+            .map_or(step.function.as_str(), |(name, _)| name);
+        let function = functions
+            .get_by_name(name)
+            .ok_or((StatusCode::NOT_FOUND, format!("function {name} not found")))?;
+        if function.runtime() == Runtime::HttpHandler {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("function {name} handles raw HTTP and has no JSON output"),
+            ));
+        }
+    }
+    let name = spec.name.clone();
+    pipelines.insert(Pipeline::new(spec)).map(Json).ok_or((
+        StatusCode::CONFLICT,
+        format!("pipeline {name} already exists"),
+    ))
+}
+
+#[tracing::instrument(skip(pipelines))]
+pub async fn get_pipeline(
+    State(pipelines): State<PipelineStore>,
+    Path(name): Path<String>,
+) -> Result<Json<Pipeline>, StatusCode> {
+    pipelines.get(&name).map(Json).ok_or(StatusCode::NOT_FOUND)
+}
+
+#[tracing::instrument(skip(pipelines))]
+pub async fn delete_pipeline(
+    State(mut pipelines): State<PipelineStore>,
+    Path(name): Path<String>,
+) -> Result<Json<Pipeline>, StatusCode> {
+    pipelines
+        .remove(&name)
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Run the pipeline with the JSON body as the first step's input and respond once it has completed, a failed step
+/// being reported in the execution rather than with an error status.
+///
+/// The execution runs in its own task so that it still completes when the caller disconnects.
+#[tracing::instrument(skip(state, body))]
+pub async fn execute_pipeline(
+    State(state): State<PipelineState>,
+    Path(name): Path<String>,
+    body: Bytes,
+) -> Result<Json<PipelineExecution>, (StatusCode, String)> {
+    let pipeline = state
+        .pipelines
+        .get(&name)
+        .ok_or((StatusCode::NOT_FOUND, format!("pipeline {name} not found")))?;
+    // an empty body is treated as `null` like the gateway does
+    let input = if body.is_empty() {
+        Value::Null
+    } else {
+        serde_json::from_slice(&body)
+            .map_err(|e| (StatusCode::BAD_REQUEST, format!("invalid JSON input: {e}")))?
+    };
+    tokio::spawn(execute(state, pipeline, input).in_current_span())
+        .await
+        .map(Json)
+        .map_err(|e| {
+            tracing::error!(error = ?e, "pipeline execution panicked");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("pipeline {name} failed to execute"),
+            )
+        })
+}
+
+/// The executions of the pipeline, oldest first
+#[tracing::instrument(skip(executions))]
+pub async fn list_pipeline_executions(
+    State(executions): State<PipelineExecutionStore>,
+    Path(name): Path<String>,
+) -> Json<Vec<PipelineExecution>> {
+    let mut executions = executions.list(&name);
+    executions.sort_by(|a, b| a.create_time().cmp(b.create_time()));
+    Json(executions)
+}
+
+#[tracing::instrument(skip(executions))]
+pub async fn get_pipeline_execution(
+    State(executions): State<PipelineExecutionStore>,
+    Path((name, id)): Path<(String, PipelineExecutionId)>,
+) -> Result<Json<PipelineExecution>, StatusCode> {
+    executions
+        .get(&id)
+        .filter(|e| e.pipeline() == name)
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{atomic::AtomicU32, Arc};
+
+    use api::{
+        function::execution::Input,
+        pipeline::{PipelineStatus, PipelineStep},
+    };
+    use serde_json::json;
+
+    use super::*;
+    use crate::api_gateway::tests::gateway;
+
+    async fn pipeline_state(failures: u32) -> PipelineState {
+        PipelineState {
+            pipelines: PipelineStore::open(StateFile::memory()).unwrap(),
+            executions: PipelineExecutionStore::new(10),
+            gateway: gateway(Arc::new(AtomicU32::new(failures))).await,
+        }
+    }
+
+    fn pipeline(steps: usize) -> Pipeline {
+        let step = PipelineStep {
+            function: "add".to_string(),
+            input: Some(JsonData::from(json!(["$output", 1]))),
+        };
+        Pipeline::new(PipelineSpec {
+            name: "test".to_string(),
+            description: String::new(),
+            steps: vec![step; steps],
+        })
+    }
+
+    #[tokio::test]
+    async fn runs_steps_in_order() {
+        let state = pipeline_state(0).await;
+        let done = execute(state.clone(), pipeline(2), json!(1)).await;
+        assert_eq!(done.status(), PipelineStatus::Succeeded);
+        assert_eq!(done.steps().len(), 2);
+        // the worker always outputs 3
+        assert_eq!(done.output().unwrap().data().value(), &json!(3));
+        assert_eq!(
+            state.executions.get(done.id()).unwrap().status(),
+            PipelineStatus::Succeeded
+        );
+    }
+
+    #[tokio::test]
+    async fn stops_at_the_first_failure() {
+        let state = pipeline_state(u32::MAX).await;
+        let done = execute(state.clone(), pipeline(2), json!(1)).await;
+        assert_eq!(done.status(), PipelineStatus::Failed);
+        assert_eq!(done.steps().len(), 1);
+        assert!(done.steps()[0].error().is_some());
+        assert_eq!(
+            state.executions.get(done.id()).unwrap().status(),
+            PipelineStatus::Failed
+        );
+    }
+
+    #[test]
+    fn keeps_the_latest_executions() {
+        let mut executions = PipelineExecutionStore::new(2);
+        let started: Vec<_> = (0..3)
+            .map(|_| PipelineExecution::new("test", Input::from(JsonData::from(json!(1)))))
+            .collect();
+        for execution in &started {
+            executions.insert(execution.clone());
+        }
+        // progress on a retained execution doesn't evict another one
+        executions.insert(started[2].clone());
+        assert!(executions.get(started[0].id()).is_none());
+        assert!(executions.get(started[1].id()).is_some());
+        assert_eq!(executions.list("test").len(), 2);
+    }
+
+    #[test]
+    fn pipelines_persist() {
+        let dir =
+            std::env::temp_dir().join(format!("wasi-faas-pipelines-{}", api::types::Id::new()));
+        let open = || PipelineStore::open(StateFile::open(&dir, "pipelines").unwrap()).unwrap();
+        let mut store = open();
+        let kept = store.insert(pipeline(2)).unwrap();
+        store.insert(Pipeline::new(PipelineSpec {
+            name: "removed".to_string(),
+            description: String::new(),
+            steps: pipeline(1).steps().to_vec(),
+        }));
+        store.remove("removed").unwrap();
+
+        let store = open();
+        assert_eq!(store.list().len(), 1);
+        assert_eq!(store.get("test").unwrap().id(), kept.id());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,83 @@
+use api::{
+    function::registration::{Function, RevisionNumber},
+    worker::Worker,
+};
+
+/// Order the workers to dispatch an execution of the function's revision to, from the reports of their last
+/// heartbeat: workers which don't support the function's runtime are left out, then workers which have already
+/// loaded the revision come first so that it doesn't have to be compiled again, the least loaded first.
+///
+/// Workers which haven't reported yet are kept after the others rather than starving until their first heartbeat.
+pub fn rank(workers: Vec<Worker>, function: &Function, revision: RevisionNumber) -> Vec<Worker> {
+    let mut workers: Vec<Worker> = workers
+        .into_iter()
+        .filter(|worker| {
+            worker
+                .report()
+                .is_none_or(|report| report.runtimes().contains(&function.runtime()))
+        })
+        .collect();
+    // sort_by is stable so equally ranked workers keep their order
+    workers.sort_by(|a, b| {
+        let key = |worker: &Worker| match worker.report() {
+            Some(report) => (
+                !report.has_loaded(function.name(), revision),
+                false,
+                report.load(),
+            ),
+            None => (true, true, 0.0),
+        };
+        let (a, b) = (key(a), key(b));
+        a.0.cmp(&b.0).then(a.1.cmp(&b.1)).then(a.2.total_cmp(&b.2))
+    });
+    workers
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use api::{function::registration::Runtime, worker::WorkerReport};
+
+    use super::*;
+    use crate::functions::samples;
+
+    fn worker(address: &str, loaded: &[u32], in_flight: u32, runtimes: Vec<Runtime>) -> Worker {
+        let mut worker = Worker::new(address.to_string().into());
+        let revisions = loaded
+            .iter()
+            .map(|n| RevisionNumber::parse(&n.to_string()).unwrap())
+            .collect();
+        worker.update_report(WorkerReport::new(
+            BTreeMap::from([("add".to_string(), revisions)]),
+            in_flight,
+            Some(4),
+            None,
+            "24.0.0".to_string(),
+            runtimes,
+        ));
+        worker
+    }
+
+    #[test]
+    fn ranking() {
+        let add = samples()
+            .into_iter()
+            .find(|spec| spec.name == "add")
+            .unwrap();
+        let function = Function::new(add);
+        let all = vec![Runtime::Wasm, Runtime::Component, Runtime::HttpHandler];
+        let workers = vec![
+            Worker::new("unreported".to_string().into()),
+            worker("cold", &[], 0, all.clone()),
+            worker("busy", &[1], 3, all.clone()),
+            worker("components-only", &[1], 0, vec![Runtime::Component]),
+            worker("idle", &[1], 1, all.clone()),
+        ];
+        let ranked = rank(workers, &function, RevisionNumber::parse("1").unwrap())
+            .iter()
+            .map(|worker| worker.address().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(ranked, ["idle", "busy", "cold", "unreported"]);
+    }
+}
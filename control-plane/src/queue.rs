@@ -0,0 +1,300 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use api::worker::{Worker, WorkerStatus};
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use tokio::{sync::Notify, time::Instant};
+
+use crate::{metrics, workers::WorkerStore};
+
+/// Requests waiting for a worker with capacity in a FIFO queue per function, bounded per function.
+///
+/// Only the request at the head of a function's queue looks for a worker when one becomes available, handing over to
+/// the next one once it has found one, so that requests are served in order without waking every waiting request.
+#[derive(Clone)]
+pub struct Queue {
+    waiting: Arc<Mutex<Waiting>>,
+    max_depth: usize,
+    max_wait: Duration,
+}
+
+#[derive(Default)]
+struct Waiting {
+    next_id: u64,
+    functions: BTreeMap<String, VecDeque<(u64, Arc<Notify>)>>,
+}
+
+impl Waiting {
+    /// Wake the request at the head of every queue
+    fn wake_heads(&self) {
+        for queue in self.functions.values() {
+            if let Some((_, notify)) = queue.front() {
+                notify.notify_one();
+            }
+        }
+    }
+}
+
+/// Why a request couldn't wait for a worker
+#[derive(Debug)]
+pub enum QueueRejection {
+    Full,
+    TimedOut,
+    /// Workers are registered but `rank` leaves all of them out, e.g. none supports the function's runtime
+    NoEligibleWorker,
+}
+
+impl IntoResponse for QueueRejection {
+    fn into_response(self) -> Response {
+        let reason = match self {
+            QueueRejection::Full => "no worker available and the queue is full",
+            QueueRejection::TimedOut => "no worker became available in time",
+            QueueRejection::NoEligibleWorker => "no registered worker can serve the function",
+        };
+        (StatusCode::SERVICE_UNAVAILABLE, reason).into_response()
+    }
+}
+
+impl Queue {
+    /// A queue for the workers of the store, woken whenever one of them becomes available
+    pub fn new(workers: &WorkerStore, max_depth: usize, max_wait: Duration) -> Self {
+        let waiting = Arc::new(Mutex::new(Waiting::default()));
+        workers.on_available({
+            let waiting = Arc::downgrade(&waiting);
+            move || {
+                if let Some(waiting) = waiting.upgrade() {
+                    waiting.lock().unwrap().wake_heads();
+                }
+            }
+        });
+        Queue {
+            waiting,
+            max_depth,
+            max_wait,
+        }
+    }
+
+    /// When a request arriving now has to give up waiting
+    pub fn deadline(&self) -> Instant {
+        Instant::now() + self.max_wait
+    }
+
+    /// The available workers for the function, ordered by `rank`, waiting at the back of the function's queue until
+    /// the deadline if there are none or other requests are already waiting.
+    ///
+    /// The request is turned away rather than waiting, or holding up the requests behind it, when workers are
+    /// registered but `rank` leaves out every one of them whatever their status, as none of them would ever do.
+    pub async fn wait(
+        &self,
+        function: &str,
+        workers: &WorkerStore,
+        deadline: Instant,
+        rank: impl Fn(Vec<Worker>) -> Vec<Worker>,
+    ) -> Result<Vec<Worker>, QueueRejection> {
+        let eligible = || {
+            let registered = workers.list(None);
+            if !registered.is_empty() && rank(registered).is_empty() {
+                metrics::record_queue_rejection(function, "no_eligible_worker");
+                return Err(QueueRejection::NoEligibleWorker);
+            }
+            Ok(())
+        };
+        eligible()?;
+        if !self.is_waiting(function) {
+            let candidates = rank(workers.list(Some(&WorkerStatus::Available)));
+            if !candidates.is_empty() {
+                return Ok(candidates);
+            }
+        }
+        let slot = self.enter(function).ok_or_else(|| {
+            metrics::record_queue_rejection(function, "full");
+            QueueRejection::Full
+        })?;
+        let start = Instant::now();
+        loop {
+            if tokio::time::timeout_at(deadline, slot.notify.notified())
+                .await
+                .is_err()
+            {
+                metrics::record_queue_rejection(function, "timeout");
+                return Err(QueueRejection::TimedOut);
+            }
+            // the workers may have changed while waiting, e.g. the only eligible one was removed
+            eligible()?;
+            let candidates = rank(workers.list(Some(&WorkerStatus::Available)));
+            if !candidates.is_empty() {
+                metrics::record_queue_wait(function, start.elapsed());
+                return Ok(candidates);
+            }
+        }
+    }
+
+    fn is_waiting(&self, function: &str) -> bool {
+        self.waiting
+            .lock()
+            .unwrap()
+            .functions
+            .contains_key(function)
+    }
+
+    /// Join the back of the function's queue, the request at the head being woken right away in case a worker became
+    /// available while it was joining
+    fn enter(&self, function: &str) -> Option<Slot> {
+        let mut waiting = self.waiting.lock().unwrap();
+        let id = waiting.next_id;
+        let queue = waiting.functions.entry(function.to_string()).or_default();
+        if queue.len() >= self.max_depth {
+            if queue.is_empty() {
+                waiting.functions.remove(function);
+            }
+            return None;
+        }
+        let notify = Arc::new(Notify::new());
+        queue.push_back((id, notify.clone()));
+        metrics::record_queue_depth(function, queue.len());
+        if queue.len() == 1 {
+            notify.notify_one();
+        }
+        waiting.next_id += 1;
+        Some(Slot {
+            queue: self.clone(),
+            function: function.to_string(),
+            id,
+            notify,
+        })
+    }
+}
+
+/// A place in a function's queue, given up when dropped, including when the caller disconnects while waiting. The
+/// request behind it is woken when the head leaves, to look for a worker in turn.
+struct Slot {
+    queue: Queue,
+    function: String,
+    id: u64,
+    notify: Arc<Notify>,
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        let mut waiting = self.queue.waiting.lock().unwrap();
+        let Some(queue) = waiting.functions.get_mut(&self.function) else {
+            return;
+        };
+        let Some(position) = queue.iter().position(|(id, _)| *id == self.id) else {
+            return;
+        };
+        queue.remove(position);
+        metrics::record_queue_depth(&self.function, queue.len());
+        match queue.front() {
+            Some((_, next)) if position == 0 => next.notify_one(),
+            Some(_) => {}
+            None => {
+                waiting.functions.remove(&self.function);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn waits_for_capacity() {
+        let mut workers = WorkerStore::new();
+        let mut worker = Worker::new("127.0.0.1:3001".to_string().into());
+        worker.update_status(WorkerStatus::Occupied);
+        workers.insert(worker.clone());
+
+        let queue = Queue::new(&workers, 1, Duration::from_secs(5));
+        let waiting = tokio::spawn({
+            let (queue, workers) = (queue.clone(), workers.clone());
+            async move { queue.wait("add", &workers, queue.deadline(), |w| w).await }
+        });
+        tokio::task::yield_now().await;
+        // the queue holds a single request
+        let full = queue.wait("add", &workers, queue.deadline(), |w| w).await;
+        assert!(matches!(full, Err(QueueRejection::Full)));
+
+        worker.update_status(WorkerStatus::Available);
+        workers.update(&worker);
+        let candidates = waiting.await.unwrap().unwrap();
+        assert_eq!(candidates.len(), 1);
+
+        let empty = WorkerStore::new();
+        let timed_out = Queue::new(&empty, 1, Duration::from_millis(10))
+            .wait("add", &empty, Instant::now(), |w| w)
+            .await;
+        assert!(matches!(timed_out, Err(QueueRejection::TimedOut)));
+    }
+
+    #[tokio::test]
+    async fn arrivals_wait_behind_queued_requests() {
+        let mut workers = WorkerStore::new();
+        let queue = Queue::new(&workers, 2, Duration::from_secs(5));
+        let (served_tx, mut served) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn({
+            let (queue, workers, served_tx) = (queue.clone(), workers.clone(), served_tx.clone());
+            async move {
+                queue
+                    .wait("add", &workers, queue.deadline(), |w| w)
+                    .await
+                    .unwrap();
+                served_tx.send("queued").unwrap();
+            }
+        });
+        tokio::task::yield_now().await;
+
+        workers.insert(Worker::new("127.0.0.1:3001".to_string().into()));
+        // a worker is available, but the request already waiting goes first
+        queue
+            .wait("add", &workers, queue.deadline(), |w| w)
+            .await
+            .unwrap();
+        served_tx.send("arrived").unwrap();
+        assert_eq!(served.recv().await, Some("queued"));
+        assert_eq!(served.recv().await, Some("arrived"));
+        assert!(!queue.is_waiting("add"));
+    }
+
+    #[tokio::test]
+    async fn ineligible_requests_dont_hold_up_the_queue() {
+        let mut workers = WorkerStore::new();
+        let mut occupied = Worker::new("127.0.0.1:3001".to_string().into());
+        occupied.update_status(WorkerStatus::Occupied);
+        workers.insert(occupied.clone());
+        let queue = Queue::new(&workers, 2, Duration::from_secs(5));
+        let none = |_: Vec<Worker>| Vec::new();
+        let rejected = queue.wait("add", &workers, queue.deadline(), none).await;
+        assert!(matches!(rejected, Err(QueueRejection::NoEligibleWorker)));
+        assert!(!queue.is_waiting("add"));
+
+        // the only worker able to serve the waiting request is removed, it leaves the queue when woken
+        let waiting = tokio::spawn({
+            let (queue, workers) = (queue.clone(), workers.clone());
+            async move {
+                let only_occupied = |w: Vec<Worker>| {
+                    w.into_iter()
+                        .filter(|w| w.address().to_string() == "127.0.0.1:3001")
+                        .collect()
+                };
+                queue
+                    .wait("add", &workers, queue.deadline(), only_occupied)
+                    .await
+            }
+        });
+        tokio::task::yield_now().await;
+        assert!(queue.is_waiting("add"));
+        workers.remove(occupied.id());
+        workers.insert(Worker::new("127.0.0.1:3002".to_string().into()));
+        let rejected = waiting.await.unwrap();
+        assert!(matches!(rejected, Err(QueueRejection::NoEligibleWorker)));
+        assert!(!queue.is_waiting("add"));
+    }
+}
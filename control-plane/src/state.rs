@@ -0,0 +1,121 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+/// A JSON document the control plane keeps across restarts, stored as `<dir>/<name>.json` or not at all
+#[derive(Clone, Debug)]
+pub struct StateFile {
+    path: Option<PathBuf>,
+    writer: Arc<Mutex<Writer>>,
+}
+
+/// The document waiting to be written in the background, a single write being in progress at a time
+#[derive(Default, Debug)]
+struct Writer {
+    next: Option<Vec<u8>>,
+    writing: bool,
+}
+
+impl StateFile {
+    pub fn open(dir: &Path, name: &str) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        Ok(StateFile {
+            path: Some(dir.join(format!("{name}.json"))),
+            writer: Arc::default(),
+        })
+    }
+    pub fn memory() -> Self {
+        StateFile {
+            path: None,
+            writer: Arc::default(),
+        }
+    }
+
+    /// The stored document, or the default one if it hasn't been saved yet
+    pub fn load<T: DeserializeOwned + Default>(&self) -> anyhow::Result<T> {
+        let Some(path) = &self.path else {
+            return Ok(T::default());
+        };
+        match std::fs::read(path) {
+            Ok(json) => Ok(serde_json::from_slice(&json)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(T::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Replace the stored document, a crash while saving leaves the previous one in place
+    pub fn save<T: Serialize>(&self, value: &T) -> anyhow::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        write(path, &serde_json::to_vec_pretty(value)?)
+    }
+
+    /// Replace the stored document from a blocking thread of the current runtime rather than the caller's, documents
+    /// saved while one is being written replacing each other so that only the latest one is written next
+    pub fn save_in_background<T: Serialize>(&self, value: &T) -> anyhow::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let json = serde_json::to_vec_pretty(value)?;
+        let mut writer = self.writer.lock().unwrap();
+        writer.next = Some(json);
+        if writer.writing {
+            return Ok(());
+        }
+        writer.writing = true;
+        let (path, writer) = (path.clone(), self.writer.clone());
+        tokio::task::spawn_blocking(move || loop {
+            let json = {
+                let mut writer = writer.lock().unwrap();
+                match writer.next.take() {
+                    Some(json) => json,
+                    None => {
+                        writer.writing = false;
+                        return;
+                    }
+                }
+            };
+            if let Err(e) = write(&path, &json) {
+                tracing::error!(error = ?e, path = %path.display(), "failed to save state");
+            }
+        });
+        Ok(())
+    }
+}
+
+fn write(path: &Path, json: &[u8]) -> anyhow::Result<()> {
+    let tmp = path.with_extension(format!("tmp.{}", std::process::id()));
+    std::fs::write(&tmp, json)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn saves_the_latest_document_in_background() {
+        let dir = std::env::temp_dir().join(format!("wasi-faas-state-{}", api::types::Id::new()));
+        let state = StateFile::open(&dir, "numbers").unwrap();
+        for n in 0..100 {
+            state.save_in_background(&vec![n]).unwrap();
+        }
+        let mut saved = Vec::<u32>::new();
+        for _ in 0..100 {
+            saved = state.load().unwrap();
+            if saved == [99] {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(saved, [99]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
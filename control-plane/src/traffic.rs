@@ -0,0 +1,330 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+
+use api::{
+    function::{
+        execution::Execution,
+        registration::{Function, FunctionId, RevisionNumber, SplitMode, TrafficSplit},
+    },
+    types::ExitKind,
+};
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    Json,
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::functions::FunctionStore;
+
+/// The revision serving a request, picked from the function's traffic split if it has one
+pub fn pick_revision(function: &Function, headers: &HeaderMap) -> RevisionNumber {
+    match function.traffic() {
+        Some(split) => pick(split, headers),
+        None => function.current_revision().number(),
+    }
+}
+
+/// Pick the revision serving a request from the split. Sticky keys are hashed with SHA-256 so that a key keeps its
+/// revision across restarts and builds of the control plane.
+pub fn pick(split: &TrafficSplit, headers: &HeaderMap) -> RevisionNumber {
+    let sticky_key = match &split.mode {
+        SplitMode::Sticky { header } => headers.get(header.as_str()),
+        SplitMode::Random => None,
+    };
+    let point = match sticky_key {
+        Some(key) => {
+            let digest = Sha256::digest(key.as_bytes());
+            u64::from_be_bytes(
+                digest[..8]
+                    .try_into()
+                    .expect("a SHA-256 digest has 32 bytes"),
+            )
+        }
+        None => rand::random(),
+    };
+    split.pick(point)
+}
+
+/// The latest outcomes of a revision under a split with auto rollback
+struct Window {
+    /// The split the outcomes were recorded under, they are forgotten when it changes
+    split: TrafficSplit,
+    /// Whether each execution failed, the oldest first
+    failures: VecDeque<bool>,
+}
+
+/// Tracks how the revisions of traffic splits with auto rollback are doing and rolls a function back when one of them
+/// fails too often.
+///
+/// Each revision is judged on its latest `min_executions` completed executions since the split was set, so that neither
+/// older executions nor the other revisions' are looked at.
+#[derive(Clone, Default)]
+pub struct RollbackMonitor {
+    windows: Arc<Mutex<BTreeMap<(FunctionId, RevisionNumber), Window>>>,
+}
+
+impl RollbackMonitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the completed execution and roll the function back if its revision is failing more often than the
+    /// split allows
+    pub fn record(&self, functions: &mut FunctionStore, completed: &Execution) {
+        let function_id = *completed.request().target_function();
+        let revision = completed.request().revision();
+        let Some(result) = completed.result() else {
+            return;
+        };
+        let Some(function) = functions.get(&function_id) else {
+            self.forget(&function_id);
+            return;
+        };
+        let Some(split) = function.traffic() else {
+            self.forget(&function_id);
+            return;
+        };
+        let Some(rollback) = split.auto_rollback else {
+            self.forget(&function_id);
+            return;
+        };
+        if revision == rollback.to || !split.revisions.iter().any(|r| r.revision == revision) {
+            return;
+        }
+
+        let size = rollback.min_executions.max(1) as usize;
+        let failure = *result.exit() != ExitKind::Success || result.error().is_some();
+        let failure_rate = {
+            let mut windows = self.windows.lock().unwrap();
+            // a changed split, or a function registered again under the same id, starts from scratch
+            windows.retain(|(id, _), window| *id != function_id || &window.split == split);
+            let window = windows
+                .entry((function_id, revision))
+                .or_insert_with(|| Window {
+                    split: split.clone(),
+                    failures: VecDeque::with_capacity(size),
+                });
+            window.failures.push_back(failure);
+            while window.failures.len() > size {
+                window.failures.pop_front();
+            }
+            if window.failures.len() < size {
+                return;
+            }
+            let failed = window.failures.iter().filter(|failed| **failed).count();
+            failed as f64 / size as f64
+        };
+        if failure_rate <= rollback.max_failure_rate {
+            return;
+        }
+
+        let name = function.name();
+        let rolled_back = functions.modify(name, |function| {
+            // the split may have been changed since it was read
+            if function.traffic() != Some(split) {
+                return Ok(false);
+            }
+            if function.set_current_revision(rollback.to).is_none() {
+                return Err(format!("revision {} doesn't exist", rollback.to));
+            }
+            function
+                .set_traffic(None)
+                .expect("clearing the split can't fail");
+            Ok(true)
+        });
+        match rolled_back {
+            Some(Ok(true)) => {
+                self.forget(&function_id);
+                tracing::warn!(
+                    function = name,
+                    %revision,
+                    failure_rate,
+                    "rolled back to revision {}",
+                    rollback.to
+                );
+            }
+            Some(Err(e)) => tracing::error!(
+                function = name,
+                %revision,
+                failure_rate,
+                "can't roll back to revision {}: {e}",
+                rollback.to
+            ),
+            Some(Ok(false)) | None => {}
+        }
+    }
+
+    fn forget(&self, function: &FunctionId) {
+        self.windows
+            .lock()
+            .unwrap()
+            .retain(|(id, _), _| id != function);
+    }
+}
+
+#[tracing::instrument(skip(store))]
+pub async fn set_traffic(
+    State(mut store): State<FunctionStore>,
+    Path(name): Path<String>,
+    Json(split): Json<TrafficSplit>,
+) -> Result<Json<Function>, (StatusCode, String)> {
+    store
+        .modify(&name, |function| {
+            function.set_traffic(Some(split)).map(|()| function.clone())
+        })
+        .ok_or((StatusCode::NOT_FOUND, format!("function {name} not found")))?
+        .map(Json)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))
+}
+
+#[tracing::instrument(skip(store))]
+pub async fn delete_traffic(
+    State(mut store): State<FunctionStore>,
+    Path(name): Path<String>,
+) -> Result<Json<Function>, StatusCode> {
+    store
+        .modify(&name, |function| {
+            function
+                .set_traffic(None)
+                .expect("clearing the split can't fail");
+            function.clone()
+        })
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+#[cfg(test)]
+mod tests {
+    use api::{
+        function::{
+            execution::{ExecutionRequest, ExecutionResult, Trigger},
+            registration::{AutoRollback, WeightedRevision},
+        },
+        types::{Id, TimeStamp},
+        worker::WorkerId,
+    };
+
+    use super::*;
+    use crate::{functions, state::StateFile};
+
+    /// A function with a canary revision 2 which is rolled back to revision 1 once two of its last three executions
+    /// have failed
+    fn canary() -> (FunctionStore, Function) {
+        let mut function = Function::new(functions::samples().remove(1));
+        let blob = function.current_revision().blob_address().clone();
+        function.add_revision(blob, String::new());
+        function.set_current_revision(RevisionNumber::parse("1").unwrap());
+        let revision = |n: &str, weight| WeightedRevision {
+            revision: RevisionNumber::parse(n).unwrap(),
+            weight,
+        };
+        function
+            .set_traffic(Some(TrafficSplit {
+                revisions: vec![revision("1", 90), revision("2", 10)],
+                mode: SplitMode::Random,
+                auto_rollback: Some(AutoRollback {
+                    to: RevisionNumber::parse("1").unwrap(),
+                    max_failure_rate: 0.5,
+                    min_executions: 3,
+                }),
+            }))
+            .unwrap();
+        let mut store = FunctionStore::open(StateFile::memory()).unwrap();
+        store.insert(function.clone());
+        (store, function)
+    }
+
+    fn completed(function: &Function, revision: &str, failed: bool) -> Execution {
+        let mut execution = Execution::new(ExecutionRequest::new(
+            *function.id(),
+            RevisionNumber::parse(revision).unwrap(),
+            None,
+            Trigger::Http,
+        ));
+        let exit = if failed {
+            ExitKind::Failure { exit_code: 1 }
+        } else {
+            ExitKind::Success
+        };
+        let worker = WorkerId::parse(&Id::new().to_string()).unwrap();
+        execution.complete(ExecutionResult::new(
+            TimeStamp::now(),
+            Ok(None),
+            exit,
+            worker,
+            vec![],
+        ));
+        execution
+    }
+
+    #[test]
+    fn sticky_keys_keep_their_revision() {
+        let (_, mut function) = canary();
+        let mut split = function.traffic().unwrap().clone();
+        split.mode = SplitMode::Sticky {
+            header: "x-user".to_string(),
+        };
+        function.set_traffic(Some(split)).unwrap();
+        let pick = |user: &'static str| {
+            let headers =
+                HeaderMap::from_iter([("x-user".parse().unwrap(), user.parse().unwrap())]);
+            pick_revision(&function, &headers).to_string()
+        };
+        // the first 8 bytes of the keys' SHA-256 digests land at 7 and 91 out of 100
+        for _ in 0..10 {
+            assert_eq!(pick("alice"), "1");
+            assert_eq!(pick("grace"), "2");
+        }
+    }
+
+    #[test]
+    fn rolls_back_a_failing_canary() {
+        let (mut functions, function) = canary();
+        let monitor = RollbackMonitor::new();
+        // the stable revision's failures don't count against the canary
+        for _ in 0..3 {
+            monitor.record(&mut functions, &completed(&function, "1", true));
+        }
+        monitor.record(&mut functions, &completed(&function, "2", true));
+        monitor.record(&mut functions, &completed(&function, "2", true));
+        // not judged before completing min_executions
+        assert!(functions.get(function.id()).unwrap().traffic().is_some());
+        monitor.record(&mut functions, &completed(&function, "2", false));
+        let rolled_back = functions.get(function.id()).unwrap();
+        assert!(rolled_back.traffic().is_none());
+        assert_eq!(rolled_back.current_revision().number().to_string(), "1");
+    }
+
+    #[test]
+    fn judges_the_latest_executions() {
+        let (mut functions, function) = canary();
+        let monitor = RollbackMonitor::new();
+        for _ in 0..6 {
+            monitor.record(&mut functions, &completed(&function, "2", false));
+        }
+        monitor.record(&mut functions, &completed(&function, "2", true));
+        assert!(functions.get(function.id()).unwrap().traffic().is_some());
+        // two failures out of the last three, even though most executions since the split succeeded
+        monitor.record(&mut functions, &completed(&function, "2", true));
+        assert!(functions.get(function.id()).unwrap().traffic().is_none());
+    }
+
+    #[test]
+    fn forgets_outcomes_when_the_split_changes() {
+        let (mut functions, function) = canary();
+        let monitor = RollbackMonitor::new();
+        monitor.record(&mut functions, &completed(&function, "2", true));
+        monitor.record(&mut functions, &completed(&function, "2", true));
+        functions.modify(function.name(), |function| {
+            let mut split = function.traffic().unwrap().clone();
+            split.revisions[1].weight = 20;
+            function.set_traffic(Some(split)).unwrap();
+        });
+        monitor.record(&mut functions, &completed(&function, "2", true));
+        assert!(functions.get(function.id()).unwrap().traffic().is_some());
+    }
+}
@@ -0,0 +1,623 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    io::Write,
+    path::Path as FilePath,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use api::{
+    function::execution::{Execution, Trigger},
+    trigger::{
+        DeliveryId, HmacAlgorithm, SignatureFormat, Webhook, WebhookId, WebhookSignature,
+        WebhookSpec,
+    },
+};
+use axum::{
+    body::Bytes,
+    extract::{Path, RawQuery, State},
+    http::{HeaderMap, Method, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    XChaCha20Poly1305, XNonce,
+};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde_json::Value;
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+
+use crate::{
+    api_gateway::{self, GatewayState, Invocation},
+    executions::ExecutionStore,
+    functions::FunctionStore,
+    metrics,
+    pending::Pending,
+    state::StateFile,
+};
+
+/// Prefix of the webhook secrets encrypted with the secret key, secrets saved before they were encrypted have none
+const SEALED_PREFIX: &str = "sealed:";
+
+/// The key webhook secrets are encrypted with before they are saved, kept in its own file so that the state directory
+/// alone doesn't give the secrets away
+#[derive(Clone)]
+pub struct SecretKey(Arc<XChaCha20Poly1305>);
+
+impl SecretKey {
+    /// Read the key from `path`, generating it on first use with permissions restricted to the control plane's user
+    pub fn open(path: &FilePath) -> anyhow::Result<Self> {
+        let key = match std::fs::read(path) {
+            Ok(key) => key,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                if let Some(dir) = path.parent() {
+                    std::fs::create_dir_all(dir)?;
+                }
+                let key = XChaCha20Poly1305::generate_key(&mut OsRng);
+                let mut options = std::fs::OpenOptions::new();
+                options.write(true).create_new(true);
+                #[cfg(unix)]
+                std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+                options.open(path)?.write_all(&key)?;
+                key.to_vec()
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let cipher = XChaCha20Poly1305::new_from_slice(&key)
+            .map_err(|_| anyhow::anyhow!("{} isn't a 32 byte key", path.display()))?;
+        Ok(SecretKey(Arc::new(cipher)))
+    }
+    /// A key which isn't kept, for state which isn't either
+    pub fn ephemeral() -> Self {
+        SecretKey(Arc::new(XChaCha20Poly1305::new(
+            &XChaCha20Poly1305::generate_key(&mut OsRng),
+        )))
+    }
+    fn seal(&self, secret: &str) -> String {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let sealed = self
+            .0
+            .encrypt(&nonce, secret.as_bytes())
+            .expect("encrypting in memory doesn't fail");
+        format!(
+            "{SEALED_PREFIX}{}",
+            STANDARD.encode([&nonce[..], &sealed].concat())
+        )
+    }
+    fn unseal(&self, stored: &str) -> anyhow::Result<String> {
+        let Some(sealed) = stored.strip_prefix(SEALED_PREFIX) else {
+            return Ok(stored.to_string());
+        };
+        let sealed = STANDARD.decode(sealed)?;
+        if sealed.len() < 24 {
+            anyhow::bail!("sealed secret is too short");
+        }
+        let (nonce, ciphertext) = sealed.split_at(24);
+        let secret = self
+            .0
+            .decrypt(XNonce::from_slice(nonce), ciphertext)
+            .map_err(|_| anyhow::anyhow!("secret wasn't sealed with this key"))?;
+        Ok(String::from_utf8(secret)?)
+    }
+}
+
+/// The webhooks, saved on every change so that their URLs keep working after a restart. Their secrets are saved
+/// encrypted with the secret key.
+#[derive(Clone)]
+pub struct WebhookStore {
+    inner: Arc<Mutex<BTreeMap<String, Webhook>>>,
+    state: StateFile,
+    key: SecretKey,
+}
+
+impl WebhookStore {
+    pub fn open(state: StateFile, key: SecretKey) -> anyhow::Result<Self> {
+        let mut webhooks = state.load::<BTreeMap<String, Webhook>>()?;
+        let mut plaintext = false;
+        for webhook in webhooks.values_mut() {
+            if let Some(signature) = webhook.signature() {
+                plaintext |= !signature.secret.starts_with(SEALED_PREFIX);
+                *webhook = webhook.with_secret(key.unseal(&signature.secret)?);
+            }
+        }
+        let store = WebhookStore {
+            inner: Arc::new(Mutex::new(webhooks)),
+            state,
+            key,
+        };
+        if plaintext {
+            tracing::info!("encrypting the webhook secrets saved in plaintext");
+            store.save(&store.inner.lock().unwrap());
+        }
+        Ok(store)
+    }
+    /// Insert the webhook unless another one is already registered under the same name
+    pub fn insert(&mut self, webhook: Webhook) -> Option<Webhook> {
+        let mut webhooks = self.inner.lock().unwrap();
+        if webhooks.contains_key(webhook.name()) {
+            return None;
+        }
+        webhooks.insert(webhook.name().to_string(), webhook.clone());
+        self.save(&webhooks);
+        Some(webhook)
+    }
+    pub fn list(&self) -> Vec<Webhook> {
+        self.inner.lock().unwrap().values().cloned().collect()
+    }
+    pub fn get(&self, name: &str) -> Option<Webhook> {
+        self.inner.lock().unwrap().get(name).cloned()
+    }
+    pub fn get_by_id(&self, id: &WebhookId) -> Option<Webhook> {
+        self.inner
+            .lock()
+            .unwrap()
+            .values()
+            .find(|w| w.id() == id)
+            .cloned()
+    }
+    pub fn remove(&mut self, name: &str) -> Option<Webhook> {
+        let mut webhooks = self.inner.lock().unwrap();
+        let webhook = webhooks.remove(name)?;
+        self.save(&webhooks);
+        Some(webhook)
+    }
+    fn save(&self, webhooks: &BTreeMap<String, Webhook>) {
+        let sealed = webhooks
+            .iter()
+            .map(|(name, webhook)| {
+                let sealed = match webhook.signature() {
+                    Some(signature) => webhook.with_secret(self.key.seal(&signature.secret)),
+                    None => webhook.clone(),
+                };
+                (name, sealed)
+            })
+            .collect::<BTreeMap<_, _>>();
+        if let Err(e) = self.state.save(&sealed) {
+            tracing::error!(error = ?e, "failed to save the webhooks");
+        }
+    }
+}
+
+#[derive(Default)]
+struct Seen {
+    /// When each delivery was first seen, by webhook and delivery id
+    times: BTreeMap<(String, String), Instant>,
+    /// The deliveries in the order they were seen, so that they expire oldest first
+    order: VecDeque<(Instant, (String, String))>,
+}
+
+/// The delivery ids seen by each webhook during the dedupe window
+#[derive(Clone)]
+pub struct Deliveries {
+    seen: Arc<Mutex<Seen>>,
+    window: Duration,
+}
+
+impl Deliveries {
+    pub fn new(window: Duration) -> Self {
+        Deliveries {
+            seen: Arc::default(),
+            window,
+        }
+    }
+    /// Record the delivery, returning whether the webhook hasn't seen it during the window
+    pub fn first_seen(&self, webhook: &str, delivery: &str) -> bool {
+        let mut seen = self.seen.lock().unwrap();
+        let now = Instant::now();
+        while let Some((time, _)) = seen.order.front() {
+            if now.duration_since(*time) < self.window {
+                break;
+            }
+            let (time, key) = seen.order.pop_front().unwrap();
+            // the delivery may have been forgotten and seen again since
+            if seen.times.get(&key) == Some(&time) {
+                seen.times.remove(&key);
+            }
+        }
+        let key = (webhook.to_string(), delivery.to_string());
+        if seen.times.contains_key(&key) {
+            return false;
+        }
+        seen.times.insert(key.clone(), now);
+        seen.order.push_back((now, key));
+        true
+    }
+    /// Forget the delivery so that a redelivery invokes the function again, e.g. after its invocation failed
+    pub fn forget(&self, webhook: &str, delivery: &str) {
+        self.seen
+            .lock()
+            .unwrap()
+            .times
+            .remove(&(webhook.to_string(), delivery.to_string()));
+    }
+}
+
+/// Check that the body was signed with the webhook's secret, `now` bounding the age of timestamped signatures
+pub fn verify(
+    signature: &WebhookSignature,
+    headers: &HeaderMap,
+    body: &[u8],
+    now: &DateTime<Utc>,
+) -> Result<(), String> {
+    let value = headers
+        .get(&signature.header)
+        .and_then(|value| value.to_str().ok())
+        .ok_or_else(|| format!("missing {} header", signature.header))?;
+    let valid = match signature.format {
+        SignatureFormat::Github => {
+            let hex = match value.split_once('=') {
+                Some((prefix, hex)) if prefix == signature.algorithm.name() => hex,
+                Some((prefix, _)) => {
+                    return Err(format!(
+                        "expected a {} signature, got {prefix}",
+                        signature.algorithm.name()
+                    ))
+                }
+                None => value,
+            };
+            matches(signature, body, hex)
+        }
+        SignatureFormat::Stripe { tolerance_secs } => {
+            let mut timestamp = None;
+            let mut candidates = vec![];
+            for part in value.split(',') {
+                match part.trim().split_once('=') {
+                    Some(("t", t)) => timestamp = t.parse::<i64>().ok(),
+                    Some(("v1", hex)) => candidates.push(hex),
+                    _ => {}
+                }
+            }
+            let timestamp = timestamp.ok_or("missing signature timestamp")?;
+            if (now.timestamp() - timestamp).unsigned_abs() > tolerance_secs {
+                return Err("signature timestamp is outside the tolerance".to_string());
+            }
+            let payload = [timestamp.to_string().as_bytes(), b".", body].concat();
+            candidates
+                .into_iter()
+                .any(|hex| matches(signature, &payload, hex))
+        }
+    };
+    if valid {
+        Ok(())
+    } else {
+        Err("signature mismatch".to_string())
+    }
+}
+
+/// Compare the HMAC of the payload with the hex signature in constant time
+fn matches(signature: &WebhookSignature, payload: &[u8], hex: &str) -> bool {
+    let Ok(expected) = hex::decode(hex) else {
+        return false;
+    };
+    let secret = signature.secret.as_bytes();
+    match signature.algorithm {
+        HmacAlgorithm::Sha1 => verify_mac::<Hmac<Sha1>>(secret, payload, &expected),
+        HmacAlgorithm::Sha256 => verify_mac::<Hmac<Sha256>>(secret, payload, &expected),
+        HmacAlgorithm::Sha512 => verify_mac::<Hmac<Sha512>>(secret, payload, &expected),
+    }
+}
+
+fn verify_mac<M: Mac + hmac::digest::KeyInit>(
+    secret: &[u8],
+    payload: &[u8],
+    expected: &[u8],
+) -> bool {
+    let mut mac = <M as Mac>::new_from_slice(secret).expect("HMAC takes keys of any length");
+    mac.update(payload);
+    mac.verify_slice(expected).is_ok()
+}
+
+/// The id of the delivery from its header or body
+fn delivery(delivery_id: &DeliveryId, headers: &HeaderMap, body: &[u8]) -> Option<String> {
+    match delivery_id {
+        DeliveryId::Header(name) => headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string),
+        DeliveryId::Field(pointer) => {
+            let body = serde_json::from_slice::<Value>(body).ok()?;
+            match body.pointer(pointer)? {
+                Value::String(id) => Some(id.clone()),
+                id => Some(id.to_string()),
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct WebhookState {
+    pub webhooks: WebhookStore,
+    pub deliveries: Deliveries,
+    pub gateway: GatewayState,
+}
+
+/// Accept a delivery once its signature is verified and invoke the function with it in the background, the sender
+/// only being told whether the delivery was accepted.
+///
+/// The function receives the delivery's headers and body as sent, a redelivery of an id seen during the dedupe window
+/// is accepted without invoking it again unless the function failed on it.
+#[tracing::instrument(skip(state, headers, body))]
+pub async fn receive(
+    State(state): State<WebhookState>,
+    Path(id): Path<WebhookId>,
+    RawQuery(query): RawQuery,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let Some(webhook) = state.webhooks.get_by_id(&id) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    if let Some(signature) = webhook.signature() {
+        if let Err(e) = verify(signature, &headers, &body, &Utc::now()) {
+            tracing::warn!(webhook = webhook.name(), error = e, "rejecting delivery");
+            metrics::record_webhook_delivery(webhook.name(), "rejected");
+            return (StatusCode::UNAUTHORIZED, e).into_response();
+        }
+    }
+    let delivery = webhook
+        .delivery_id()
+        .and_then(|delivery_id| delivery(delivery_id, &headers, &body));
+    if let Some(delivery) = &delivery {
+        if !state.deliveries.first_seen(webhook.name(), delivery) {
+            tracing::info!(webhook = webhook.name(), delivery, "ignoring redelivery");
+            metrics::record_webhook_delivery(webhook.name(), "duplicate");
+            return StatusCode::ACCEPTED.into_response();
+        }
+    }
+    let seen = delivery.clone();
+    let invocation = Invocation {
+        function: webhook.function().to_string(),
+        path: None,
+        query,
+        method: Method::POST,
+        headers,
+        body,
+        trigger: Trigger::Webhook {
+            webhook: webhook.name().to_string(),
+            delivery,
+        },
+    };
+    let pending = Pending {
+        request: invocation.into_async(),
+        replaying: None,
+    };
+    let Ok(invoked) = api_gateway::spawn_async(state.gateway, pending) else {
+        tracing::warn!(
+            webhook = webhook.name(),
+            "too many asynchronous requests pending"
+        );
+        if let Some(delivery) = &seen {
+            state.deliveries.forget(webhook.name(), delivery);
+        }
+        return StatusCode::SERVICE_UNAVAILABLE.into_response();
+    };
+    metrics::record_webhook_delivery(webhook.name(), "accepted");
+    tokio::spawn(async move {
+        if invoked.await.is_ok_and(|outcome| outcome.succeeded) {
+            tracing::info!(webhook = webhook.name(), "webhook invocation completed");
+            metrics::record_webhook_delivery(webhook.name(), "succeeded");
+        } else {
+            tracing::warn!(webhook = webhook.name(), "webhook invocation failed");
+            metrics::record_webhook_delivery(webhook.name(), "failed");
+            // a redelivery of a delivery which failed is invoked again
+            if let Some(delivery) = &seen {
+                state.deliveries.forget(webhook.name(), delivery);
+            }
+        }
+    });
+    StatusCode::ACCEPTED.into_response()
+}
+
+#[tracing::instrument(skip(webhooks))]
+pub async fn list_webhooks(State(webhooks): State<WebhookStore>) -> Json<Vec<Webhook>> {
+    Json(webhooks.list().iter().map(Webhook::redacted).collect())
+}
+
+/// Register a webhook, its deliveries being received on `/hooks/{id}`
+#[tracing::instrument(skip(webhooks, functions, spec))]
+pub async fn create_webhook(
+    State((mut webhooks, functions)): State<(WebhookStore, FunctionStore)>,
+    Json(spec): Json<WebhookSpec>,
+) -> Result<Json<Webhook>, (StatusCode, String)> {
+    let function = spec
+        .function
+        .split_once('@')
+        .map_or(spec.function.as_str(), |(name, _)| name);
+    if functions.get_by_name(function).is_none() {
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!("function {function} not found"),
+        ));
+    }
+    let name = spec.name.clone();
+    webhooks
+        .insert(Webhook::new(spec))
+        .map(|webhook| Json(webhook.redacted()))
+        .ok_or((
+            StatusCode::CONFLICT,
+            format!("webhook {name} already exists"),
+        ))
+}
+
+#[tracing::instrument(skip(webhooks))]
+pub async fn get_webhook(
+    State(webhooks): State<WebhookStore>,
+    Path(name): Path<String>,
+) -> Result<Json<Webhook>, StatusCode> {
+    webhooks
+        .get(&name)
+        .map(|webhook| Json(webhook.redacted()))
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+#[tracing::instrument(skip(webhooks))]
+pub async fn delete_webhook(
+    State(mut webhooks): State<WebhookStore>,
+    Path(name): Path<String>,
+) -> Result<Json<Webhook>, StatusCode> {
+    webhooks
+        .remove(&name)
+        .map(|webhook| Json(webhook.redacted()))
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// The executions started by the webhook's deliveries, oldest first
+#[tracing::instrument(skip(webhooks, executions))]
+pub async fn list_webhook_runs(
+    State((webhooks, executions)): State<(WebhookStore, ExecutionStore)>,
+    Path(name): Path<String>,
+) -> Result<Json<Vec<Execution>>, StatusCode> {
+    webhooks.get(&name).ok_or(StatusCode::NOT_FOUND)?;
+    let mut runs = executions
+        .list(None)
+        .into_iter()
+        .filter(|e| matches!(e.request().trigger(), Trigger::Webhook { webhook, .. } if *webhook == name))
+        .collect::<Vec<_>>();
+    runs.sort_by(|a, b| a.request().create_time().cmp(b.request().create_time()));
+    Ok(Json(runs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signature(
+        header: &str,
+        algorithm: HmacAlgorithm,
+        format: SignatureFormat,
+    ) -> WebhookSignature {
+        WebhookSignature {
+            secret: "It's a Secret to Everybody".to_string(),
+            header: header.to_string(),
+            algorithm,
+            format,
+        }
+    }
+
+    fn headers(name: &'static str, value: &str) -> HeaderMap {
+        HeaderMap::from_iter([(name.parse().unwrap(), value.parse().unwrap())])
+    }
+
+    #[test]
+    fn verifies_signatures() {
+        let now = Utc::now();
+        let github = signature(
+            "x-hub-signature-256",
+            HmacAlgorithm::Sha256,
+            SignatureFormat::Github,
+        );
+        let signed = headers(
+            "x-hub-signature-256",
+            "sha256=757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17",
+        );
+        assert_eq!(verify(&github, &signed, b"Hello, World!", &now), Ok(()));
+        assert!(verify(&github, &signed, b"Hello, World?", &now).is_err());
+        assert!(verify(&github, &HeaderMap::new(), b"Hello, World!", &now).is_err());
+
+        let sha1 = signature(
+            "x-hub-signature",
+            HmacAlgorithm::Sha1,
+            SignatureFormat::Github,
+        );
+        let signed = headers(
+            "x-hub-signature",
+            "sha1=01dc10d0c83e72ed246219cdd91669667fe2ca59",
+        );
+        assert_eq!(verify(&sha1, &signed, b"Hello, World!", &now), Ok(()));
+        // the prefix has to name the webhook's algorithm
+        let mislabelled = headers(
+            "x-hub-signature",
+            "sha256=01dc10d0c83e72ed246219cdd91669667fe2ca59",
+        );
+        assert!(verify(&sha1, &mislabelled, b"Hello, World!", &now).is_err());
+        let unlabelled = headers(
+            "x-hub-signature",
+            "01dc10d0c83e72ed246219cdd91669667fe2ca59",
+        );
+        assert_eq!(verify(&sha1, &unlabelled, b"Hello, World!", &now), Ok(()));
+
+        let mut stripe = signature(
+            "stripe-signature",
+            HmacAlgorithm::Sha256,
+            SignatureFormat::Stripe {
+                tolerance_secs: 300,
+            },
+        );
+        stripe.secret = "whsec_test".to_string();
+        let signed = headers(
+            "stripe-signature",
+            "t=1700000000,v1=bad,v1=c89214b5b5da833daed6f0b8c5bb6bd58cea9022bd80ccc78230f3942d632925",
+        );
+        let body = br#"{"id":"evt_1"}"#;
+        let sent = DateTime::from_timestamp(1_700_000_100, 0).unwrap();
+        assert_eq!(verify(&stripe, &signed, body, &sent), Ok(()));
+        let replayed = DateTime::from_timestamp(1_700_001_000, 0).unwrap();
+        assert!(verify(&stripe, &signed, body, &replayed).is_err());
+    }
+
+    #[test]
+    fn dedupes_deliveries() {
+        let deliveries = Deliveries::new(Duration::from_millis(50));
+        assert!(deliveries.first_seen("github", "1"));
+        assert!(!deliveries.first_seen("github", "1"));
+        assert!(deliveries.first_seen("stripe", "1"));
+        assert!(deliveries.first_seen("github", "2"));
+
+        // a failed delivery is forgotten so that its redelivery is invoked
+        deliveries.forget("github", "2");
+        assert!(deliveries.first_seen("github", "2"));
+        assert!(!deliveries.first_seen("github", "2"));
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(deliveries.first_seen("github", "1"));
+        let seen = deliveries.seen.lock().unwrap();
+        assert_eq!(seen.times.len(), 1);
+        assert_eq!(seen.order.len(), 1);
+    }
+
+    #[test]
+    fn seals_secrets() {
+        let dir =
+            std::env::temp_dir().join(format!("wasi-faas-webhooks-{}", api::types::Id::new()));
+        let key = SecretKey::open(&dir.join("webhooks.key")).unwrap();
+        let sealed = key.seal("It's a Secret to Everybody");
+        assert!(sealed.starts_with(SEALED_PREFIX));
+        assert!(!sealed.contains("Secret"));
+        assert_ne!(key.seal("It's a Secret to Everybody"), sealed);
+        assert_eq!(key.unseal(&sealed).unwrap(), "It's a Secret to Everybody");
+        assert_eq!(
+            key.unseal("saved in plaintext").unwrap(),
+            "saved in plaintext"
+        );
+        assert!(SecretKey::ephemeral().unseal(&sealed).is_err());
+
+        let state = || StateFile::open(&dir, "webhooks").unwrap();
+        let mut webhooks = WebhookStore::open(state(), key.clone()).unwrap();
+        webhooks.insert(Webhook::new(WebhookSpec {
+            name: "github".to_string(),
+            function: "add".to_string(),
+            signature: Some(signature(
+                "x-hub-signature-256",
+                HmacAlgorithm::Sha256,
+                SignatureFormat::Github,
+            )),
+            delivery_id: None,
+        }));
+        let saved = std::fs::read_to_string(dir.join("webhooks.json")).unwrap();
+        assert!(!saved.contains("It's a Secret to Everybody"));
+        // the key is read back from its file
+        let key = SecretKey::open(&dir.join("webhooks.key")).unwrap();
+        let webhooks = WebhookStore::open(state(), key).unwrap();
+        let webhook = webhooks.get("github").unwrap();
+        assert_eq!(
+            webhook.signature().unwrap().secret,
+            "It's a Secret to Everybody"
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
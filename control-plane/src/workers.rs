@@ -5,25 +5,51 @@ use std::{
 
 use axum::{
     extract::{Path, Query, State},
+    http::StatusCode,
     Json,
 };
 
-use api::worker::{Worker, WorkerAddress, WorkerId, WorkerStatus};
+use api::worker::{Worker, WorkerAddress, WorkerId, WorkerReport, WorkerStatus};
 use serde::{Deserialize, Serialize};
 
+type Listener = Box<dyn Fn() + Send + Sync>;
+
 #[derive(Clone)]
 pub struct WorkerStore {
     inner: Arc<Mutex<BTreeMap<WorkerId, Worker>>>,
+    /// Called whenever a worker becomes available, e.g. for queued requests waiting for capacity
+    listeners: Arc<Mutex<Vec<Listener>>>,
+}
+
+impl Default for WorkerStore {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl WorkerStore {
     pub fn new() -> Self {
         WorkerStore {
             inner: Arc::new(Mutex::new(BTreeMap::new())),
+            listeners: Arc::default(),
+        }
+    }
+    /// Call the listener whenever a worker is stored as available having been unavailable or unknown, once the worker
+    /// can be listed
+    pub fn on_available(&self, listener: impl Fn() + Send + Sync + 'static) {
+        self.listeners.lock().unwrap().push(Box::new(listener));
+    }
+    fn signal(&self, before: Option<&WorkerStatus>, after: &WorkerStatus) {
+        if *after == WorkerStatus::Available && before != Some(&WorkerStatus::Available) {
+            for listener in self.listeners.lock().unwrap().iter() {
+                listener();
+            }
         }
     }
     pub fn insert(&mut self, worker: Worker) {
-        self.inner.lock().unwrap().insert(*worker.id(), worker);
+        let status = worker.status().clone();
+        let before = self.inner.lock().unwrap().insert(*worker.id(), worker);
+        self.signal(before.as_ref().map(Worker::status), &status);
     }
     pub fn list(&self, status: Option<&WorkerStatus>) -> Vec<Worker> {
         self.inner
@@ -44,12 +70,13 @@ impl WorkerStore {
         self.inner.lock().unwrap().get(&id).cloned()
     }
     pub fn update(&mut self, worker: &Worker) -> Option<Worker> {
-        if let Some(entry) = self.inner.lock().unwrap().get_mut(worker.id()) {
-            *entry = worker.clone();
-            Some(worker.clone())
-        } else {
-            None
-        }
+        let before = {
+            let mut workers = self.inner.lock().unwrap();
+            let entry = workers.get_mut(worker.id())?;
+            std::mem::replace(entry, worker.clone())
+        };
+        self.signal(Some(before.status()), worker.status());
+        Some(worker.clone())
     }
     pub fn touch(&mut self, id: &WorkerId) -> Option<Worker> {
         if let Some(entry) = self.inner.lock().unwrap().get_mut(id) {
@@ -59,6 +86,25 @@ impl WorkerStore {
             None
         }
     }
+    pub fn report(&mut self, id: &WorkerId, report: WorkerReport) -> Option<Worker> {
+        let (before, worker) = {
+            let mut workers = self.inner.lock().unwrap();
+            let entry = workers.get_mut(id)?;
+            let before = entry.status().clone();
+            entry.update_report(report);
+            (before, entry.clone())
+        };
+        self.signal(Some(&before), worker.status());
+        Some(worker)
+    }
+    /// Stop sending executions to an available worker which turned one away, until it reports capacity again
+    pub fn mark_occupied(&mut self, id: &WorkerId) {
+        if let Some(worker) = self.inner.lock().unwrap().get_mut(id) {
+            if *worker.status() == WorkerStatus::Available {
+                worker.update_status(WorkerStatus::Occupied);
+            }
+        }
+    }
     pub fn remove(&mut self, id: &WorkerId) -> Option<Worker> {
         self.inner.lock().unwrap().remove(id)
     }
@@ -103,14 +149,42 @@ pub async fn update_worker(
     State(mut store): State<WorkerStore>,
     Path(worker_id): Path<WorkerId>,
     maybe_worker_json: Option<Json<Worker>>,
-) -> Json<Option<Worker>> {
-    if let Some(Json(worker)) = maybe_worker_json {
-        let result = store.update(&worker);
-        Json(result)
+) -> Result<Json<Worker>, StatusCode> {
+    // workers use the 404 to find out they have been forgotten and have to register again
+    let result = if let Some(Json(worker)) = maybe_worker_json {
+        store.update(&worker)
     } else {
-        let result = store.touch(&worker_id);
-        Json(result)
+        store.touch(&worker_id)
+    };
+    result.map(Json).ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Heartbeat carrying the worker's report, a 404 tells the worker to register again
+#[tracing::instrument(skip(store, report))]
+pub async fn heartbeat(
+    State(mut store): State<WorkerStore>,
+    Path(worker_id): Path<WorkerId>,
+    Json(report): Json<WorkerReport>,
+) -> Result<Json<Worker>, StatusCode> {
+    store
+        .report(&worker_id, report)
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Register a worker under its existing id, e.g. when it reconnects after the control plane restarted
+#[tracing::instrument(skip(store))]
+pub async fn put_worker(
+    State(mut store): State<WorkerStore>,
+    Path(worker_id): Path<WorkerId>,
+    Json(mut worker): Json<Worker>,
+) -> Result<Json<Worker>, StatusCode> {
+    if *worker.id() != worker_id {
+        return Err(StatusCode::BAD_REQUEST);
     }
+    worker.touch();
+    store.insert(worker.clone());
+    Ok(Json(worker))
 }
 
 // FIXME: don't actually delete the worker, just mark it as deleted and disabled
@@ -120,3 +194,57 @@ pub async fn delete_worker(
 ) -> Json<Option<Worker>> {
     Json(store.remove(&worker_id))
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[test]
+    fn signals_workers_becoming_available() {
+        let mut store = WorkerStore::new();
+        let signals = Arc::new(AtomicUsize::new(0));
+        store.on_available({
+            let (store, signals) = (store.clone(), signals.clone());
+            move || {
+                // the worker can already be listed when the listener is called
+                assert_eq!(store.list(Some(&WorkerStatus::Available)).len(), 1);
+                signals.fetch_add(1, Ordering::SeqCst);
+            }
+        });
+        let mut worker = Worker::new("127.0.0.1:3001".to_string().into());
+        store.insert(worker.clone());
+        assert_eq!(signals.load(Ordering::SeqCst), 1);
+        // a worker which stays available isn't signalled again
+        store.update(&worker);
+        assert_eq!(signals.load(Ordering::SeqCst), 1);
+
+        store.mark_occupied(worker.id());
+        worker.update_status(WorkerStatus::Available);
+        store.update(&worker);
+        assert_eq!(signals.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn marks_only_available_workers_occupied() {
+        let mut store = WorkerStore::new();
+        let mut available = Worker::new("127.0.0.1:3001".to_string().into());
+        let mut draining = Worker::new("127.0.0.1:3002".to_string().into());
+        draining.update_status(WorkerStatus::Draining);
+        store.insert(available.clone());
+        store.insert(draining.clone());
+
+        store.mark_occupied(available.id());
+        store.mark_occupied(draining.id());
+        let status = |store: &WorkerStore, worker: &Worker| {
+            store.get(*worker.id()).unwrap().status().clone()
+        };
+        assert_eq!(status(&store, &available), WorkerStatus::Occupied);
+        assert_eq!(status(&store, &draining), WorkerStatus::Draining);
+        // until it reports having capacity again
+        available.update_status(WorkerStatus::Available);
+        store.update(&available);
+        assert_eq!(status(&store, &available), WorkerStatus::Available);
+    }
+}
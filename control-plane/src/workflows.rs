@@ -0,0 +1,640 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use api::{
+    function::{execution::Trigger, registration::Runtime, retry::RetryPolicy},
+    pipeline::{substitute, StepExecution},
+    types::JsonData,
+    workflow::{
+        Workflow, WorkflowExecution, WorkflowExecutionId, WorkflowSpec, WorkflowStatus,
+        WorkflowStep,
+    },
+};
+use axum::{
+    body::Bytes,
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use futures::{future::BoxFuture, stream, FutureExt, StreamExt, TryStreamExt};
+use serde_json::Value;
+use tokio::time::Instant;
+
+use crate::{
+    api_gateway::{self, GatewayState},
+    functions::FunctionStore,
+    metrics,
+    state::StateFile,
+};
+
+/// The workflows, saved on every change
+#[derive(Clone)]
+pub struct WorkflowStore {
+    inner: Arc<Mutex<BTreeMap<String, Workflow>>>,
+    state: StateFile,
+}
+
+impl WorkflowStore {
+    pub fn open(state: StateFile) -> anyhow::Result<Self> {
+        Ok(WorkflowStore {
+            inner: Arc::new(Mutex::new(state.load()?)),
+            state,
+        })
+    }
+    /// Insert the workflow unless another one is already registered under the same name
+    pub fn insert(&mut self, workflow: Workflow) -> Option<Workflow> {
+        let mut workflows = self.inner.lock().unwrap();
+        if workflows.contains_key(workflow.name()) {
+            return None;
+        }
+        workflows.insert(workflow.name().to_string(), workflow.clone());
+        self.save(&workflows);
+        Some(workflow)
+    }
+    pub fn list(&self) -> Vec<Workflow> {
+        self.inner.lock().unwrap().values().cloned().collect()
+    }
+    pub fn get(&self, name: &str) -> Option<Workflow> {
+        self.inner.lock().unwrap().get(name).cloned()
+    }
+    pub fn remove(&mut self, name: &str) -> Option<Workflow> {
+        let mut workflows = self.inner.lock().unwrap();
+        let workflow = workflows.remove(name)?;
+        self.save(&workflows);
+        Some(workflow)
+    }
+    fn save(&self, workflows: &BTreeMap<String, Workflow>) {
+        if let Err(e) = self.state.save(workflows) {
+            tracing::error!(error = ?e, "failed to save the workflows");
+        }
+    }
+}
+
+/// Elements of a map step's list input run at once when the step doesn't set `max_concurrency`
+const DEFAULT_MAP_CONCURRENCY: usize = 16;
+
+/// How long a call keeps waiting for its function or a worker to be available, e.g. while they register again after
+/// a restart, before the gateway turning it away counts as an attempt
+const UNAVAILABLE_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// The shortest wait between calls turned away as unavailable, the retry policy's backoff growing it from there
+const MIN_UNAVAILABLE_WAIT: Duration = Duration::from_millis(100);
+
+/// The workflow executions, saved after every call attempt so that running ones resume where they were after a
+/// restart. Running executions are all kept, completed ones only the latest `max_retained`, the oldest being dropped
+/// first.
+#[derive(Clone)]
+pub struct WorkflowExecutionStore {
+    inner: Arc<Mutex<BTreeMap<WorkflowExecutionId, WorkflowExecution>>>,
+    state: StateFile,
+    max_retained: usize,
+}
+
+impl WorkflowExecutionStore {
+    pub fn open(state: StateFile, max_retained: usize) -> anyhow::Result<Self> {
+        Ok(WorkflowExecutionStore {
+            inner: Arc::new(Mutex::new(state.load()?)),
+            state,
+            max_retained,
+        })
+    }
+    pub fn insert(&mut self, execution: WorkflowExecution) {
+        let mut executions = self.inner.lock().unwrap();
+        executions.insert(*execution.id(), execution);
+        self.save(&executions);
+    }
+    pub fn list(&self, workflow: &str) -> Vec<WorkflowExecution> {
+        self.inner
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|e| e.workflow() == workflow)
+            .cloned()
+            .collect()
+    }
+    pub fn get(&self, id: &WorkflowExecutionId) -> Option<WorkflowExecution> {
+        self.inner.lock().unwrap().get(id).cloned()
+    }
+    /// The executions which were still running when the control plane stopped
+    pub fn running(&self) -> Vec<WorkflowExecution> {
+        self.inner
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|e| e.status() == WorkflowStatus::Running)
+            .cloned()
+            .collect()
+    }
+    /// Apply the change to the execution and save it
+    fn update(&mut self, id: &WorkflowExecutionId, change: impl FnOnce(&mut WorkflowExecution)) {
+        let mut executions = self.inner.lock().unwrap();
+        if let Some(execution) = executions.get_mut(id) {
+            change(execution);
+            if execution.status() != WorkflowStatus::Running {
+                self.prune(&mut executions);
+            }
+            self.save(&executions);
+        }
+    }
+    fn prune(&self, executions: &mut BTreeMap<WorkflowExecutionId, WorkflowExecution>) {
+        let mut completed = executions
+            .values()
+            .filter(|e| e.status() != WorkflowStatus::Running)
+            .map(|e| (e.complete_time().cloned(), *e.id()))
+            .collect::<Vec<_>>();
+        if completed.len() <= self.max_retained {
+            return;
+        }
+        completed.sort();
+        for (_, id) in &completed[..completed.len() - self.max_retained] {
+            executions.remove(id);
+        }
+    }
+    fn save(&self, executions: &BTreeMap<WorkflowExecutionId, WorkflowExecution>) {
+        if let Err(e) = self.state.save_in_background(executions) {
+            tracing::error!(error = ?e, "failed to save the workflow executions");
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct WorkflowState {
+    pub workflows: WorkflowStore,
+    pub executions: WorkflowExecutionStore,
+    pub gateway: GatewayState,
+}
+
+/// Run the execution's steps until it succeeds or a step fails for good, calls which already succeeded, before a
+/// restart for instance, aren't made again.
+///
+/// Calls are made at least once: a call whose outcome wasn't saved before a restart is made again.
+pub async fn run(mut state: WorkflowState, id: WorkflowExecutionId) {
+    let Some(execution) = state.executions.get(&id) else {
+        return;
+    };
+    let result = run_steps(
+        &state,
+        &execution,
+        execution.steps(),
+        String::new(),
+        execution.input().data().value().clone(),
+    )
+    .await;
+    let status = match result {
+        Ok(output) => {
+            state
+                .executions
+                .update(&id, |e| e.succeed(JsonData::from(output).into()));
+            "succeeded"
+        }
+        Err(e) => {
+            tracing::warn!(workflow = execution.workflow(), %id, error = e, "workflow failed");
+            state.executions.update(&id, |execution| execution.fail(e));
+            "failed"
+        }
+    };
+    metrics::record_workflow_execution(execution.workflow(), status);
+}
+
+/// Run the steps in order, each with the previous step's output
+fn run_steps<'a>(
+    state: &'a WorkflowState,
+    execution: &'a WorkflowExecution,
+    steps: &'a [WorkflowStep],
+    path: String,
+    input: Value,
+) -> BoxFuture<'a, Result<Value, String>> {
+    async move {
+        let mut value = input;
+        for (i, step) in steps.iter().enumerate() {
+            value = run_step(state, execution, step, format!("{path}/{i}"), value).await?;
+        }
+        Ok(value)
+    }
+    .boxed()
+}
+
+async fn run_step(
+    state: &WorkflowState,
+    execution: &WorkflowExecution,
+    step: &WorkflowStep,
+    path: String,
+    input: Value,
+) -> Result<Value, String> {
+    match step {
+        WorkflowStep::Call {
+            function,
+            input: template,
+            retry,
+        } => {
+            let input = match template {
+                Some(template) => substitute(template.value(), &input),
+                None => input,
+            };
+            let retry = retry.clone().unwrap_or_default();
+            call(state, execution, function, &retry, path, input).await
+        }
+        WorkflowStep::Parallel { branches } => {
+            let branches = branches.iter().enumerate().map(|(i, steps)| {
+                run_steps(
+                    state,
+                    execution,
+                    steps,
+                    format!("{path}/branch/{i}"),
+                    input.clone(),
+                )
+            });
+            futures::future::try_join_all(branches)
+                .await
+                .map(Value::Array)
+        }
+        WorkflowStep::Map {
+            steps,
+            max_concurrency,
+        } => {
+            let Value::Array(items) = input else {
+                return Err(format!("step {path} maps over a list but got {input}"));
+            };
+            let concurrency = max_concurrency.unwrap_or(DEFAULT_MAP_CONCURRENCY).max(1);
+            let items = items.into_iter().enumerate().map(|(i, item)| {
+                run_steps(state, execution, steps, format!("{path}/item/{i}"), item)
+            });
+            stream::iter(items)
+                .buffered(concurrency)
+                .try_collect()
+                .await
+                .map(Value::Array)
+        }
+        WorkflowStep::Choice { choices, default } => {
+            match choices.iter().position(|c| c.condition.holds(&input)) {
+                Some(i) => {
+                    let path = format!("{path}/choice/{i}");
+                    run_steps(state, execution, &choices[i].steps, path, input).await
+                }
+                None => {
+                    run_steps(state, execution, default, format!("{path}/default"), input).await
+                }
+            }
+        }
+    }
+}
+
+/// Call the function through the gateway until it succeeds or the retry policy's attempts are used up, the attempts
+/// made before a restart counting towards them.
+///
+/// Calls the gateway turns away because the function or a worker for it isn't available are made again without
+/// counting as attempts, for up to [`UNAVAILABLE_TIMEOUT`], the wait between them backing off like attempts do.
+async fn call(
+    state: &WorkflowState,
+    execution: &WorkflowExecution,
+    function: &str,
+    retry: &RetryPolicy,
+    path: String,
+    input: Value,
+) -> Result<Value, String> {
+    let mut executions = state.executions.clone();
+    let mut unavailable_since = None;
+    let mut unavailable_waits = 0;
+    loop {
+        let attempts = executions
+            .get(execution.id())
+            .and_then(|e| e.calls().get(&path).cloned())
+            .unwrap_or_default();
+        if let Some(output) = attempts.output() {
+            return Ok(output.data().value().clone());
+        }
+        let attempt = attempts.attempts().len() as u32;
+        if let Some(last) = attempts.attempts().last() {
+            let exit = last.result().map(|result| result.exit());
+            if attempt >= retry.max_attempts.max(1) || !retry.retries(exit) {
+                return Err(format!(
+                    "step {path} ({function}) failed after {attempt} attempts: {}",
+                    last.error().unwrap_or_default()
+                ));
+            }
+        }
+        if attempt > 0 {
+            tokio::time::sleep(retry.backoff(attempt)).await;
+        }
+        let trigger = Trigger::Workflow {
+            workflow: execution.workflow().to_string(),
+            execution: *execution.id(),
+            step: path.clone(),
+        };
+        let call = api_gateway::call(state.gateway.clone(), function, &input, trigger).await;
+        let unavailable = call.execution.is_none()
+            && matches!(
+                call.status,
+                StatusCode::NOT_FOUND | StatusCode::SERVICE_UNAVAILABLE
+            );
+        if !unavailable {
+            unavailable_since = None;
+            unavailable_waits = 0;
+        } else if unavailable_since.get_or_insert_with(Instant::now).elapsed() < UNAVAILABLE_TIMEOUT
+        {
+            tracing::info!(
+                workflow = execution.workflow(),
+                step = path,
+                status = %call.status,
+                "function unavailable, waiting"
+            );
+            unavailable_waits += 1;
+            tokio::time::sleep(retry.backoff(unavailable_waits).max(MIN_UNAVAILABLE_WAIT)).await;
+            continue;
+        }
+        if let Err(e) = &call.output {
+            tracing::warn!(
+                workflow = execution.workflow(),
+                step = path,
+                attempt = attempt + 1,
+                error = e,
+                "call failed"
+            );
+        }
+        let attempt = StepExecution::new(
+            function.to_string(),
+            call.execution,
+            call.result,
+            call.output.as_ref().err().cloned(),
+        );
+        let output = call.output.ok().map(|output| JsonData::from(output).into());
+        executions.update(execution.id(), |e| e.record_attempt(&path, attempt, output));
+    }
+}
+
+/// Resume the executions which were running when the control plane stopped
+pub fn resume(state: &WorkflowState) {
+    for execution in state.executions.running() {
+        tracing::info!(workflow = execution.workflow(), id = %execution.id(), "resuming workflow");
+        tokio::spawn(run(state.clone(), *execution.id()));
+    }
+}
+
+#[tracing::instrument(skip(workflows))]
+pub async fn list_workflows(State(workflows): State<WorkflowStore>) -> Json<Vec<Workflow>> {
+    Json(workflows.list())
+}
+
+/// Register a workflow of functions taking and returning JSON
+#[tracing::instrument(skip(workflows, functions))]
+pub async fn create_workflow(
+    State((mut workflows, functions)): State<(WorkflowStore, FunctionStore)>,
+    Json(spec): Json<WorkflowSpec>,
+) -> Result<Json<Workflow>, (StatusCode, String)> {
+    if spec.steps.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "a workflow needs at least one step".to_string(),
+        ));
+    }
+    for function in spec.steps.iter().flat_map(WorkflowStep::functions) {
+        let name = function.split_once('@').map_or(function, |(name, _)| name);
+        let function = functions
+            .get_by_name(name)
+            .ok_or((StatusCode::NOT_FOUND, format!("function {name} not found")))?;
+        if function.runtime() == Runtime::HttpHandler {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("function {name} handles raw HTTP and has no JSON output"),
+            ));
+        }
+    }
+    let name = spec.name.clone();
+    workflows.insert(Workflow::new(spec)).map(Json).ok_or((
+        StatusCode::CONFLICT,
+        format!("workflow {name} already exists"),
+    ))
+}
+
+#[tracing::instrument(skip(workflows))]
+pub async fn get_workflow(
+    State(workflows): State<WorkflowStore>,
+    Path(name): Path<String>,
+) -> Result<Json<Workflow>, StatusCode> {
+    workflows.get(&name).map(Json).ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Remove the workflow, its running executions carry on with the steps they started with
+#[tracing::instrument(skip(workflows))]
+pub async fn delete_workflow(
+    State(mut workflows): State<WorkflowStore>,
+    Path(name): Path<String>,
+) -> Result<Json<Workflow>, StatusCode> {
+    workflows
+        .remove(&name)
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Start the workflow with the JSON body as its input and respond with the running execution, whose progress is
+/// polled on its own URL
+#[tracing::instrument(skip(state, body))]
+pub async fn execute_workflow(
+    State(mut state): State<WorkflowState>,
+    Path(name): Path<String>,
+    body: Bytes,
+) -> Result<(StatusCode, Json<WorkflowExecution>), (StatusCode, String)> {
+    let workflow = state
+        .workflows
+        .get(&name)
+        .ok_or((StatusCode::NOT_FOUND, format!("workflow {name} not found")))?;
+    // an empty body is treated as `null` like the gateway does
+    let input = if body.is_empty() {
+        Value::Null
+    } else {
+        serde_json::from_slice(&body)
+            .map_err(|e| (StatusCode::BAD_REQUEST, format!("invalid JSON input: {e}")))?
+    };
+    let execution = WorkflowExecution::new(&workflow, JsonData::from(input).into());
+    state.executions.insert(execution.clone());
+    tokio::spawn(run(state, *execution.id()));
+    Ok((StatusCode::ACCEPTED, Json(execution)))
+}
+
+/// The executions of the workflow, oldest first
+#[tracing::instrument(skip(executions))]
+pub async fn list_workflow_executions(
+    State(executions): State<WorkflowExecutionStore>,
+    Path(name): Path<String>,
+) -> Json<Vec<WorkflowExecution>> {
+    let mut executions = executions.list(&name);
+    executions.sort_by(|a, b| a.create_time().cmp(b.create_time()));
+    Json(executions)
+}
+
+#[tracing::instrument(skip(executions))]
+pub async fn get_workflow_execution(
+    State(executions): State<WorkflowExecutionStore>,
+    Path((name, id)): Path<(String, WorkflowExecutionId)>,
+) -> Result<Json<WorkflowExecution>, StatusCode> {
+    executions
+        .get(&id)
+        .filter(|e| e.workflow() == name)
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{atomic::AtomicU32, Arc};
+
+    use serde_json::json;
+
+    use api::function::registration::Function;
+
+    use super::*;
+    use crate::{api_gateway::tests::gateway, functions};
+
+    async fn workflow_state(failures: u32, executions: StateFile) -> WorkflowState {
+        WorkflowState {
+            workflows: WorkflowStore::open(StateFile::memory()).unwrap(),
+            executions: WorkflowExecutionStore::open(executions, 10).unwrap(),
+            gateway: gateway(Arc::new(AtomicU32::new(failures))).await,
+        }
+    }
+
+    fn execution(steps: Value, input: Value) -> WorkflowExecution {
+        let workflow = Workflow::new(WorkflowSpec {
+            name: "test".to_string(),
+            description: String::new(),
+            steps: serde_json::from_value(steps).unwrap(),
+        });
+        WorkflowExecution::new(&workflow, JsonData::from(input).into())
+    }
+
+    /// Start the execution and wait for it to complete
+    async fn execute(state: &mut WorkflowState, execution: WorkflowExecution) -> WorkflowExecution {
+        let id = *execution.id();
+        state.executions.insert(execution);
+        run(state.clone(), id).await;
+        state.executions.get(&id).unwrap()
+    }
+
+    fn attempts(execution: &WorkflowExecution, path: &str) -> usize {
+        execution.calls()[path].attempts().len()
+    }
+
+    #[tokio::test]
+    async fn runs_steps() {
+        let mut state = workflow_state(0, StateFile::memory()).await;
+        let steps = json!([
+            { "map": { "steps": [{ "call": { "function": "add", "input": ["$output", 1] } }] } },
+            { "parallel": { "branches": [[], [{ "call": { "function": "add", "input": [1, 2] } }]] } }
+        ]);
+        let done = execute(&mut state, execution(steps, json!([1, 2]))).await;
+        assert_eq!(done.status(), WorkflowStatus::Succeeded);
+        // the worker always outputs 3
+        assert_eq!(done.output().unwrap().data().value(), &json!([[3, 3], 3]));
+        for path in ["/0/item/0/0", "/0/item/1/0", "/1/branch/1/0"] {
+            assert_eq!(attempts(&done, path), 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn retries_calls() {
+        let retry = json!({ "max_attempts": 2, "initial_backoff_ms": 1 });
+        let steps = json!([{ "call": { "function": "add", "retry": retry } }]);
+
+        let mut state = workflow_state(1, StateFile::memory()).await;
+        let done = execute(&mut state, execution(steps.clone(), json!([1, 2]))).await;
+        assert_eq!(done.status(), WorkflowStatus::Succeeded);
+        assert_eq!(attempts(&done, "/0"), 2);
+
+        let mut state = workflow_state(2, StateFile::memory()).await;
+        let done = execute(&mut state, execution(steps, json!([1, 2]))).await;
+        assert_eq!(done.status(), WorkflowStatus::Failed);
+        assert_eq!(attempts(&done, "/0"), 2);
+    }
+
+    #[tokio::test]
+    async fn waits_for_a_worker_without_counting_attempts() {
+        let mut state = workflow_state(0, StateFile::memory()).await;
+        let worker = state.gateway.workers.list(None).remove(0);
+        state.gateway.workers.remove(worker.id());
+        let retry = json!({ "max_attempts": 1, "initial_backoff_ms": 1 });
+        let steps = json!([{ "call": { "function": "add", "retry": retry } }]);
+        let running = tokio::spawn({
+            let mut state = state.clone();
+            async move { execute(&mut state, execution(steps, json!([1, 2]))).await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        state.gateway.workers.insert(worker);
+        let done = running.await.unwrap();
+        assert_eq!(done.status(), WorkflowStatus::Succeeded);
+        assert_eq!(attempts(&done, "/0"), 1);
+    }
+
+    #[tokio::test]
+    async fn resumes_after_restart() {
+        let dir =
+            std::env::temp_dir().join(format!("wasi-faas-workflows-{}", api::types::Id::new()));
+        let file = StateFile::open(&dir, "workflow_executions").unwrap();
+        let functions = StateFile::open(&dir, "functions").unwrap();
+        let add = functions::samples()
+            .into_iter()
+            .find(|spec| spec.name == "add");
+        FunctionStore::open(functions.clone())
+            .unwrap()
+            .insert(Function::new(add.unwrap()));
+        let steps = json!([
+            { "call": { "function": "add" } },
+            { "call": { "function": "add", "input": ["$output", 1] } }
+        ]);
+        let mut started = execution(steps, json!([1, 2]));
+        let id = *started.id();
+        // the first step succeeded before the control plane stopped
+        let attempt = StepExecution::new("add".to_string(), None, None, None);
+        started.record_attempt("/0", attempt, Some(JsonData::from(json!(3)).into()));
+        WorkflowExecutionStore::open(file.clone(), 10)
+            .unwrap()
+            .insert(started);
+        let saved = |file: &StateFile| {
+            file.load::<BTreeMap<WorkflowExecutionId, WorkflowExecution>>()
+                .unwrap()
+                .contains_key(&id)
+        };
+        while !saved(&file) {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let mut state = workflow_state(0, file).await;
+        // the functions registered before the restart
+        state.gateway.functions = FunctionStore::open(functions).unwrap();
+        resume(&state);
+        let done = loop {
+            let execution = state.executions.get(&id).unwrap();
+            if execution.status() != WorkflowStatus::Running {
+                break execution;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        };
+        assert_eq!(done.status(), WorkflowStatus::Succeeded);
+        assert_eq!(attempts(&done, "/0"), 1);
+        assert_eq!(attempts(&done, "/1"), 1);
+        // only the second step was called again
+        assert_eq!(state.gateway.executions.list(None).len(), 1);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn keeps_the_latest_completed_executions() {
+        let mut executions = WorkflowExecutionStore::open(StateFile::memory(), 1).unwrap();
+        let ids = (0..3)
+            .map(|_| {
+                let execution = execution(json!([]), Value::Null);
+                let id = *execution.id();
+                executions.insert(execution);
+                id
+            })
+            .collect::<Vec<_>>();
+        for id in &ids[..2] {
+            executions.update(id, |e| e.succeed(JsonData::from(Value::Null).into()));
+        }
+        assert!(executions.get(&ids[0]).is_none());
+        // the running execution is kept whatever the limit
+        assert!(executions.get(&ids[1]).is_some() && executions.get(&ids[2]).is_some());
+    }
+}
@@ -0,0 +1,76 @@
+use std::{path::PathBuf, time::Duration};
+
+use clap::Parser;
+use control_plane::{
+    blobs::BlobStore, config::Config, functions::FunctionStore, metrics, state::StateFile,
+};
+use settings::ServerConfig;
+use watch::Watcher;
+use worker::{config::WorkerConfig, server};
+
+mod watch;
+
+/// Run the control plane and a worker in one process, serving the `.wasm` files of a directory as functions and
+/// reloading them when they change
+#[derive(clap::Parser)]
+struct Args {
+    /// Directory of `.wasm` files, each one is served on `/api/<file name without .wasm>`
+    #[clap(default_value = ".")]
+    dir: PathBuf,
+    #[clap(long, default_value = "127.0.0.1:3000")]
+    address: String,
+    #[clap(long, default_value = "127.0.0.1:3001")]
+    worker_address: String,
+    /// Directory holding uploaded blobs, the worker id and compiled modules
+    #[clap(long, default_value = "data/dev")]
+    data_dir: PathBuf,
+    /// How often the directory is checked for changes
+    #[clap(long, default_value = "500")]
+    poll_interval_ms: u64,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    telemetry::init("dev")?;
+    let Args {
+        dir,
+        address,
+        worker_address,
+        data_dir,
+        poll_interval_ms,
+    } = Args::try_parse()?;
+    // the worker records its metrics with the control plane's recorder as there is one per process
+    let metrics_handle = metrics::install()?;
+    let blob_store = BlobStore::open(&data_dir.join("blobs"))?;
+    // the functions are registered again from the directory on every start
+    let function_store = FunctionStore::open(StateFile::memory())?;
+
+    // register the functions before the worker starts so that it loads them right away
+    let mut watcher = Watcher::new(dir.clone(), function_store.clone(), blob_store.clone());
+    watcher.scan()?;
+
+    let app = control_plane::app(
+        &Config::default(),
+        function_store,
+        blob_store,
+        metrics_handle.clone(),
+    )?;
+    let listener = tokio::net::TcpListener::bind(&address).await?;
+    tracing::info!("listening on {}", listener.local_addr()?);
+
+    let mut worker = WorkerConfig {
+        server: ServerConfig::new(&worker_address),
+        ..WorkerConfig::default()
+    };
+    worker.control_plane.address = format!("http://{address}");
+    worker.storage.worker_id_file = data_dir.join("worker_id").display().to_string();
+    worker.storage.function_dir = dir.display().to_string();
+    worker.storage.module_cache_dir = data_dir.join("module-cache").display().to_string();
+    tokio::select! {
+        result = axum::serve(listener, app) => result?,
+        result = server::run(worker, |_| Ok(metrics_handle.clone())) => result?,
+        () = watcher.run(Duration::from_millis(poll_interval_ms)) => {}
+    }
+    telemetry::shutdown();
+    Ok(())
+}
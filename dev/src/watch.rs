@@ -0,0 +1,254 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use api::function::registration::{EgressPolicy, Function, FunctionSpec, InputKind, Runtime};
+use control_plane::{blobs::BlobStore, functions::FunctionStore};
+use wasmparser::{Parser, Payload};
+
+/// Registers every `.wasm` file in a directory as a function named after the file, and uploads a new revision of it
+/// whenever the file changes
+pub struct Watcher {
+    dir: PathBuf,
+    functions: FunctionStore,
+    blobs: BlobStore,
+    /// Modification times of the files as of the last scan, keyed by function name
+    seen: BTreeMap<String, SystemTime>,
+}
+
+impl Watcher {
+    pub fn new(dir: PathBuf, functions: FunctionStore, blobs: BlobStore) -> Self {
+        Watcher {
+            dir,
+            functions,
+            blobs,
+            seen: BTreeMap::new(),
+        }
+    }
+
+    /// Poll the directory for changes, there is no portable way to be notified of them without another dependency
+    pub async fn run(mut self, interval: Duration) {
+        loop {
+            tokio::time::sleep(interval).await;
+            // the directory may be moved away and back while editing, the next scan picks it up again
+            if let Err(e) = self.scan() {
+                tracing::warn!("can't read {}: {e:#}", self.dir.display());
+            }
+        }
+    }
+
+    /// Sync the registered functions with the directory, functions whose file was removed are removed too.
+    ///
+    /// Fails only when the directory can't be read, in which case the functions are left as they are.
+    pub fn scan(&mut self) -> anyhow::Result<()> {
+        let mut present = BTreeMap::new();
+        for entry in std::fs::read_dir(&self.dir)? {
+            let path = match entry {
+                Ok(entry) => entry.path(),
+                Err(e) => {
+                    tracing::warn!("can't read an entry of {}: {e}", self.dir.display());
+                    continue;
+                }
+            };
+            if path.extension().is_none_or(|extension| extension != "wasm") {
+                continue;
+            }
+            let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+            let modified = match std::fs::metadata(&path).and_then(|metadata| metadata.modified()) {
+                Ok(modified) => modified,
+                // removed since the directory was listed, the function is removed with the others
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => {
+                    tracing::warn!("can't read {}: {e}", path.display());
+                    // keep the function as it is until the file can be read again
+                    if let Some(seen) = self.seen.get(name) {
+                        present.insert(name.to_string(), *seen);
+                    }
+                    continue;
+                }
+            };
+            present.insert(name.to_string(), modified);
+            if self.seen.get(name) == Some(&modified) {
+                continue;
+            }
+            // a file which is still being written fails to parse, it is picked up again on the next change
+            if let Err(e) = self.sync(name, &path) {
+                tracing::warn!("can't load {}: {e:#}", path.display());
+            }
+        }
+        for name in self.seen.keys().filter(|name| !present.contains_key(*name)) {
+            if self.functions.remove_by_name(name).is_some() {
+                tracing::info!("removed {name}");
+            }
+        }
+        self.seen = present;
+        Ok(())
+    }
+
+    fn sync(&mut self, name: &str, path: &Path) -> anyhow::Result<()> {
+        let wasm = std::fs::read(path)?;
+        let (runtime, input_type) = inspect(name, &wasm)?;
+        let blob_address = self
+            .blobs
+            .put(&wasm)
+            .map_err(|(_, reason)| anyhow::anyhow!(reason))?;
+
+        match self.functions.get_by_name(name) {
+            // touched without changing
+            Some(function) if function.current_revision().blob_address() == &blob_address => {}
+            Some(function) if function.runtime() == runtime => {
+                let revision = self.functions.modify(name, |function| {
+                    function
+                        .add_revision(blob_address, format!("{} changed", path.display()))
+                        .number()
+                });
+                if let Some(revision) = revision {
+                    tracing::info!("reloaded {name} as revision {revision}");
+                }
+            }
+            // the runtime is fixed at registration so a file rebuilt for another one is registered again
+            existing => {
+                if existing.is_some() {
+                    self.functions.remove_by_name(name);
+                }
+                let spec = FunctionSpec {
+                    name: name.to_string(),
+                    description: path.display().to_string(),
+                    runtime,
+                    input_type,
+                    input_schema: None,
+                    output_type: None,
+                    output_schema: None,
+                    blob_address,
+                    note: String::new(),
+                    egress: EgressPolicy::default(),
+                    retry: None,
+                };
+                self.functions.insert(Function::new(spec));
+                tracing::info!("registered {name} ({runtime:?}) on /api/{name}");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Work out the runtime of the wasm and the input it takes from its exports.
+///
+/// Components exporting `wasi:http/incoming-handler` are HTTP handlers and other components implement the `handler`
+/// world. Core modules exporting a function named after the file take a list of numbers, commands take no input.
+fn inspect(name: &str, wasm: &[u8]) -> anyhow::Result<(Runtime, InputKind)> {
+    if Parser::is_component(wasm) {
+        for payload in Parser::new(0).parse_all(wasm) {
+            if let Payload::ComponentExportSection(exports) = payload? {
+                for export in exports {
+                    if export?.name.0.starts_with("wasi:http/incoming-handler") {
+                        return Ok((Runtime::HttpHandler, InputKind::None));
+                    }
+                }
+            }
+        }
+        // the handler world takes any JSON object
+        return Ok((Runtime::Component, InputKind::Object(BTreeMap::new())));
+    }
+    if !Parser::is_core_wasm(wasm) {
+        anyhow::bail!("not a wasm module or component");
+    }
+    for payload in Parser::new(0).parse_all(wasm) {
+        if let Payload::ExportSection(exports) = payload? {
+            for export in exports {
+                if export?.name == name {
+                    return Ok((Runtime::Wasm, InputKind::List(Box::new(InputKind::Number))));
+                }
+            }
+        }
+    }
+    Ok((Runtime::Wasm, InputKind::None))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use control_plane::state::StateFile;
+
+    use super::*;
+
+    const COMPONENT: &[u8] = b"\0asm\x0d\0\x01\0";
+    const COMMAND: &[u8] = b"\0asm\x01\0\0\0";
+    /// A core module exporting an empty function named `add`
+    const ADD: &[u8] = &[
+        0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+        0x01, 0x04, 0x01, 0x60, 0x00, 0x00, // type section: () -> ()
+        0x03, 0x02, 0x01, 0x00, // function section
+        0x07, 0x07, 0x01, 0x03, b'a', b'd', b'd', 0x00, 0x00, // export section
+        0x0a, 0x04, 0x01, 0x02, 0x00, 0x0b, // code section
+    ];
+
+    #[test]
+    fn inspects_exports() {
+        assert_eq!(
+            inspect("add", ADD).unwrap(),
+            (Runtime::Wasm, InputKind::List(Box::new(InputKind::Number)))
+        );
+        assert_eq!(
+            inspect("sub", ADD).unwrap(),
+            (Runtime::Wasm, InputKind::None)
+        );
+        assert_eq!(
+            inspect("add", COMMAND).unwrap(),
+            (Runtime::Wasm, InputKind::None)
+        );
+        assert_eq!(
+            inspect("add", COMPONENT).unwrap(),
+            (Runtime::Component, InputKind::Object(BTreeMap::new()))
+        );
+        assert!(inspect("add", b"add").is_err());
+    }
+
+    /// Write the file with a modification time of its own so that the change is seen however coarse the clock is
+    fn write(path: &Path, wasm: &[u8], seconds: u64) {
+        std::fs::write(path, wasm).unwrap();
+        File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(seconds))
+            .unwrap();
+    }
+
+    #[test]
+    fn syncs_functions_with_the_directory() {
+        let dir = std::env::temp_dir().join(format!("wasi-faas-watch-{}", api::types::Id::new()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let functions = FunctionStore::open(StateFile::memory()).unwrap();
+        let mut watcher = Watcher::new(dir.clone(), functions.clone(), BlobStore::memory());
+        let path = dir.join("add.wasm");
+        write(&path, COMMAND, 1);
+        std::fs::write(dir.join("notes.txt"), "not a function").unwrap();
+
+        watcher.scan().unwrap();
+        let add = functions.get_by_name("add").unwrap();
+        assert_eq!(add.input_type(), &InputKind::None);
+        assert_eq!(functions.list().len(), 1);
+
+        write(&path, ADD, 2);
+        watcher.scan().unwrap();
+        let add = functions.get_by_name("add").unwrap();
+        assert_eq!(add.revisions().len(), 2);
+
+        // an unreadable directory leaves the functions registered
+        std::fs::rename(&dir, dir.with_extension("moved")).unwrap();
+        assert!(watcher.scan().is_err());
+        assert!(functions.get_by_name("add").is_some());
+        std::fs::rename(dir.with_extension("moved"), &dir).unwrap();
+
+        std::fs::remove_file(&path).unwrap();
+        watcher.scan().unwrap();
+        assert!(functions.get_by_name("add").is_none());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
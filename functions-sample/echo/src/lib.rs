@@ -0,0 +1,53 @@
+use wasi::http::types::{
+    Fields, IncomingBody, IncomingRequest, Method, OutgoingBody, OutgoingResponse,
+    ResponseOutparam,
+};
+
+struct Echo;
+
+impl wasi::exports::http::incoming_handler::Guest for Echo {
+    fn handle(request: IncomingRequest, response_out: ResponseOutparam) {
+        let headers = Fields::new();
+        headers
+            .set(&"content-type".to_string(), &[b"text/plain".to_vec()])
+            .unwrap();
+        let response = OutgoingResponse::new(headers);
+        let body = response.body().unwrap();
+        ResponseOutparam::set(response_out, Ok(response));
+
+        let out = body.write().unwrap();
+        let path = request.path_with_query().unwrap_or_default();
+        out.blocking_write_and_flush(describe(&request.method(), &path).as_bytes())
+            .unwrap();
+
+        // stream the request body back as it arrives
+        let incoming = request.consume().unwrap();
+        let stream = incoming.stream().unwrap();
+        while let Ok(chunk) = stream.blocking_read(4096) {
+            out.blocking_write_and_flush(&chunk).unwrap();
+        }
+        drop(stream);
+        IncomingBody::finish(incoming);
+        drop(out);
+        OutgoingBody::finish(body, None).unwrap();
+    }
+}
+
+wasi::http::proxy::export!(Echo);
+
+fn describe(method: &Method, path: &str) -> String {
+    let method = match method {
+        Method::Get => "GET",
+        Method::Head => "HEAD",
+        Method::Post => "POST",
+        Method::Put => "PUT",
+        Method::Delete => "DELETE",
+        Method::Connect => "CONNECT",
+        Method::Options => "OPTIONS",
+        Method::Trace => "TRACE",
+        Method::Patch => "PATCH",
+        Method::Other(other) => other,
+    };
+    format!("{method} {path}\n")
+}
+
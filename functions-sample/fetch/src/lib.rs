@@ -0,0 +1,69 @@
+use wasi::http::{
+    outgoing_handler,
+    types::{
+        ErrorCode, Fields, IncomingRequest, OutgoingBody, OutgoingRequest, OutgoingResponse,
+        ResponseOutparam, Scheme,
+    },
+};
+
+struct Fetch;
+
+impl wasi::exports::http::incoming_handler::Guest for Fetch {
+    /// Fetch `http://<authority>/<path>` for a request to `/<authority>/<path>` and relay the response
+    fn handle(request: IncomingRequest, response_out: ResponseOutparam) {
+        let path = request.path_with_query().unwrap_or_default();
+        let Some((authority, path)) = target(&path) else {
+            respond(response_out, 400, b"expected /<authority>/<path>\n");
+            return;
+        };
+        match fetch(authority, &path) {
+            Ok((status, body)) => respond(response_out, status, &body),
+            Err(e) => respond(response_out, 502, format!("{e:?}\n").as_bytes()),
+        }
+    }
+}
+
+wasi::http::proxy::export!(Fetch);
+
+fn target(path: &str) -> Option<(&str, String)> {
+    let (authority, rest) = path
+        .trim_start_matches('/')
+        .split_once('/')
+        .unwrap_or((path.trim_start_matches('/'), ""));
+    (!authority.is_empty()).then(|| (authority, format!("/{rest}")))
+}
+
+fn fetch(authority: &str, path: &str) -> Result<(u16, Vec<u8>), ErrorCode> {
+    let request = OutgoingRequest::new(Fields::new());
+    request.set_scheme(Some(&Scheme::Http)).unwrap();
+    request.set_authority(Some(authority)).unwrap();
+    request.set_path_with_query(Some(path)).unwrap();
+    OutgoingBody::finish(request.body().unwrap(), None).unwrap();
+
+    let pending = outgoing_handler::handle(request, None)?;
+    pending.subscribe().block();
+    let response = pending.get().unwrap().unwrap()?;
+
+    let incoming = response.consume().unwrap();
+    let stream = incoming.stream().unwrap();
+    let mut body = Vec::new();
+    while let Ok(chunk) = stream.blocking_read(4096) {
+        body.extend(chunk);
+    }
+    Ok((response.status(), body))
+}
+
+fn respond(response_out: ResponseOutparam, status: u16, body: &[u8]) {
+    let response = OutgoingResponse::new(Fields::new());
+    response.set_status_code(status).unwrap();
+    let outgoing = response.body().unwrap();
+    ResponseOutparam::set(response_out, Ok(response));
+    let out = outgoing.write().unwrap();
+    // a single blocking write is limited to 4096 bytes
+    for chunk in body.chunks(4096) {
+        out.blocking_write_and_flush(chunk).unwrap();
+    }
+    drop(out);
+    OutgoingBody::finish(outgoing, None).unwrap();
+}
+
@@ -0,0 +1,38 @@
+use serde::{Deserialize, Serialize};
+
+wit_bindgen::generate!({
+    world: "handler",
+    path: "../../worker/wit",
+});
+
+#[derive(Deserialize)]
+struct Input {
+    name: String,
+}
+
+#[derive(Serialize)]
+struct Output {
+    greeting: String,
+}
+
+struct Greet;
+
+impl Guest for Greet {
+    fn handle(request: Request) -> Result<Response, Error> {
+        let input: Input = serde_json::from_str(&request.input)
+            .map_err(|e| Error::InvalidInput(e.to_string()))?;
+        let output = Output {
+            greeting: greet(&input.name),
+        };
+        Ok(Response {
+            output: serde_json::to_string(&output).map_err(|e| Error::Failed(e.to_string()))?,
+        })
+    }
+}
+
+export!(Greet);
+
+fn greet(name: &str) -> String {
+    format!("Hello, {name}!")
+}
+
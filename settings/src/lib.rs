@@ -0,0 +1,288 @@
+//! Layered configuration for the binaries: the defaults, then a TOML file, then environment variables, then
+//! command-line flags, each layer overriding the keys it sets.
+
+use std::path::Path;
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tls::TlsConfig;
+use toml::{Table, Value};
+
+pub mod tls;
+
+/// Where a server listens and whether it serves HTTPS
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ServerConfig {
+    pub listen_address: String,
+    pub tls: Option<TlsConfig>,
+}
+
+impl ServerConfig {
+    pub fn new(listen_address: &str) -> Self {
+        ServerConfig {
+            listen_address: listen_address.to_string(),
+            tls: None,
+        }
+    }
+    pub fn scheme(&self) -> &'static str {
+        match self.tls {
+            Some(_) => "https",
+            None => "http",
+        }
+    }
+}
+
+/// Values set on the command line, keyed by their dotted path in the configuration, e.g. `server.listen_address`
+#[derive(Default)]
+pub struct Flags(Table);
+
+impl Flags {
+    /// Set the key if the flag was given
+    pub fn set(&mut self, key: &str, value: Option<impl Into<Value>>) -> &mut Self {
+        if let Some(value) = value {
+            insert(&mut self.0, key, value.into());
+        }
+        self
+    }
+    /// Set a `key=value` pair given with `--set`, the value being read as the type of the key, see [`coerce`]
+    pub fn set_pair(&mut self, pair: &str) -> anyhow::Result<&mut Self> {
+        let (key, value) = pair
+            .split_once('=')
+            .ok_or_else(|| anyhow::anyhow!("expected key=value but got {pair}"))?;
+        insert(
+            &mut self.0,
+            key.trim(),
+            Value::String(value.trim().to_string()),
+        );
+        Ok(self)
+    }
+}
+
+/// Load the configuration from its layers.
+///
+/// Environment variables are named after the key with the prefix, upper cased and with `__` between tables, e.g.
+/// `WASI_FAAS_SERVER__LISTEN_ADDRESS` for `server.listen_address` with the `WASI_FAAS_` prefix.
+pub fn load<T: Serialize + DeserializeOwned + Default>(
+    file: Option<&Path>,
+    env_prefix: &str,
+    flags: Flags,
+) -> anyhow::Result<T> {
+    let mut config = defaults::<T>()?;
+    if let Some(file) = file {
+        let contents = std::fs::read_to_string(file)
+            .map_err(|e| anyhow::anyhow!("can't read {}: {e}", file.display()))?;
+        let table = toml::from_str(&contents)
+            .map_err(|e| anyhow::anyhow!("invalid config file {}: {e}", file.display()))?;
+        merge(&mut config, table);
+    }
+    merge_strings(&mut config, env_layer(env_prefix, std::env::vars()));
+    merge_strings(&mut config, flags.0);
+    Value::Table(config)
+        .try_into()
+        .map_err(|e| anyhow::anyhow!("invalid configuration: {e}"))
+}
+
+/// The configuration as a TOML document, e.g. to print the effective configuration
+pub fn to_toml<T: Serialize>(config: &T) -> anyhow::Result<String> {
+    Ok(toml::to_string_pretty(config)?)
+}
+
+/// Completes on ctrl-c or, on unix, SIGTERM
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!(error = ?e, "failed to listen for ctrl-c");
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                tracing::error!(error = ?e, "failed to listen for SIGTERM");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+    tokio::select! {
+        () = ctrl_c => {},
+        () = terminate => {},
+    }
+}
+
+fn defaults<T: Serialize + Default>() -> anyhow::Result<Table> {
+    match Value::try_from(T::default())? {
+        Value::Table(table) => Ok(table),
+        _ => anyhow::bail!("the configuration must be a table"),
+    }
+}
+
+fn merge(base: &mut Table, layer: Table) {
+    for (key, value) in layer {
+        match (base.get_mut(&key), value) {
+            (Some(Value::Table(base)), Value::Table(layer)) => merge(base, layer),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Merge a layer whose values were given as strings, reading each one as the type of the key it overrides
+fn merge_strings(base: &mut Table, layer: Table) {
+    for (key, value) in layer {
+        match (base.get_mut(&key), value) {
+            (Some(Value::Table(base)), Value::Table(layer)) => merge_strings(base, layer),
+            (existing, Value::String(value)) => {
+                let value = coerce(existing.as_deref(), value);
+                base.insert(key, value);
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+fn env_layer(prefix: &str, vars: impl Iterator<Item = (String, String)>) -> Table {
+    let mut table = Table::new();
+    for (name, value) in vars {
+        if let Some(key) = name.strip_prefix(prefix) {
+            let key = key.to_lowercase().replace("__", ".");
+            insert(&mut table, &key, Value::String(value));
+        }
+    }
+    table
+}
+
+fn insert(table: &mut Table, key: &str, value: Value) {
+    match key.split_once('.') {
+        Some((head, rest)) => {
+            let entry = table
+                .entry(head)
+                .or_insert_with(|| Value::Table(Table::new()));
+            if !entry.is_table() {
+                *entry = Value::Table(Table::new());
+            }
+            if let Value::Table(nested) = entry {
+                insert(nested, rest, value);
+            }
+        }
+        None => {
+            table.insert(key.to_string(), value);
+        }
+    }
+}
+
+/// A value given as a string for a key holding `existing`: string keys take it as is, other keys parse it as TOML so
+/// that numbers, booleans and arrays can be set from strings. Keys without a value yet, e.g. unset options, take
+/// numbers, booleans and arrays parsed too but never dates, which only a TOML file can set.
+fn coerce(existing: Option<&Value>, value: String) -> Value {
+    if let Some(Value::String(_)) = existing {
+        return Value::String(value);
+    }
+    let parsed = toml::from_str::<Table>(&format!("value = {value}"))
+        .ok()
+        .and_then(|mut table| table.remove("value"));
+    match (existing, parsed) {
+        (_, None) | (None, Some(Value::Datetime(_))) => Value::String(value),
+        (_, Some(parsed)) => parsed,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Config {
+        server: Server,
+        timeout_ms: u64,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Server {
+        listen_address: String,
+        threads: u32,
+    }
+
+    impl Default for Config {
+        fn default() -> Self {
+            Config {
+                server: Server {
+                    listen_address: "127.0.0.1:3000".to_string(),
+                    threads: 1,
+                },
+                timeout_ms: 1000,
+            }
+        }
+    }
+
+    #[test]
+    fn layering() {
+        let mut config = defaults::<Config>().unwrap();
+        merge(
+            &mut config,
+            toml::from_str("timeout_ms = 5000\n[server]\nthreads = 4").unwrap(),
+        );
+        let env = [
+            ("TEST_SERVER__THREADS".to_string(), "8".to_string()),
+            ("OTHER_TIMEOUT_MS".to_string(), "1".to_string()),
+        ];
+        merge_strings(&mut config, env_layer("TEST_", env.into_iter()));
+        let mut flags = Flags::default();
+        flags
+            .set("server.listen_address", Some("0.0.0.0:80"))
+            .set("timeout_ms", None::<i64>);
+        merge_strings(&mut config, flags.0);
+
+        let config: Config = Value::Table(config).try_into().unwrap();
+        assert_eq!(
+            config,
+            Config {
+                server: Server {
+                    listen_address: "0.0.0.0:80".to_string(),
+                    threads: 8,
+                },
+                timeout_ms: 5000,
+            }
+        );
+    }
+
+    #[test]
+    fn strings_keep_their_type() {
+        let mut config = defaults::<Config>().unwrap();
+        let mut flags = Flags::default();
+        for pair in [
+            "server.listen_address=true",
+            "server.threads=4",
+            "name=1979-05-27",
+        ] {
+            flags.set_pair(pair).unwrap();
+        }
+        merge_strings(&mut config, flags.0);
+        assert_eq!(
+            config["server"]["listen_address"],
+            Value::String("true".to_string())
+        );
+        assert_eq!(config["server"]["threads"], Value::Integer(4));
+        assert_eq!(config["name"], Value::String("1979-05-27".to_string()));
+
+        let env = [(
+            "TEST_SERVER__LISTEN_ADDRESS".to_string(),
+            "[::1]:80".to_string(),
+        )];
+        merge_strings(&mut config, env_layer("TEST_", env.into_iter()));
+        assert_eq!(
+            config["server"]["listen_address"],
+            Value::String("[::1]:80".to_string())
+        );
+    }
+}
@@ -0,0 +1,201 @@
+use std::{fs::File, future::Future, io::BufReader, path::Path, sync::Arc, time::Duration};
+
+use axum::Router;
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::conn::auto,
+    service::TowerToHyperService,
+};
+use serde::{Deserialize, Serialize};
+use tokio::{net::TcpListener, sync::watch};
+use tokio_rustls::{rustls, TlsAcceptor};
+
+/// How long a client has to complete the TLS handshake, so that idle connections don't hold a task forever
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// PEM encoded certificate chain and private key to serve HTTPS with
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+    pub cert: String,
+    pub key: String,
+}
+
+/// Serve the app on the listener, over TLS if it is configured, until `shutdown` completes and the open
+/// connections have finished their in-flight requests
+pub async fn serve(
+    listener: TcpListener,
+    app: Router,
+    tls: Option<&TlsConfig>,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> anyhow::Result<()> {
+    let Some(tls) = tls else {
+        axum::serve(listener, app)
+            .with_graceful_shutdown(shutdown)
+            .await?;
+        return Ok(());
+    };
+    let acceptor = acceptor(tls)?;
+    // every connection holds a receiver of both channels, the first one telling it to finish its in-flight
+    // requests and close, the second one being closed once they are all done
+    let (signal_tx, signal_rx) = watch::channel(());
+    let (close_tx, close_rx) = watch::channel(());
+    tokio::pin!(shutdown);
+    loop {
+        let accepted = tokio::select! {
+            accepted = listener.accept() => accepted,
+            () = &mut shutdown => break,
+        };
+        let (stream, peer) = match accepted {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                // e.g. running out of file descriptors, which may resolve itself
+                tracing::warn!(error = ?e, "failed to accept connection");
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+        let acceptor = acceptor.clone();
+        let app = app.clone();
+        let mut signal_rx = signal_rx.clone();
+        let close_rx = close_rx.clone();
+        tokio::spawn(async move {
+            let stream =
+                match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                    Ok(Ok(stream)) => stream,
+                    Ok(Err(e)) => {
+                        tracing::debug!(error = ?e, %peer, "TLS handshake failed");
+                        return;
+                    }
+                    Err(_) => {
+                        tracing::debug!(%peer, "TLS handshake timed out");
+                        return;
+                    }
+                };
+            let builder = auto::Builder::new(TokioExecutor::new());
+            let connection =
+                builder.serve_connection(TokioIo::new(stream), TowerToHyperService::new(app));
+            tokio::pin!(connection);
+            let result = tokio::select! {
+                result = connection.as_mut() => result,
+                _ = signal_rx.changed() => {
+                    connection.as_mut().graceful_shutdown();
+                    connection.await
+                }
+            };
+            if let Err(e) = result {
+                tracing::debug!(error = ?e, %peer, "connection failed");
+            }
+            drop(close_rx);
+        });
+    }
+    drop(listener);
+    drop(close_rx);
+    let _ = signal_tx.send(());
+    close_tx.closed().await;
+    Ok(())
+}
+
+fn acceptor(tls: &TlsConfig) -> anyhow::Result<TlsAcceptor> {
+    let certs = rustls_pemfile::certs(&mut BufReader::new(open(&tls.cert)?))
+        .collect::<Result<Vec<_>, _>>()?;
+    let key = rustls_pemfile::private_key(&mut BufReader::new(open(&tls.key)?))?
+        .ok_or_else(|| anyhow::anyhow!("{} holds no private key", tls.key))?;
+    let config = rustls::ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)?;
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+/// An HTTP client trusting the certificate authority in the PEM file on top of the system's roots, e.g. to reach
+/// servers using a self-signed certificate, whose requests time out as a whole
+pub fn client(ca: Option<&str>, timeout: Duration) -> anyhow::Result<reqwest::Client> {
+    Ok(client_builder(ca)?.timeout(timeout).build()?)
+}
+
+/// A builder of an HTTP client trusting the certificate authority as [`client`] does, with no timeout set yet
+pub fn client_builder(ca: Option<&str>) -> anyhow::Result<reqwest::ClientBuilder> {
+    let mut builder = reqwest::Client::builder();
+    if let Some(ca) = ca {
+        let pem = std::fs::read(ca).map_err(|e| anyhow::anyhow!("can't read {ca}: {e}"))?;
+        for certificate in reqwest::Certificate::from_pem_bundle(&pem)? {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+    Ok(builder)
+}
+
+fn open(path: &str) -> anyhow::Result<File> {
+    File::open(Path::new(path)).map_err(|e| anyhow::anyhow!("can't open {path}: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::routing::get;
+    use tokio::sync::{mpsc, oneshot};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn finishes_in_flight_requests_on_shutdown() {
+        let (started_tx, mut started_rx) = mpsc::channel::<()>(1);
+        let app = Router::new().route(
+            "/",
+            get(move || async move {
+                started_tx.send(()).await.unwrap();
+                tokio::time::sleep(Duration::from_millis(200)).await;
+                "done"
+            }),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+        let server = tokio::spawn(serve(listener, app, None, async {
+            let _ = shutdown_rx.await;
+        }));
+
+        let request = tokio::spawn(reqwest::get(url.clone()));
+        started_rx.recv().await.unwrap();
+        shutdown_tx.send(()).unwrap();
+        let response = request.await.unwrap().unwrap();
+        assert_eq!(response.text().await.unwrap(), "done");
+        server.await.unwrap().unwrap();
+        // no new connections are accepted once drained
+        assert!(reqwest::get(url).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn serves_https() {
+        let testdata = |name| format!("{}/testdata/{name}", env!("CARGO_MANIFEST_DIR"));
+        let tls = TlsConfig {
+            cert: testdata("cert.pem"),
+            key: testdata("key.pem"),
+        };
+        let app = Router::new().route("/", get(|| async { "secure" }));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+        let server = tokio::spawn(async move {
+            serve(listener, app, Some(&tls), async {
+                let _ = shutdown_rx.await;
+            })
+            .await
+        });
+
+        let client = client(Some(&testdata("ca.pem")), Duration::from_secs(5)).unwrap();
+        let response = client
+            .get(format!("https://localhost:{port}/"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.text().await.unwrap(), "secure");
+        // the server's certificate isn't trusted without the CA, and plain HTTP isn't served
+        let untrusted = reqwest::get(format!("https://localhost:{port}/")).await;
+        assert!(untrusted.is_err());
+        let plain = reqwest::get(format!("http://localhost:{port}/")).await;
+        assert!(plain.is_err());
+
+        shutdown_tx.send(()).unwrap();
+        server.await.unwrap().unwrap();
+    }
+}
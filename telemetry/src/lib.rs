@@ -0,0 +1,116 @@
+use axum::{extract::Request, http::HeaderMap, middleware::Next, response::Response};
+use opentelemetry::{global, trace::TracerProvider as _, KeyValue};
+use opentelemetry_http::{HeaderExtractor, HeaderInjector};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{propagation::TraceContextPropagator, runtime, trace, Resource};
+use tracing::{level_filters::LevelFilter, Instrument};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+
+/// Environment variable holding the base url of an OTLP/HTTP collector, spans are only exported when it is set
+pub const OTLP_ENDPOINT_ENV: &str = "OTEL_EXPORTER_OTLP_ENDPOINT";
+
+/// Install the global tracing subscriber and W3C trace context propagator for the given service
+pub fn init(service_name: &'static str) -> anyhow::Result<()> {
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let otlp_endpoint = std::env::var(OTLP_ENDPOINT_ENV).ok();
+    let provider = tracer_provider(service_name, otlp_endpoint.as_deref())?;
+    let tracer = provider.tracer(service_name);
+    global::set_tracer_provider(provider);
+
+    tracing_subscriber::registry()
+        .with(LevelFilter::INFO)
+        .with(tracing_subscriber::fmt::layer())
+        .with(tracing_opentelemetry::layer().with_tracer(tracer))
+        .try_init()?;
+    if let Some(endpoint) = otlp_endpoint {
+        tracing::info!("exporting traces to {}", endpoint);
+    }
+    Ok(())
+}
+
+/// Flush any spans which haven't been exported yet
+pub fn shutdown() {
+    global::shutdown_tracer_provider();
+}
+
+// spans are still given trace ids without an exporter so that context keeps being propagated to downstream services
+fn tracer_provider(
+    service_name: &'static str,
+    otlp_endpoint: Option<&str>,
+) -> anyhow::Result<trace::TracerProvider> {
+    let config = trace::Config::default()
+        .with_resource(Resource::new([KeyValue::new("service.name", service_name)]));
+    let Some(endpoint) = otlp_endpoint else {
+        return Ok(trace::TracerProvider::builder().with_config(config).build());
+    };
+    let exporter = opentelemetry_otlp::new_exporter()
+        .http()
+        .with_endpoint(format!("{}/v1/traces", endpoint.trim_end_matches('/')));
+    let provider = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(exporter)
+        .with_trace_config(config)
+        .install_batch(runtime::Tokio)?;
+    Ok(provider)
+}
+
+/// Middleware continuing the trace from an incoming `traceparent` header, if there is one
+pub async fn propagate(request: Request, next: Next) -> Response {
+    let parent = global::get_text_map_propagator(|propagator| {
+        propagator.extract(&HeaderExtractor(request.headers()))
+    });
+    let span = tracing::info_span!(
+        "request",
+        method = %request.method(),
+        uri = %request.uri(),
+    );
+    span.set_parent(parent);
+    next.run(request).instrument(span).await
+}
+
+/// Add the `traceparent` of the current span to outgoing request headers
+pub fn inject_context(headers: &mut HeaderMap) {
+    let context = tracing::Span::current().context();
+    global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&context, &mut HeaderInjector(headers))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Bytes, extract::State, routing::post, Router};
+    use opentelemetry::trace::{Tracer, TracerProvider as _};
+    use tokio::sync::mpsc;
+
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn exports_spans_to_collector() {
+        // stand-in for an OTLP collector which only records the export requests it receives
+        let (sender, mut receiver) = mpsc::unbounded_channel::<Bytes>();
+        let collector = Router::new()
+            .route(
+                "/v1/traces",
+                post(
+                    |State(sender): State<mpsc::UnboundedSender<Bytes>>, body: Bytes| async move {
+                        sender.send(body).unwrap();
+                    },
+                ),
+            )
+            .with_state(sender);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, collector).await });
+
+        let provider = tracer_provider("test", Some(&endpoint)).unwrap();
+        provider.tracer("test").in_span("execute", |_| {});
+        tokio::task::spawn_blocking(move || provider.force_flush())
+            .await
+            .unwrap();
+
+        let export = receiver.recv().await.unwrap();
+        assert!(export.windows(7).any(|w| w == b"execute"));
+    }
+}
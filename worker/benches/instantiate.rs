@@ -0,0 +1,105 @@
+use api::function::registration::{Function, FunctionSpec, InputKind, Runtime};
+use criterion::{criterion_group, criterion_main, Criterion};
+use wasmtime::{Engine, Linker, Module, Store, Val};
+use wasmtime_wasi::WasiCtxBuilder;
+use worker::{
+    engine::{self, EngineOptions},
+    executor::execute_wasm,
+    function::LoadedFunction,
+};
+
+const ADD: &str = r#"
+(module
+  (memory (export "memory") 17)
+  (func (export "add") (param i32 i32) (result i32)
+    local.get 0
+    local.get 1
+    i32.add))
+"#;
+
+fn load(engine: &Engine) -> LoadedFunction {
+    let linkers = engine::build_linkers(engine).unwrap();
+    let module = Module::new(engine, ADD).unwrap();
+    let function = Function::new(FunctionSpec {
+        name: "add".to_string(),
+        description: String::new(),
+        runtime: Runtime::Wasm,
+        input_type: InputKind::List(Box::new(InputKind::Number)),
+        input_schema: None,
+        output_type: None,
+        output_schema: None,
+        blob_address: "add.wasm".to_string().into(),
+        note: String::new(),
+        egress: Default::default(),
+        retry: None,
+    });
+    LoadedFunction::module(function, &linkers, &module).unwrap()
+}
+
+/// The previous per-request path: a fresh linker with WASI added before every instantiation
+async fn execute_with_fresh_linker(engine: &Engine, module: &Module) -> anyhow::Result<i32> {
+    let mut linker = Linker::new(engine);
+    wasmtime_wasi::preview1::add_to_linker_async(&mut linker, |cx| cx)?;
+    let wasi = WasiCtxBuilder::new()
+        .inherit_stdio()
+        .inherit_args()
+        .build_p1();
+    let mut store = Store::new(engine, wasi);
+    store.set_fuel(u64::MAX)?;
+    let instance = linker.instantiate_async(&mut store, module).await?;
+    instance
+        .get_typed_func::<(i32, i32), i32>(&mut store, "add")?
+        .call_async(&mut store, (1, 2))
+        .await
+}
+
+fn instantiate(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let mut group = c.benchmark_group("instantiate");
+
+    let engine = engine::build_engine(EngineOptions::default()).unwrap();
+    let module = Module::new(&engine, ADD).unwrap();
+    group.bench_function("linker_per_request", |b| {
+        b.to_async(&runtime)
+            .iter(|| async { execute_with_fresh_linker(&engine, &module).await.unwrap() })
+    });
+
+    let function = load(&engine);
+    group.bench_function("instance_pre", |b| {
+        b.to_async(&runtime).iter(|| async {
+            execute_wasm(
+                "add",
+                &function,
+                engine.clone(),
+                "add",
+                &[Val::I32(1), Val::I32(2)],
+            )
+            .await
+            .unwrap()
+        })
+    });
+
+    let pooling_engine = engine::build_engine(EngineOptions {
+        pooling_instances: Some(16),
+    })
+    .unwrap();
+    let function = load(&pooling_engine);
+    group.bench_function("instance_pre_pooling", |b| {
+        b.to_async(&runtime).iter(|| async {
+            execute_wasm(
+                "add",
+                &function,
+                pooling_engine.clone(),
+                "add",
+                &[Val::I32(1), Val::I32(2)],
+            )
+            .await
+            .unwrap()
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, instantiate);
+criterion_main!(benches);
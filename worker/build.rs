@@ -0,0 +1,60 @@
+//! Expose the version of wasmtime the worker is built with, as resolved by cargo for this build, so that workers can
+//! report it to the control plane.
+
+use std::process::Command;
+
+use serde_json::Value;
+
+fn main() {
+    let lock = concat!(env!("CARGO_MANIFEST_DIR"), "/../Cargo.lock");
+    println!("cargo:rerun-if-changed={lock}");
+    let version = wasmtime_version().unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=WASMTIME_VERSION={version}");
+}
+
+/// The version of the `wasmtime` package the worker depends on in the resolved dependency graph
+fn wasmtime_version() -> Option<String> {
+    let cargo = std::env::var("CARGO").ok()?;
+    let output = Command::new(cargo)
+        .args([
+            "metadata",
+            "--format-version",
+            "1",
+            "--offline",
+            "--filter-platform",
+        ])
+        .arg(std::env::var("TARGET").ok()?)
+        .arg("--manifest-path")
+        .arg(concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml"))
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        println!(
+            "cargo:warning=cargo metadata failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        return None;
+    }
+    let metadata: Value = serde_json::from_slice(&output.stdout).ok()?;
+    let packages = metadata["packages"].as_array()?;
+    let id = |name: &str| {
+        packages
+            .iter()
+            .find(|package| package["name"] == name)
+            .and_then(|package| package["id"].as_str())
+    };
+    let worker = id(env!("CARGO_PKG_NAME"))?;
+    // the dependency of the worker rather than any wasmtime in the graph, in case several versions are resolved
+    let node = metadata["resolve"]["nodes"]
+        .as_array()?
+        .iter()
+        .find(|node| node["id"] == worker)?;
+    let wasmtime = node["deps"]
+        .as_array()?
+        .iter()
+        .find(|dep| dep["name"] == "wasmtime")?["pkg"]
+        .as_str()?;
+    packages.iter().find(|package| package["id"] == wasmtime)?["version"]
+        .as_str()
+        .map(str::to_string)
+}
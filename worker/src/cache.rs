@@ -0,0 +1,220 @@
+use std::{
+    fs::File,
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+use sha2::{Digest, Sha256};
+use wasmtime::{component::Component, Engine, Module};
+
+use crate::metrics;
+
+/// On-disk cache of compiled modules and components.
+///
+/// Artifacts are stored as `<dir>/<engine fingerprint>/<sha256 of the wasm>.cwasm`. The fingerprint is derived from
+/// wasmtime's precompile compatibility hash, which covers the wasmtime version, target and compilation settings, so
+/// upgrading wasmtime or changing the engine configuration moves to a fresh directory. Workers of other versions may
+/// share the root during a rolling deploy, so the other directories are only removed once none has opened them for
+/// `STALE_AFTER`.
+pub struct ModuleCache {
+    dir: PathBuf,
+}
+
+/// How long a cache directory is kept after a worker last opened it
+const STALE_AFTER: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+/// Touched whenever a worker opens the directory
+const LAST_OPENED: &str = "last-opened";
+
+impl ModuleCache {
+    pub fn open(root: &Path, engine: &Engine) -> anyhow::Result<Self> {
+        let fingerprint = engine_fingerprint(engine);
+        std::fs::create_dir_all(root)?;
+        let dir = root.join(&fingerprint);
+        std::fs::create_dir_all(&dir)?;
+        File::create(dir.join(LAST_OPENED))?.set_modified(SystemTime::now())?;
+        for entry in std::fs::read_dir(root)? {
+            let entry = entry?;
+            if entry.file_name() == fingerprint.as_str() || !entry.file_type()?.is_dir() {
+                continue;
+            }
+            // a cache which can't be removed is left for the next worker to try again
+            if let Err(e) = remove_if_stale(&entry.path()) {
+                tracing::warn!(error = ?e, "can't remove stale module cache {}", entry.path().display());
+            }
+        }
+        Ok(ModuleCache { dir })
+    }
+
+    /// Load the module or component at `path`, compiling and caching it if there is no usable artifact for it yet
+    pub fn load<T: Compiled>(&self, engine: &Engine, name: &str, path: &Path) -> anyhow::Result<T> {
+        self.load_bytes(engine, name, &std::fs::read(path)?)
+    }
+
+    /// Load a module or component from its wasm bytes, e.g. a blob downloaded from the control plane
+    pub fn load_bytes<T: Compiled>(
+        &self,
+        engine: &Engine,
+        name: &str,
+        wasm: &[u8],
+    ) -> anyhow::Result<T> {
+        let artifact = self.dir.join(format!("{:x}.cwasm", Sha256::digest(wasm)));
+
+        if artifact.exists() {
+            // SAFETY: the artifact was written by `Compiled::serialize` from this cache for the same engine fingerprint
+            match unsafe { T::deserialize_file(engine, &artifact) } {
+                Ok(compiled) => {
+                    metrics::record_module_cache(name, true);
+                    return Ok(compiled);
+                }
+                Err(e) => {
+                    tracing::warn!(error = ?e, "discarding unusable cached artifact {}", artifact.display());
+                }
+            }
+        }
+        metrics::record_module_cache(name, false);
+
+        let start = Instant::now();
+        let compiled = T::compile(engine, wasm)?;
+        metrics::record_compile(name, start.elapsed());
+
+        // write to a temporary file first so that a concurrent or interrupted write never leaves a truncated artifact
+        let tmp = artifact.with_extension(format!("tmp.{}", std::process::id()));
+        std::fs::write(&tmp, compiled.serialize()?)?;
+        std::fs::rename(&tmp, &artifact)?;
+        Ok(compiled)
+    }
+}
+
+/// Compiled wasm which can be stored in the cache
+pub trait Compiled: Sized {
+    fn compile(engine: &Engine, wasm: &[u8]) -> anyhow::Result<Self>;
+    fn serialize(&self) -> anyhow::Result<Vec<u8>>;
+    /// # Safety
+    /// `path` must hold an artifact produced by `serialize`, see `Module::deserialize_file`
+    unsafe fn deserialize_file(engine: &Engine, path: &Path) -> anyhow::Result<Self>;
+}
+
+impl Compiled for Module {
+    fn compile(engine: &Engine, wasm: &[u8]) -> anyhow::Result<Self> {
+        Module::new(engine, wasm)
+    }
+    fn serialize(&self) -> anyhow::Result<Vec<u8>> {
+        Module::serialize(self)
+    }
+    unsafe fn deserialize_file(engine: &Engine, path: &Path) -> anyhow::Result<Self> {
+        Module::deserialize_file(engine, path)
+    }
+}
+
+impl Compiled for Component {
+    fn compile(engine: &Engine, wasm: &[u8]) -> anyhow::Result<Self> {
+        Component::new(engine, wasm)
+    }
+    fn serialize(&self) -> anyhow::Result<Vec<u8>> {
+        Component::serialize(self)
+    }
+    unsafe fn deserialize_file(engine: &Engine, path: &Path) -> anyhow::Result<Self> {
+        Component::deserialize_file(engine, path)
+    }
+}
+
+/// Remove the cache directory of another engine unless a worker has opened it within `STALE_AFTER`
+fn remove_if_stale(dir: &Path) -> anyhow::Result<()> {
+    // caches written before the marker existed are judged by the last artifact added to them
+    let last_opened = match std::fs::metadata(dir.join(LAST_OPENED)) {
+        Ok(metadata) => metadata.modified()?,
+        Err(_) => std::fs::metadata(dir)?.modified()?,
+    };
+    if last_opened.elapsed().unwrap_or_default() > STALE_AFTER {
+        tracing::info!("removing stale module cache {}", dir.display());
+        std::fs::remove_dir_all(dir)?;
+    }
+    Ok(())
+}
+
+/// Feeds what is hashed to SHA-256, whose digest unlike `DefaultHasher`'s is the same across Rust releases
+struct Sha256Hasher(Sha256);
+
+impl Hasher for Sha256Hasher {
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+    fn finish(&self) -> u64 {
+        unreachable!("the digest is read with `finalize`")
+    }
+}
+
+fn engine_fingerprint(engine: &Engine) -> String {
+    let mut hasher = Sha256Hasher(Sha256::new());
+    engine.precompile_compatibility_hash().hash(&mut hasher);
+    // half of the digest is plenty to tell engines apart and keeps the path short
+    format!("{:x}", hasher.0.finalize())[..32].to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{self, EngineOptions};
+
+    const ADD: &str = r#"(module (func (export "add") (param i32 i32) (result i32) local.get 0 local.get 1 i32.add))"#;
+
+    fn engine() -> Engine {
+        engine::build_engine(EngineOptions {
+            pooling_instances: None,
+        })
+        .unwrap()
+    }
+
+    fn root() -> PathBuf {
+        let root = std::env::temp_dir().join(format!("wasi-faas-cache-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&root).unwrap();
+        root
+    }
+
+    #[test]
+    fn fingerprints_are_stable() {
+        let fingerprint = engine_fingerprint(&engine());
+        assert_eq!(fingerprint, engine_fingerprint(&engine()));
+        assert_eq!(fingerprint.len(), 32);
+        assert!(fingerprint.chars().all(|c| c.is_ascii_hexdigit()));
+    }
+
+    #[test]
+    fn caches_compiled_modules() {
+        let root = root();
+        let engine = engine();
+        let cache = ModuleCache::open(&root, &engine).unwrap();
+        let artifacts = || std::fs::read_dir(&cache.dir).unwrap().count();
+        let compiled: Module = cache.load_bytes(&engine, "add", ADD.as_bytes()).unwrap();
+        assert_eq!(artifacts(), 2);
+        let cached: Module = cache.load_bytes(&engine, "add", ADD.as_bytes()).unwrap();
+        assert_eq!(artifacts(), 2);
+        assert!(cached.get_export("add").is_some() && compiled.get_export("add").is_some());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn removes_only_stale_caches() {
+        let root = root();
+        let recent = root.join("recent");
+        let stale = root.join("stale");
+        for dir in [&recent, &stale] {
+            std::fs::create_dir_all(dir).unwrap();
+            File::create(dir.join(LAST_OPENED)).unwrap();
+        }
+        File::create(stale.join(LAST_OPENED))
+            .unwrap()
+            .set_modified(SystemTime::now() - STALE_AFTER - Duration::from_secs(60))
+            .unwrap();
+
+        ModuleCache::open(&root, &engine()).unwrap();
+        assert!(recent.exists());
+        assert!(!stale.exists());
+        assert!(root
+            .join(engine_fingerprint(&engine()))
+            .join(LAST_OPENED)
+            .exists());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,110 @@
+use std::time::Instant;
+
+use api::function::registration::EgressPolicy;
+use wasmtime::component::ResourceTable;
+use wasmtime_wasi::{WasiCtx, WasiCtxBuilder, WasiView};
+use wasmtime_wasi_http::{
+    bindings::http::types::ErrorCode,
+    body::HyperOutgoingBody,
+    types::{default_send_request_handler, HostFutureIncomingResponse, OutgoingRequestConfig},
+    HttpResult, WasiHttpCtx, WasiHttpView,
+};
+
+use crate::report::ExecutionLog;
+
+wasmtime::component::bindgen!({
+    world: "handler",
+    path: "wit",
+    async: true,
+});
+
+/// Store data for component instances, shared by `handler` and `wasi:http/proxy` components
+pub struct ComponentState {
+    ctx: WasiCtx,
+    http: WasiHttpCtx,
+    table: ResourceTable,
+    egress: EgressPolicy,
+    log: ExecutionLog,
+}
+
+impl ComponentState {
+    pub fn new(egress: EgressPolicy, log: ExecutionLog) -> Self {
+        // TODO: bind stdout to a buffer and return the buffer as the response instead
+        let ctx = WasiCtxBuilder::new().inherit_stdio().inherit_args().build();
+        ComponentState {
+            ctx,
+            http: WasiHttpCtx::new(),
+            table: ResourceTable::new(),
+            egress,
+            log,
+        }
+    }
+}
+
+impl WasiView for ComponentState {
+    fn table(&mut self) -> &mut ResourceTable {
+        &mut self.table
+    }
+    fn ctx(&mut self) -> &mut WasiCtx {
+        &mut self.ctx
+    }
+}
+
+impl WasiHttpView for ComponentState {
+    fn ctx(&mut self) -> &mut WasiHttpCtx {
+        &mut self.http
+    }
+    fn table(&mut self) -> &mut ResourceTable {
+        &mut self.table
+    }
+
+    /// Only send requests to hosts on the function's allowlist, bounding every phase of the request by its timeout
+    fn send_request(
+        &mut self,
+        request: hyper::Request<HyperOutgoingBody>,
+        config: OutgoingRequestConfig,
+    ) -> HttpResult<HostFutureIncomingResponse> {
+        let call = format!("{} {}", request.method(), request.uri());
+        let Some(host) = request.uri().host() else {
+            self.log.push(format!("outbound {call} rejected: no host"));
+            return Err(ErrorCode::HttpRequestUriInvalid.into());
+        };
+        let port = request
+            .uri()
+            .port_u16()
+            .unwrap_or(if config.use_tls { 443 } else { 80 });
+        if !self.egress.allows(host, port) {
+            tracing::warn!(host, port, "outbound request denied");
+            self.log.push(format!(
+                "outbound {call} denied: {host}:{port} is not allowed"
+            ));
+            return Err(ErrorCode::HttpRequestDenied.into());
+        }
+
+        let timeout = self.egress.timeout();
+        let config = OutgoingRequestConfig {
+            use_tls: config.use_tls,
+            connect_timeout: config.connect_timeout.min(timeout),
+            first_byte_timeout: config.first_byte_timeout.min(timeout),
+            between_bytes_timeout: config.between_bytes_timeout.min(timeout),
+        };
+        let log = self.log.clone();
+        let handle = wasmtime_wasi::runtime::spawn(async move {
+            let start = Instant::now();
+            let response =
+                tokio::time::timeout(timeout, default_send_request_handler(request, config))
+                    .await
+                    .unwrap_or(Err(ErrorCode::ConnectionTimeout));
+            let elapsed = start.elapsed();
+            match &response {
+                Ok(response) => log.push(format!(
+                    "outbound {call} -> {} in {elapsed:?}",
+                    response.resp.status()
+                )),
+                Err(e) => log.push(format!("outbound {call} failed in {elapsed:?}: {e}")),
+            }
+            Ok(response)
+        });
+        Ok(HostFutureIncomingResponse::pending(handle))
+    }
+}
@@ -0,0 +1,130 @@
+use serde::{Deserialize, Serialize};
+use settings::ServerConfig;
+
+/// Prefix of the environment variables overriding the configuration, e.g. `WASI_FAAS_WORKER_SERVER__LISTEN_ADDRESS`
+pub const ENV_PREFIX: &str = "WASI_FAAS_WORKER_";
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct WorkerConfig {
+    pub server: ServerConfig,
+    pub control_plane: ControlPlaneConfig,
+    pub storage: StorageConfig,
+    pub engine: EngineConfig,
+    pub limits: LimitsConfig,
+    pub shutdown: ShutdownConfig,
+}
+
+impl Default for WorkerConfig {
+    fn default() -> Self {
+        WorkerConfig {
+            server: ServerConfig::new("127.0.0.1:3001"),
+            control_plane: ControlPlaneConfig {
+                address: "http://localhost:3000".to_string(),
+                ca: None,
+                heartbeat_interval_ms: 5_000,
+                max_backoff_ms: 30_000,
+                timeout_ms: 30_000,
+            },
+            storage: StorageConfig {
+                worker_id_file: "data/worker_id".to_string(),
+                function_dir: "functions-sample/".to_string(),
+                module_cache_dir: "data/module-cache".to_string(),
+            },
+            engine: EngineConfig {
+                pooling_instances: None,
+            },
+            limits: LimitsConfig {
+                max_concurrency: 64,
+                max_loaded_revisions: 100,
+            },
+            shutdown: ShutdownConfig {
+                drain_timeout_ms: 30_000,
+            },
+        }
+    }
+}
+
+impl WorkerConfig {
+    /// The executions the worker runs at once, there can't be more than instance slots
+    pub fn capacity(&self) -> anyhow::Result<u32> {
+        anyhow::ensure!(
+            self.limits.max_concurrency > 0,
+            "limits.max_concurrency must be at least 1"
+        );
+        anyhow::ensure!(
+            self.engine.pooling_instances != Some(0),
+            "engine.pooling_instances must be at least 1"
+        );
+        Ok(self
+            .engine
+            .pooling_instances
+            .map_or(self.limits.max_concurrency, |instances| {
+                instances.min(self.limits.max_concurrency)
+            }))
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ControlPlaneConfig {
+    pub address: String,
+    /// PEM file of the certificate authority of a control plane serving HTTPS with a private certificate
+    pub ca: Option<String>,
+    pub heartbeat_interval_ms: u64,
+    /// Longest delay between retries while heartbeats are failing
+    pub max_backoff_ms: u64,
+    /// How long the control plane has to respond to registration, heartbeats, reports and downloads
+    pub timeout_ms: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct StorageConfig {
+    pub worker_id_file: String,
+    /// Directory the functions registered with a file name instead of an uploaded blob are read from
+    pub function_dir: String,
+    /// Directory holding compiled modules so they aren't recompiled on every start
+    pub module_cache_dir: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct EngineConfig {
+    /// Pre-allocate this many instance slots with the pooling allocator
+    pub pooling_instances: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct LimitsConfig {
+    /// Executions run at once, further ones are rejected so that the gateway sends them to another worker
+    pub max_concurrency: u32,
+    /// Revisions kept loaded, the least recently used ones being evicted beyond it
+    pub max_loaded_revisions: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ShutdownConfig {
+    /// How long in-flight executions have to finish once the worker is draining, they are abandoned afterwards
+    pub drain_timeout_ms: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capacity() {
+        let mut config = WorkerConfig::default();
+        assert_eq!(config.capacity().unwrap(), 64);
+        config.engine.pooling_instances = Some(8);
+        assert_eq!(config.capacity().unwrap(), 8);
+        config.engine.pooling_instances = Some(0);
+        assert!(config.capacity().is_err());
+        config.engine.pooling_instances = None;
+        config.limits.max_concurrency = 0;
+        assert!(config.capacity().is_err());
+    }
+}
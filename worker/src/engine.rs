@@ -0,0 +1,82 @@
+use wasmtime::{
+    component, Config, Engine, InstanceAllocationStrategy, Linker, PoolingAllocationConfig,
+};
+use wasmtime_wasi::preview1::WasiP1Ctx;
+
+use crate::component::ComponentState;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct EngineOptions {
+    /// Number of instance slots to pre-allocate with the pooling allocator, on-demand allocation is used when unset
+    pub pooling_instances: Option<u32>,
+}
+
+pub fn build_engine(options: EngineOptions) -> anyhow::Result<Engine> {
+    let mut config = Config::new();
+    config.async_support(true);
+    config.consume_fuel(true);
+    config.wasm_component_model(true);
+    if let Some(instances) = options.pooling_instances {
+        let mut pooling = PoolingAllocationConfig::default();
+        pooling
+            .total_core_instances(instances)
+            .total_component_instances(instances)
+            .total_memories(instances)
+            .total_tables(instances)
+            .total_stacks(instances);
+        config.allocation_strategy(InstanceAllocationStrategy::Pooling(pooling));
+    }
+    Engine::new(&config)
+}
+
+/// Linkers shared by every function instantiated on the engine
+pub struct Linkers {
+    pub module: Linker<WasiP1Ctx>,
+    pub component: component::Linker<ComponentState>,
+}
+
+pub fn build_linkers(engine: &Engine) -> anyhow::Result<Linkers> {
+    let mut module = Linker::new(engine);
+    wasmtime_wasi::preview1::add_to_linker_async(&mut module, |cx| cx)?;
+    let mut component = component::Linker::new(engine);
+    wasmtime_wasi::add_to_linker_async(&mut component)?;
+    wasmtime_wasi_http::add_only_http_to_linker_async(&mut component)?;
+    Ok(Linkers { module, component })
+}
+
+#[cfg(test)]
+mod tests {
+    use wasmtime::{Module, Store};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn pooling_bounds_live_instances() {
+        let engine = build_engine(EngineOptions {
+            pooling_instances: Some(1),
+        })
+        .unwrap();
+        let module = Module::new(&engine, "(module (memory 1))").unwrap();
+        let pre = Linker::<()>::new(&engine).instantiate_pre(&module).unwrap();
+
+        let mut first = Store::new(&engine, ());
+        pre.instantiate_async(&mut first).await.unwrap();
+        // the only slot is taken until the store holding the instance is dropped
+        let mut second = Store::new(&engine, ());
+        assert!(pre.instantiate_async(&mut second).await.is_err());
+        drop(first);
+        let mut third = Store::new(&engine, ());
+        pre.instantiate_async(&mut third).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn on_demand_allocation_is_unbounded() {
+        let engine = build_engine(EngineOptions::default()).unwrap();
+        let module = Module::new(&engine, "(module (memory 1))").unwrap();
+        let pre = Linker::<()>::new(&engine).instantiate_pre(&module).unwrap();
+        let mut stores: Vec<_> = (0..4).map(|_| Store::new(&engine, ())).collect();
+        for store in &mut stores {
+            pre.instantiate_async(store).await.unwrap();
+        }
+    }
+}
@@ -1,96 +1,386 @@
-use std::{collections::BTreeMap, sync::Arc};
+use std::{sync::Arc, time::Instant};
 
-use axum::{extract::State, response::IntoResponse, Json};
-use wasmtime::{Engine, Linker, Module, Store};
-use wasmtime_wasi::WasiCtxBuilder;
+use api::{
+    function::{
+        execution::{
+            ExecutionId, Output, EXECUTION_ID_HEADER, FUNCTION_ID_HEADER, REVISION_HEADER,
+        },
+        registration::{FunctionId, RevisionNumber, Runtime},
+    },
+    types::{ExitKind, JsonData, TimeStamp},
+    worker::WORKER_FULL_HEADER,
+};
+use axum::{
+    body::{Body, Bytes},
+    extract::{Path, Request, State},
+    http::{uri::InvalidUri, Request as HttpRequest, Response as HttpResponse, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use http_body_util::{BodyExt, Full};
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::sync::oneshot;
+use tracing::Instrument;
+use wasmtime::{Engine, ExternType, Store, Trap, Val, ValType};
+use wasmtime_wasi::{preview1::WasiP1Ctx, I32Exit, WasiCtxBuilder};
+use wasmtime_wasi_http::{bindings::http::types::Scheme, body::HyperOutgoingBody, WasiHttpView};
 
-#[tracing::instrument(level = "info", skip(function_map, engine))]
-pub async fn execute_hello(
-    State((function_map, engine)): State<(Arc<BTreeMap<String, Module>>, wasmtime::Engine)>,
-) -> impl IntoResponse {
-    tracing::info!("executing");
+use crate::{
+    component::{self, ComponentState},
+    function::{FunctionRegistry, Instantiable, LoadedFunction},
+    metrics,
+    report::{ExecutionLog, Reporter},
+    status::Status,
+};
 
-    let module = function_map
-        .get("hello")
-        .ok_or_else(|| anyhow::anyhow!("Couldn't load the hello function"))
-        .unwrap();
-    execute_wasm::<(), ()>(module, engine, "_start", ())
-        .await
-        .unwrap();
+#[derive(Clone)]
+pub struct ExecutorState {
+    pub functions: Arc<FunctionRegistry>,
+    pub engine: Engine,
+    pub reporter: Reporter,
+    pub status: Status,
 }
 
-#[tracing::instrument(level = "info", skip(function_map, engine))]
-pub async fn execute_add(
-    State((function_map, engine)): State<(Arc<BTreeMap<String, Module>>, wasmtime::Engine)>,
-    Json((param1, param2)): Json<(i32, i32)>,
-) -> impl IntoResponse {
-    tracing::info!("executing");
-    let module = function_map
-        .get("add")
-        .ok_or_else(|| anyhow::anyhow!("Couldn't load the add function"))
-        .unwrap();
-    let result = execute_wasm::<(i32, i32), i32>(module, engine, "add", (param1, param2))
-        .await
-        .unwrap();
-    Json(result)
+#[derive(Debug, Deserialize)]
+pub struct FunctionPath {
+    function: String,
+    path: Option<String>,
 }
 
-#[tracing::instrument(level = "info", skip(function_map, engine))]
-pub async fn execute_sub(
-    State((function_map, engine)): State<(Arc<BTreeMap<String, Module>>, wasmtime::Engine)>,
-    Json((param1, param2)): Json<(i32, i32)>,
-) -> impl IntoResponse {
+/// Execute a function according to its runtime. `Wasm` and `Component` functions receive the JSON payload while
+/// `HttpHandler` functions receive the request as is with the path under the function's name.
+///
+/// Requests dispatched by the gateway carry an execution id, their result is reported back to the control plane. They
+/// also carry the revision to execute, other requests execute the function's current revision.
+#[tracing::instrument(level = "info", skip(state, request))]
+pub async fn execute(
+    State(state): State<ExecutorState>,
+    Path(FunctionPath { function, path }): Path<FunctionPath>,
+    request: Request,
+) -> Response {
     tracing::info!("executing");
-    let module = function_map
-        .get("sub")
-        .ok_or_else(|| anyhow::anyhow!("Couldn't load the sub function"))
-        .unwrap();
-    let result = execute_wasm::<(i32, i32), i32>(module, engine, "sub", (param1, param2))
-        .await
-        .unwrap();
-    Json(result)
+    let Some(in_flight) = state.status.try_enter() else {
+        tracing::warn!("rejecting execution, the worker is at capacity");
+        metrics::record_rejection(&function);
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            [(WORKER_FULL_HEADER, "true")],
+            "the worker is at capacity",
+        )
+            .into_response();
+    };
+    let revision = request
+        .headers()
+        .get(REVISION_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| RevisionNumber::parse(value).ok());
+    let id = request
+        .headers()
+        .get(FUNCTION_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| FunctionId::parse(value).ok());
+    let loaded = match state.functions.get(&function, id, revision).await {
+        Ok(Some(loaded)) => loaded,
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                format!("function {function} not found"),
+            )
+                .into_response()
+        }
+        Err(e) => {
+            tracing::error!(error = ?e, "failed to load function");
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("failed to load {function}: {e:#}"),
+            )
+                .into_response();
+        }
+    };
+    let loaded = &*loaded;
+    let execution = request
+        .headers()
+        .get(EXECUTION_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| ExecutionId::parse(value).ok());
+    let start_time = TimeStamp::now();
+    let log = ExecutionLog::default();
+
+    let (exit, result) = match loaded.runtime() {
+        Runtime::Wasm => execute_module(&function, loaded, state.engine, request).await,
+        Runtime::Component => {
+            execute_handler(&function, execution, loaded, state.engine, request, &log).await
+        }
+        Runtime::HttpHandler => {
+            let reporter = state.reporter.clone();
+            // the handler's body is streamed after the response is returned, it is in flight until it exits
+            let on_exit = move |exit: ExitKind, error: Option<String>, log: ExecutionLog| {
+                drop(in_flight);
+                if let Some(execution) = execution {
+                    let outcome = error.map_or(Ok(None), Err);
+                    reporter.report(execution, start_time, outcome, exit, &log);
+                }
+            };
+            return execute_http_handler(
+                &function,
+                path.as_deref(),
+                loaded,
+                state.engine,
+                request,
+                log,
+                on_exit,
+            )
+            .await
+            .into_response();
+        }
+    };
+
+    // a guest which ran successfully but broke its declared output contract still failed
+    let (exit, result) = match result {
+        Ok(output) => match loaded.function().validate_output(output.value()) {
+            Ok(()) => (exit, Ok(output)),
+            Err(e) => (
+                ExitKind::Failure { exit_code: 1 },
+                Err((
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("function returned invalid output: {e}"),
+                )),
+            ),
+        },
+        Err(e) => (exit, Err(e)),
+    };
+    // counted once the exit is final so that the metric agrees with the reported result
+    metrics::record_invocation(&function, &exit);
+    if let Some(execution) = execution {
+        let outcome = match &result {
+            Ok(output) => Ok(Some(Output::from(output.clone()))),
+            Err((_, reason)) => Err(reason.clone()),
+        };
+        state
+            .reporter
+            .report(execution, start_time, outcome, exit, &log);
+    }
+    result.map(Json).into_response()
 }
 
-#[tracing::instrument(level = "info", skip(function_map, engine))]
-pub async fn execute_mul(
-    State((function_map, engine)): State<(Arc<BTreeMap<String, Module>>, wasmtime::Engine)>,
-    Json((param1, param2)): Json<(i32, i32)>,
-) -> impl IntoResponse {
-    tracing::info!("executing");
-    let module = function_map
-        .get("mul")
-        .ok_or_else(|| anyhow::anyhow!("Couldn't load the mul function"))
-        .unwrap();
-    let result = execute_wasm::<(i32, i32), i32>(module, engine, "mul", (param1, param2))
+type JsonResult = Result<JsonData, (StatusCode, String)>;
+
+/// An empty body is treated as `null` so that functions without input can be invoked without one
+async fn json_input(request: Request) -> Result<Value, (StatusCode, String)> {
+    let body = request
+        .into_body()
+        .collect()
         .await
-        .unwrap();
-    Json(result)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?
+        .to_bytes();
+    if body.is_empty() {
+        return Ok(Value::Null);
+    }
+    serde_json::from_slice(&body).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))
 }
 
-#[tracing::instrument(level = "info", skip(function_map, engine))]
-pub async fn execute_div(
-    State((function_map, engine)): State<(Arc<BTreeMap<String, Module>>, wasmtime::Engine)>,
-    Json((param1, param2)): Json<(i32, i32)>,
-) -> impl IntoResponse {
-    tracing::info!("executing");
-    let module = function_map
-        .get("div")
-        .ok_or_else(|| anyhow::anyhow!("Couldn't load the div function"))
-        .unwrap();
-    let result = execute_wasm::<(i32, i32), i32>(module, engine, "div", (param1, param2))
+async fn execute_module(
+    function_name: &str,
+    function: &LoadedFunction,
+    engine: Engine,
+    request: Request,
+) -> (ExitKind, JsonResult) {
+    let input = match json_input(request).await {
+        Ok(input) => input,
+        Err(e) => return (ExitKind::Failure { exit_code: 1 }, Err(e)),
+    };
+    let (entrypoint, params) = match module_params(function_name, function, &input) {
+        Ok(resolved) => resolved,
+        Err(reason) => {
+            return (
+                ExitKind::Failure { exit_code: 1 },
+                Err((StatusCode::UNPROCESSABLE_ENTITY, reason)),
+            )
+        }
+    };
+    let result = execute_wasm(function_name, function, engine, &entrypoint, &params).await;
+    let exit = exit_kind(&result);
+    let result = match result {
+        Ok(results) => Ok(JsonData::from(results_to_json(&results))),
+        // commands exit through `proc_exit`, an exit code of 0 is a successful run without output
+        Err(_) if exit == ExitKind::Success => Ok(JsonData::from(Value::Null)),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, format!("{e:#}"))),
+    };
+    (exit, result)
+}
+
+async fn execute_handler(
+    function_name: &str,
+    execution: Option<ExecutionId>,
+    function: &LoadedFunction,
+    engine: Engine,
+    request: Request,
+    log: &ExecutionLog,
+) -> (ExitKind, JsonResult) {
+    let input = match json_input(request).await {
+        Ok(input) => input,
+        Err(e) => return (ExitKind::Failure { exit_code: 1 }, Err(e)),
+    };
+    let request = component::Request {
+        id: execution.unwrap_or_default().to_string(),
+        function: function_name.to_string(),
+        input: input.to_string(),
+    };
+    let result = call_component(function_name, function, engine, &request, log).await;
+    let mut exit = match &result {
+        Ok(Err(_)) => ExitKind::Failure { exit_code: 1 },
+        _ => exit_kind(&result),
+    };
+    let result = match result {
+        Ok(Ok(response)) => JsonData::try_from(response.output.into_bytes()).map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("function returned invalid JSON: {e}"),
+            )
+        }),
+        Ok(Err(component::Error::InvalidInput(reason))) => {
+            Err((StatusCode::UNPROCESSABLE_ENTITY, reason))
+        }
+        Ok(Err(component::Error::Failed(reason))) => {
+            Err((StatusCode::INTERNAL_SERVER_ERROR, reason))
+        }
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+    };
+    // a guest which returned output that isn't JSON failed even though it ran to completion
+    if result.is_err() && exit == ExitKind::Success {
+        exit = ExitKind::Failure { exit_code: 1 };
+    }
+    (exit, result)
+}
+
+async fn execute_http_handler(
+    function_name: &str,
+    path: Option<&str>,
+    function: &LoadedFunction,
+    engine: Engine,
+    request: Request,
+    log: ExecutionLog,
+    on_exit: impl FnOnce(ExitKind, Option<String>, ExecutionLog) + Send + 'static,
+) -> Result<Response, (StatusCode, String)> {
+    let (mut parts, body) = request.into_parts();
+    let body = body
+        .collect()
         .await
-        .unwrap();
-    Json(result)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?
+        .to_bytes();
+    // the guest only sees the part of the path under its own name
+    let path_and_query = match parts.uri.query() {
+        Some(query) => format!("/{}?{query}", path.unwrap_or_default()),
+        None => format!("/{}", path.unwrap_or_default()),
+    };
+    parts.uri = path_and_query
+        .parse()
+        .map_err(|e: InvalidUri| (StatusCode::BAD_REQUEST, e.to_string()))?;
+
+    match call_http_handler(
+        function_name,
+        function,
+        engine,
+        HttpRequest::from_parts(parts, body),
+        log,
+        on_exit,
+    )
+    .await
+    {
+        Ok(response) => Ok(response.map(Body::new)),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+    }
+}
+
+/// Resolve the entrypoint of a core wasm module and convert the JSON input to its params.
+///
+/// The entrypoint is the export named after the function, falling back to `_start` for commands. A list is passed as
+/// positional params, `null` as no params and any other value as the only param.
+pub fn module_params(
+    function_name: &str,
+    function: &LoadedFunction,
+    input: &Value,
+) -> Result<(String, Vec<Val>), String> {
+    let Instantiable::Module(pre) = function.instantiable() else {
+        return Err(format!("{function_name} is not a core wasm module"));
+    };
+    let (entrypoint, ty) = [function_name, "_start"]
+        .into_iter()
+        .find_map(|name| match pre.module().get_export(name) {
+            Some(ExternType::Func(ty)) => Some((name.to_string(), ty)),
+            _ => None,
+        })
+        .ok_or_else(|| format!("{function_name} exports neither `{function_name}` nor `_start`"))?;
+
+    let args = match input {
+        Value::Null => vec![],
+        Value::Array(values) => values.iter().collect(),
+        value => vec![value],
+    };
+    let params = ty.params().collect::<Vec<_>>();
+    if args.len() != params.len() {
+        return Err(format!(
+            "expected {} params but got {}",
+            params.len(),
+            args.len()
+        ));
+    }
+    let params = params
+        .iter()
+        .zip(args)
+        .enumerate()
+        .map(|(i, (ty, arg))| {
+            to_val(ty, arg).ok_or_else(|| format!("param {i} should be {ty} but got {arg}"))
+        })
+        .collect::<Result<_, _>>()?;
+    Ok((entrypoint, params))
+}
+
+fn to_val(ty: &ValType, value: &Value) -> Option<Val> {
+    match ty {
+        ValType::I32 => value
+            .as_i64()
+            .and_then(|v| i32::try_from(v).ok())
+            .map(Val::I32),
+        ValType::I64 => value.as_i64().map(Val::I64),
+        ValType::F32 => value.as_f64().map(|v| Val::F32((v as f32).to_bits())),
+        ValType::F64 => value.as_f64().map(|v| Val::F64(v.to_bits())),
+        _ => None,
+    }
 }
 
-async fn execute_wasm<Input: wasmtime::WasmParams, Output: wasmtime::WasmResults>(
-    module: &Module,
+/// No results become `null`, a single result its value and several a list
+fn results_to_json(results: &[Val]) -> Value {
+    let mut values = results
+        .iter()
+        .map(|val| match val {
+            Val::I32(v) => Value::from(*v),
+            Val::I64(v) => Value::from(*v),
+            Val::F32(v) => Value::from(f32::from_bits(*v)),
+            Val::F64(v) => Value::from(f64::from_bits(*v)),
+            _ => Value::Null,
+        })
+        .collect::<Vec<_>>();
+    match values.len() {
+        0 => Value::Null,
+        1 => values.remove(0),
+        _ => Value::Array(values),
+    }
+}
+
+pub async fn execute_wasm(
+    function_name: &str,
+    function: &LoadedFunction,
     engine: Engine,
     entrypoint_name: &str,
-    params: Input,
-) -> anyhow::Result<Output> {
-    let mut linker = Linker::new(&engine);
-    wasmtime_wasi::preview1::add_to_linker_async(&mut linker, |cx| cx)?;
+    params: &[Val],
+) -> anyhow::Result<Vec<Val>> {
+    let Instantiable::Module(pre) = function.instantiable() else {
+        anyhow::bail!("{function_name} is not a core wasm module");
+    };
+    record_cold_start(function_name, function);
+    let start = Instant::now();
 
     // TODO: bind stdout to a buffer and return the buffer as the response instead
     let wasi = WasiCtxBuilder::new()
@@ -98,10 +388,296 @@ async fn execute_wasm<Input: wasmtime::WasmParams, Output: wasmtime::WasmResults
         .inherit_args()
         .build_p1();
     let mut store = Store::new(&engine, wasi);
-    let instance = linker.instantiate_async(&mut store, module).await?;
-    instance
-        .get_typed_func::<Input, Output>(&mut store, entrypoint_name)
-        .map_err(|err| anyhow::anyhow!("Couldn't find the entrypoint: {}", err))?
-        .call_async(&mut store, params)
-        .await
+    store.set_fuel(u64::MAX)?;
+    let result = call_wasm(&mut store, pre, entrypoint_name, params).await;
+
+    let fuel_consumed = u64::MAX - store.get_fuel()?;
+    metrics::record_run(function_name, start.elapsed(), fuel_consumed);
+    result
+}
+
+async fn call_wasm(
+    store: &mut Store<WasiP1Ctx>,
+    pre: &wasmtime::InstancePre<WasiP1Ctx>,
+    entrypoint_name: &str,
+    params: &[Val],
+) -> anyhow::Result<Vec<Val>> {
+    let instance = pre
+        .instantiate_async(&mut *store)
+        .instrument(tracing::info_span!("instantiate"))
+        .await?;
+    let func = instance
+        .get_func(&mut *store, entrypoint_name)
+        .ok_or_else(|| anyhow::anyhow!("Couldn't find the entrypoint: {entrypoint_name}"))?;
+    let mut results = vec![Val::I32(0); func.ty(&*store).results().len()];
+    func.call_async(&mut *store, params, &mut results)
+        .instrument(tracing::info_span!("call", entrypoint = entrypoint_name))
+        .await?;
+    Ok(results)
+}
+
+/// Invoke the `handle` export of a component function, guest errors are returned in the inner result
+pub async fn call_component(
+    function_name: &str,
+    function: &LoadedFunction,
+    engine: Engine,
+    request: &component::Request,
+    log: &ExecutionLog,
+) -> anyhow::Result<Result<component::Response, component::Error>> {
+    let Instantiable::Component(pre) = function.instantiable() else {
+        anyhow::bail!("{function_name} is not a component");
+    };
+    record_cold_start(function_name, function);
+    let start = Instant::now();
+
+    let state = ComponentState::new(function.function().egress().clone(), log.clone());
+    let mut store = Store::new(&engine, state);
+    store.set_fuel(u64::MAX)?;
+    let result = async {
+        let handler = pre
+            .instantiate_async(&mut store)
+            .instrument(tracing::info_span!("instantiate"))
+            .await?;
+        handler
+            .call_handle(&mut store, request)
+            .instrument(tracing::info_span!("call", entrypoint = "handle"))
+            .await
+    }
+    .await;
+
+    let fuel_consumed = u64::MAX - store.get_fuel()?;
+    metrics::record_run(function_name, start.elapsed(), fuel_consumed);
+    result
+}
+
+/// Invoke the `wasi:http/incoming-handler` export of a component function.
+///
+/// The guest keeps running in a separate task after it has set the response so that the body can be streamed back,
+/// `on_exit` is called from that task with the exit and failure reason once the guest has returned.
+pub async fn call_http_handler(
+    function_name: &str,
+    function: &LoadedFunction,
+    engine: Engine,
+    request: HttpRequest<Bytes>,
+    log: ExecutionLog,
+    on_exit: impl FnOnce(ExitKind, Option<String>, ExecutionLog) + Send + 'static,
+) -> anyhow::Result<HttpResponse<HyperOutgoingBody>> {
+    let Instantiable::HttpHandler(pre) = function.instantiable() else {
+        anyhow::bail!("{function_name} is not an http handler");
+    };
+    record_cold_start(function_name, function);
+    let start = Instant::now();
+
+    let state = ComponentState::new(function.function().egress().clone(), log.clone());
+    let mut store = Store::new(&engine, state);
+    store.set_fuel(u64::MAX)?;
+    let (sender, receiver) = oneshot::channel();
+    let request = store.data_mut().new_incoming_request(
+        Scheme::Http,
+        request.map(|body| Full::new(body).map_err(|never| match never {})),
+    )?;
+    let response = store.data_mut().new_response_outparam(sender)?;
+
+    let pre = pre.clone();
+    let function_name = function_name.to_string();
+    let task = tokio::spawn(
+        async move {
+            let result = async {
+                let proxy = pre
+                    .instantiate_async(&mut store)
+                    .instrument(tracing::info_span!("instantiate"))
+                    .await?;
+                proxy
+                    .wasi_http_incoming_handler()
+                    .call_handle(&mut store, request, response)
+                    .instrument(tracing::info_span!("call", entrypoint = "handle"))
+                    .await
+            }
+            .await;
+            let fuel_consumed = u64::MAX - store.get_fuel().unwrap_or(u64::MAX);
+            let exit = exit_kind(&result);
+            metrics::record_execution(&function_name, &exit, start.elapsed(), fuel_consumed);
+            let error = result.as_ref().err().map(|e| format!("{e:#}"));
+            on_exit(exit, error, log);
+            result
+        }
+        .in_current_span(),
+    );
+
+    match receiver.await {
+        Ok(Ok(response)) => Ok(response),
+        Ok(Err(error)) => Err(anyhow::anyhow!("function failed to respond: {error:?}")),
+        // the guest returned or trapped without setting a response
+        Err(_) => match task.await? {
+            Ok(()) => Err(anyhow::anyhow!("function didn't set a response")),
+            Err(e) => Err(e),
+        },
+    }
+}
+
+fn record_cold_start(function_name: &str, function: &LoadedFunction) {
+    if function.mark_invoked() {
+        metrics::record_cold_start(function_name);
+    }
+}
+
+/// Classify the outcome of a wasm call, a WASI `proc_exit` is reported with its exit code
+fn exit_kind<T>(result: &anyhow::Result<T>) -> ExitKind {
+    match result {
+        Ok(_) => ExitKind::Success,
+        Err(err) => match err.downcast_ref::<I32Exit>() {
+            Some(I32Exit(0)) => ExitKind::Success,
+            Some(I32Exit(code)) => ExitKind::Failure {
+                exit_code: u8::try_from(*code).unwrap_or(u8::MAX),
+            },
+            None if err.downcast_ref::<Trap>() == Some(&Trap::Interrupt) => ExitKind::TimeOut,
+            None => ExitKind::Failure { exit_code: 1 },
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use api::{
+        function::{
+            execution::ExecutionResult,
+            registration::{EgressPolicy, Function, FunctionSpec, InputKind},
+        },
+        worker::Worker,
+    };
+    use axum::{
+        extract::State,
+        routing::{any, put},
+        Router,
+    };
+    use reqwest::Client;
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::{
+        cache::ModuleCache,
+        engine::{self, EngineOptions},
+    };
+
+    const ADD: &str = r#"
+        (module
+          (func (export "add") (param i32 i32) (result i32)
+            local.get 0
+            local.get 1
+            i32.add))
+    "#;
+
+    fn add(output_type: InputKind) -> Function {
+        Function::new(FunctionSpec {
+            name: "add".to_string(),
+            description: String::new(),
+            runtime: Runtime::Wasm,
+            input_type: InputKind::List(Box::new(InputKind::Number)),
+            input_schema: None,
+            output_type: Some(output_type),
+            output_schema: None,
+            blob_address: "add.wat".to_string().into(),
+            note: String::new(),
+            egress: EgressPolicy::default(),
+            retry: None,
+        })
+    }
+
+    /// Serve the executor, the results it reports being sent on the returned channel
+    async fn executor(
+        dir: &Path,
+        functions: Vec<Function>,
+    ) -> (String, mpsc::UnboundedReceiver<ExecutionResult>) {
+        let (results, received) = mpsc::unbounded_channel();
+        let complete = |State(results): State<mpsc::UnboundedSender<ExecutionResult>>,
+                        Json(result): Json<ExecutionResult>| async move {
+            results.send(result).unwrap();
+        };
+        let control_plane = Router::new()
+            .route("/executions/:id/result", put(complete))
+            .with_state(results);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let control_plane_address = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, control_plane).await });
+
+        let engine = engine::build_engine(EngineOptions {
+            pooling_instances: None,
+        })
+        .unwrap();
+        let registry = Arc::new(
+            FunctionRegistry::new(
+                engine.clone(),
+                engine::build_linkers(&engine).unwrap(),
+                ModuleCache::open(&dir.join("cache"), &engine).unwrap(),
+                Client::new(),
+                control_plane_address.clone(),
+                dir,
+                16,
+            )
+            .unwrap(),
+        );
+        assert_eq!(
+            registry.preload(functions.clone()).await.len(),
+            functions.len()
+        );
+        let worker = Worker::new("127.0.0.1:0".to_string().into());
+        let state = ExecutorState {
+            functions: registry.clone(),
+            engine,
+            reporter: Reporter::new(Client::new(), control_plane_address, *worker.id()),
+            status: Status::new(registry, 4),
+        };
+        let app = Router::new()
+            .route("/:function", any(execute))
+            .with_state(state);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        (address, received)
+    }
+
+    #[tokio::test]
+    async fn malformed_output_fails_the_execution() {
+        let dir = std::env::temp_dir().join(format!("wasi-faas-executor-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("add.wat"), ADD).unwrap();
+        let number = add(InputKind::Number);
+        let string = add(InputKind::String);
+        let (address, mut results) = executor(&dir, vec![number.clone(), string.clone()]).await;
+
+        for (function, status, exit) in [
+            (&number, StatusCode::OK, ExitKind::Success),
+            (
+                &string,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ExitKind::Failure { exit_code: 1 },
+            ),
+        ] {
+            let execution = uuid::Uuid::new_v4().simple().to_string();
+            let response = Client::new()
+                .post(format!("{address}/add"))
+                .header(FUNCTION_ID_HEADER, function.id().to_string())
+                .header(
+                    REVISION_HEADER,
+                    function.current_revision().number().to_string(),
+                )
+                .header(EXECUTION_ID_HEADER, execution)
+                .body("[1, 2]")
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status().as_u16(), status.as_u16());
+            let result = results.recv().await.unwrap();
+            assert_eq!(result.exit(), &exit);
+            if status == StatusCode::OK {
+                assert_eq!(response.text().await.unwrap(), "3");
+                assert!(result.error().is_none());
+            } else {
+                assert!(result.error().unwrap().contains("invalid output"));
+            }
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
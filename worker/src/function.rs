@@ -1,22 +1,531 @@
-use std::{collections::BTreeMap, path::PathBuf};
+use std::{
+    collections::BTreeMap,
+    path::{self, Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
 
-use wasmtime::Module;
+use api::{
+    function::registration::{Function, FunctionId, RevisionNumber, Runtime},
+    types::BlobAddress,
+};
+use reqwest::{Client, StatusCode};
+use tokio::sync::OnceCell;
+use wasmtime::{component::Component, Engine, InstancePre, Module};
+use wasmtime_wasi::preview1::WasiP1Ctx;
+use wasmtime_wasi_http::bindings::ProxyPre;
 
-const FUNCTIONS: [&str; 5] = ["hello", "add", "sub", "mul", "div"];
+use crate::{
+    cache::ModuleCache,
+    component::{ComponentState, HandlerPre},
+    engine::Linkers,
+};
 
-// TODO: in theory this would load from a set of addresses based on a list of assigned functions (depending on capacity etc)
-pub async fn load_functions(
-    function_dir: &PathBuf,
-    engine: &wasmtime::Engine,
-) -> anyhow::Result<BTreeMap<String, Module>> {
-    let mut function_map = BTreeMap::new();
-    let function_dir = function_dir.canonicalize()?.display().to_string();
+/// A compiled module or component with its imports already resolved against the worker's linkers
+pub enum Instantiable {
+    Module(InstancePre<WasiP1Ctx>),
+    Component(HandlerPre<ComponentState>),
+    HttpHandler(ProxyPre<ComponentState>),
+}
+
+pub struct LoadedFunction {
+    function: Function,
+    instantiable: Instantiable,
+    invoked: AtomicBool,
+}
+
+impl LoadedFunction {
+    pub fn module(function: Function, linkers: &Linkers, module: &Module) -> anyhow::Result<Self> {
+        Ok(Self::new(
+            function,
+            Instantiable::Module(linkers.module.instantiate_pre(module)?),
+        ))
+    }
+    pub fn component(
+        function: Function,
+        linkers: &Linkers,
+        component: &Component,
+    ) -> anyhow::Result<Self> {
+        Ok(Self::new(
+            function,
+            Instantiable::Component(HandlerPre::new(
+                linkers.component.instantiate_pre(component)?,
+            )?),
+        ))
+    }
+    pub fn http_handler(
+        function: Function,
+        linkers: &Linkers,
+        component: &Component,
+    ) -> anyhow::Result<Self> {
+        Ok(Self::new(
+            function,
+            Instantiable::HttpHandler(ProxyPre::new(
+                linkers.component.instantiate_pre(component)?,
+            )?),
+        ))
+    }
+    fn new(function: Function, instantiable: Instantiable) -> Self {
+        LoadedFunction {
+            function,
+            instantiable,
+            invoked: AtomicBool::new(false),
+        }
+    }
+    pub fn function(&self) -> &Function {
+        &self.function
+    }
+    pub fn instantiable(&self) -> &Instantiable {
+        &self.instantiable
+    }
+    pub fn runtime(&self) -> Runtime {
+        match self.instantiable {
+            Instantiable::Module(_) => Runtime::Wasm,
+            Instantiable::Component(_) => Runtime::Component,
+            Instantiable::HttpHandler(_) => Runtime::HttpHandler,
+        }
+    }
+    /// Mark the function as invoked, returning true if this is the first invocation since it was loaded (a cold start)
+    pub fn mark_invoked(&self) -> bool {
+        !self.invoked.swap(true, Ordering::Relaxed)
+    }
+}
+
+/// A revision being loaded or loaded
+type Slot = OnceCell<Arc<LoadedFunction>>;
+
+/// The slots of the revisions with when they were last used, at most `max` of them being kept
+struct Loaded {
+    slots: BTreeMap<(FunctionId, RevisionNumber), (Arc<Slot>, u64)>,
+    /// Incremented on every use, so that the least recently used slot has the lowest
+    uses: u64,
+    max: usize,
+}
+
+impl Loaded {
+    fn get(&mut self, key: &(FunctionId, RevisionNumber)) -> Option<Arc<Slot>> {
+        self.uses += 1;
+        let (slot, last_used) = self.slots.get_mut(key)?;
+        *last_used = self.uses;
+        Some(slot.clone())
+    }
+    /// The revision's slot, created if needed by evicting the least recently used revisions beyond `max`. Executions
+    /// of an evicted revision keep it until they are done, a later one loading it again from the module cache.
+    fn get_or_insert(&mut self, key: (FunctionId, RevisionNumber)) -> Arc<Slot> {
+        if let Some(slot) = self.get(&key) {
+            return slot;
+        }
+        while self.slots.len() >= self.max.max(1) {
+            let Some(oldest) = self
+                .slots
+                .iter()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(key, _)| *key)
+            else {
+                break;
+            };
+            tracing::info!(function = %oldest.0, revision = %oldest.1, "evicting the least recently used revision");
+            self.slots.remove(&oldest);
+        }
+        let slot = Arc::new(Slot::new());
+        self.slots.insert(key, (slot.clone(), self.uses));
+        slot
+    }
+}
+
+/// The functions this worker can execute, keyed by function id and revision so that a function registered again under
+/// the same name doesn't execute the code of the one it replaced.
+///
+/// Revisions are loaded on first use so that a revision uploaded or rolled back to after the worker started can be
+/// executed without a restart, and the least recently used ones are evicted beyond `max_loaded`. Blobs uploaded to the control plane are downloaded from it, other blob addresses are
+/// paths under `function_dir`.
+pub struct FunctionRegistry {
+    /// Only locked to look up or insert a slot, a revision is fetched and compiled in its slot without holding it so
+    /// that lookups of loaded revisions are never held up by a load
+    loaded: Mutex<Loaded>,
+    engine: Engine,
+    linkers: Arc<Linkers>,
+    cache: Arc<ModuleCache>,
+    client: Client,
+    control_plane_address: String,
+    function_dir: PathBuf,
+}
+
+impl FunctionRegistry {
+    pub fn new(
+        engine: Engine,
+        linkers: Linkers,
+        cache: ModuleCache,
+        client: Client,
+        control_plane_address: String,
+        function_dir: &Path,
+        max_loaded: usize,
+    ) -> anyhow::Result<Self> {
+        Ok(FunctionRegistry {
+            loaded: Mutex::new(Loaded {
+                slots: BTreeMap::new(),
+                uses: 0,
+                max: max_loaded,
+            }),
+            engine,
+            linkers: Arc::new(linkers),
+            cache: Arc::new(cache),
+            client,
+            control_plane_address,
+            function_dir: function_dir.canonicalize()?,
+        })
+    }
+
+    /// Load the current revisions of the registered functions, functions which haven't been built are skipped
+    // TODO: in theory this would only load the functions assigned to this worker (depending on capacity etc)
+    pub async fn preload(&self, functions: Vec<Function>) -> Vec<String> {
+        let mut loaded = Vec::new();
+        for function in functions {
+            let name = function.name().to_string();
+            let revision = function.current_revision().number();
+            match self.load(function, revision).await {
+                Ok(_) => loaded.push(format!("{name}@{revision}")),
+                Err(e) => tracing::warn!("skipping {name}: {e:#}"),
+            }
+        }
+        loaded
+    }
+
+    /// The loaded revisions by function name
+    pub fn loaded(&self) -> BTreeMap<String, Vec<RevisionNumber>> {
+        let mut functions = BTreeMap::<String, Vec<RevisionNumber>>::new();
+        for ((_, revision), (slot, _)) in self.loaded.lock().unwrap().slots.iter() {
+            if let Some(loaded) = slot.get() {
+                functions
+                    .entry(loaded.function().name().to_string())
+                    .or_default()
+                    .push(*revision);
+            }
+        }
+        functions
+    }
+
+    /// Get a revision of the function, the current one if unset, loading it if it hasn't been yet. The gateway passes
+    /// the id of the function it resolved, a loaded revision is then served without asking the control plane.
+    /// Returns `None` if the function or revision isn't registered, or the function was registered again since.
+    pub async fn get(
+        &self,
+        name: &str,
+        id: Option<FunctionId>,
+        revision: Option<RevisionNumber>,
+    ) -> anyhow::Result<Option<Arc<LoadedFunction>>> {
+        if let (Some(id), Some(revision)) = (id, revision) {
+            let slot = self.loaded.lock().unwrap().get(&(id, revision));
+            if let Some(loaded) = slot.as_deref().and_then(Slot::get) {
+                return Ok(Some(loaded.clone()));
+            }
+        }
+        let Some(function) = self.fetch_function(name).await? else {
+            return Ok(None);
+        };
+        if id.is_some_and(|id| id != *function.id()) {
+            return Ok(None);
+        }
+        let revision = revision.unwrap_or(function.current_revision().number());
+        if function.revision(revision).is_none() {
+            return Ok(None);
+        }
+        self.load(function, revision).await.map(Some)
+    }
+
+    /// Concurrent requests for a cold revision wait on the same slot so that it is compiled once
+    async fn load(
+        &self,
+        function: Function,
+        revision: RevisionNumber,
+    ) -> anyhow::Result<Arc<LoadedFunction>> {
+        let slot = self
+            .loaded
+            .lock()
+            .unwrap()
+            .get_or_insert((*function.id(), revision));
+        slot.get_or_try_init(|| self.compile(function, revision))
+            .await
+            .cloned()
+    }
+
+    async fn compile(
+        &self,
+        mut function: Function,
+        revision: RevisionNumber,
+    ) -> anyhow::Result<Arc<LoadedFunction>> {
+        // the loaded function's current revision is the one it serves
+        let blob_address = function
+            .set_current_revision(revision)
+            .ok_or_else(|| anyhow::anyhow!("revision {revision} doesn't exist"))?
+            .blob_address()
+            .clone();
+        let wasm = self.fetch_blob(&blob_address).await?;
+        let (engine, linkers, cache) = (
+            self.engine.clone(),
+            self.linkers.clone(),
+            self.cache.clone(),
+        );
+        let function = tokio::task::spawn_blocking(move || {
+            let name = function.name().to_string();
+            anyhow::Ok(match function.runtime() {
+                Runtime::Wasm => {
+                    let module = cache.load_bytes(&engine, &name, &wasm)?;
+                    LoadedFunction::module(function, &linkers, &module)?
+                }
+                Runtime::Component => {
+                    let component = cache.load_bytes(&engine, &name, &wasm)?;
+                    LoadedFunction::component(function, &linkers, &component)?
+                }
+                Runtime::HttpHandler => {
+                    let component = cache.load_bytes(&engine, &name, &wasm)?;
+                    LoadedFunction::http_handler(function, &linkers, &component)?
+                }
+            })
+        })
+        .await??;
+        Ok(Arc::new(function))
+    }
+
+    async fn fetch_function(&self, name: &str) -> anyhow::Result<Option<Function>> {
+        let response = self
+            .client
+            .get(format!("{}/functions/{name}", self.control_plane_address))
+            .send()
+            .await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        Ok(Some(response.error_for_status()?.json().await?))
+    }
+
+    async fn fetch_blob(&self, blob_address: &BlobAddress) -> anyhow::Result<Vec<u8>> {
+        if blob_address.digest().is_none() {
+            let path = local_blob_path(&self.function_dir, blob_address)?;
+            return tokio::fs::read(&path)
+                .await
+                .map_err(|e| anyhow::anyhow!("can't read {}: {e}", path.display()));
+        }
+        let wasm = self
+            .client
+            .get(format!(
+                "{}/blobs/{blob_address}",
+                self.control_plane_address
+            ))
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+        Ok(wasm.to_vec())
+    }
+}
+
+/// The path of a local blob under the function directory, addresses which could point outside of it are rejected
+fn local_blob_path(function_dir: &Path, blob_address: &BlobAddress) -> anyhow::Result<PathBuf> {
+    let relative = Path::new(blob_address.as_str());
+    if !relative.components().all(|component| {
+        matches!(
+            component,
+            path::Component::Normal(_) | path::Component::CurDir
+        )
+    }) {
+        anyhow::bail!(
+            "blob address {blob_address} isn't a relative path under the function directory"
+        );
+    }
+    Ok(function_dir.join(relative))
+}
+
+#[cfg(test)]
+mod tests {
+    use api::function::registration::{EgressPolicy, FunctionSpec, InputKind};
+
+    use super::*;
+    use crate::engine::{self, EngineOptions};
+
+    const ADD: &str = r#"
+        (module
+          (func (export "add") (param i32 i32) (result i32)
+            local.get 0
+            local.get 1
+            i32.add))
+    "#;
+
+    fn add(blob_address: &str) -> Function {
+        Function::new(FunctionSpec {
+            name: "add".to_string(),
+            description: String::new(),
+            runtime: Runtime::Wasm,
+            input_type: InputKind::List(Box::new(InputKind::Number)),
+            input_schema: None,
+            output_type: None,
+            output_schema: None,
+            blob_address: blob_address.to_string().into(),
+            note: String::new(),
+            egress: EgressPolicy::default(),
+            retry: None,
+        })
+    }
+
+    #[test]
+    fn local_blob_paths() {
+        let dir = Path::new("/functions");
+        let path = |address: &str| local_blob_path(dir, &address.to_string().into());
+        assert_eq!(
+            path("add/add.wasm").unwrap(),
+            Path::new("/functions/add/add.wasm")
+        );
+        assert_eq!(
+            path("./add.wasm").unwrap(),
+            Path::new("/functions/./add.wasm")
+        );
+        assert!(path("../secret.wasm").is_err());
+        assert!(path("add/../../secret.wasm").is_err());
+        assert!(path("/etc/passwd").is_err());
+    }
+
+    /// Loads run on the current-thread runtime of `#[tokio::test]`, and are cached by function id
+    #[tokio::test]
+    async fn loads_by_function_id() {
+        let dir =
+            std::env::temp_dir().join(format!("wasi-faas-functions-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("add.wat"), ADD).unwrap();
+        let engine = engine::build_engine(EngineOptions {
+            pooling_instances: None,
+        })
+        .unwrap();
+        let registry = FunctionRegistry::new(
+            engine.clone(),
+            engine::build_linkers(&engine).unwrap(),
+            ModuleCache::open(&dir.join("cache"), &engine).unwrap(),
+            Client::new(),
+            // nothing listens there, a lookup which isn't served from the registry fails
+            "http://127.0.0.1:9".to_string(),
+            &dir,
+            16,
+        )
+        .unwrap();
+
+        let function = add("add.wat");
+        let revision = function.current_revision().number();
+        assert_eq!(
+            registry
+                .preload(vec![function.clone(), add("../add.wat")])
+                .await,
+            ["add@1"]
+        );
+        assert_eq!(registry.loaded()["add"], [revision]);
+
+        let loaded = registry
+            .get("add", Some(*function.id()), Some(revision))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(loaded.function().id(), function.id());
+        assert_eq!(loaded.runtime(), Runtime::Wasm);
+        // the same name registered again isn't served the previous function's code
+        let replacement = add("add.wat");
+        assert!(registry
+            .get("add", Some(*replacement.id()), Some(revision))
+            .await
+            .is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn evicts_the_least_recently_used_revisions() {
+        let dir =
+            std::env::temp_dir().join(format!("wasi-faas-functions-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("add.wat"), ADD).unwrap();
+        let engine = engine::build_engine(EngineOptions {
+            pooling_instances: None,
+        })
+        .unwrap();
+        let registry = FunctionRegistry::new(
+            engine.clone(),
+            engine::build_linkers(&engine).unwrap(),
+            ModuleCache::open(&dir.join("cache"), &engine).unwrap(),
+            Client::new(),
+            "http://127.0.0.1:9".to_string(),
+            &dir,
+            2,
+        )
+        .unwrap();
+        let functions = [add("add.wat"), add("add.wat"), add("add.wat")];
+        let get = |function: &Function| {
+            registry.get(
+                "add",
+                Some(*function.id()),
+                Some(function.current_revision().number()),
+            )
+        };
+        registry.preload(functions[..2].to_vec()).await;
+        // the first function is used again, the second one becomes the least recently used
+        assert!(get(&functions[0]).await.is_ok());
+        registry.preload(functions[2..].to_vec()).await;
+
+        assert_eq!(registry.loaded()["add"].len(), 2);
+        assert!(get(&functions[0]).await.is_ok());
+        assert!(get(&functions[2]).await.is_ok());
+        // evicted, loading it again asks the control plane
+        assert!(get(&functions[1]).await.is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Executions of a loaded revision share its `InstancePre`, each one only instantiating it, including on the
+    /// pooling allocator whose single slot is reused once an execution is done with it
+    #[tokio::test]
+    async fn executions_share_the_loaded_instance_pre() {
+        let dir =
+            std::env::temp_dir().join(format!("wasi-faas-functions-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("add.wat"), ADD).unwrap();
+        let engine = engine::build_engine(EngineOptions {
+            pooling_instances: Some(1),
+        })
+        .unwrap();
+        let registry = FunctionRegistry::new(
+            engine.clone(),
+            engine::build_linkers(&engine).unwrap(),
+            ModuleCache::open(&dir.join("cache"), &engine).unwrap(),
+            Client::new(),
+            "http://127.0.0.1:9".to_string(),
+            &dir,
+            16,
+        )
+        .unwrap();
+        let function = add("add.wat");
+        let revision = function.current_revision().number();
+        registry.preload(vec![function.clone()]).await;
+
+        let mut previous: Option<Arc<LoadedFunction>> = None;
+        for i in 0..3 {
+            let loaded = registry
+                .get("add", Some(*function.id()), Some(revision))
+                .await
+                .unwrap()
+                .unwrap();
+            if let Some(previous) = &previous {
+                assert!(Arc::ptr_eq(previous, &loaded));
+            }
+            let params = [wasmtime::Val::I32(i), wasmtime::Val::I32(2)];
+            let results =
+                crate::executor::execute_wasm("add", &loaded, engine.clone(), "add", &params)
+                    .await
+                    .unwrap();
+            assert_eq!(results[0].unwrap_i32(), i + 2);
+            previous = Some(loaded);
+        }
+        // only the first execution is a cold start
+        assert!(!previous.unwrap().mark_invoked());
 
-    for fn_name in FUNCTIONS {
-        let template_string =
-            format!("{function_dir}/{fn_name}/target/wasm32-wasip1/debug/{fn_name}.wasm");
-        let module = Module::from_file(engine, template_string)?;
-        function_map.insert(fn_name.to_string(), module);
+        std::fs::remove_dir_all(&dir).unwrap();
     }
-    Ok(function_map)
 }
@@ -0,0 +1,163 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use api::{
+    types::TimeStamp,
+    worker::{Worker, WorkerStatus},
+};
+use reqwest::{Client, StatusCode};
+use serde::Serialize;
+
+use crate::status::Status;
+
+/// Delay of the first retry after a failed heartbeat, doubling with every failure up to the maximum backoff
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+
+#[derive(Serialize, Clone, Copy, Default, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectionState {
+    #[default]
+    Connected,
+    Disconnected,
+}
+
+#[derive(Serialize, Clone, Default, Debug)]
+pub struct ConnectivityReport {
+    state: ConnectionState,
+    last_heartbeat: Option<TimeStamp>,
+    consecutive_failures: u32,
+    last_error: Option<String>,
+    reregistrations: u32,
+}
+
+/// The worker's view of its connection to the control plane
+#[derive(Clone, Default)]
+pub struct Connectivity {
+    inner: Arc<Mutex<ConnectivityReport>>,
+}
+
+impl ConnectivityReport {
+    pub fn state(&self) -> ConnectionState {
+        self.state
+    }
+}
+
+impl Connectivity {
+    pub fn report(&self) -> ConnectivityReport {
+        self.inner.lock().unwrap().clone()
+    }
+    fn connected(&self) {
+        let mut report = self.inner.lock().unwrap();
+        report.state = ConnectionState::Connected;
+        report.last_heartbeat = Some(TimeStamp::now());
+        report.consecutive_failures = 0;
+    }
+    fn failed(&self, error: &anyhow::Error) -> u32 {
+        let mut report = self.inner.lock().unwrap();
+        report.state = ConnectionState::Disconnected;
+        report.consecutive_failures += 1;
+        report.last_error = Some(format!("{error:#}"));
+        report.consecutive_failures
+    }
+    fn reregistered(&self) {
+        self.inner.lock().unwrap().reregistrations += 1;
+    }
+}
+
+/// Sends heartbeats carrying the worker's report until it is aborted, registering the worker again under the same id
+/// when the control plane no longer knows it, e.g. after a restart
+pub struct Heartbeat {
+    pub client: Client,
+    pub control_plane_address: String,
+    pub worker: Worker,
+    pub interval: Duration,
+    pub max_backoff: Duration,
+    pub status: Status,
+}
+
+impl Heartbeat {
+    pub async fn run(mut self) {
+        let mut delay = self.interval;
+        loop {
+            // becoming full or having capacity again is reported right away so that the gateway routes accordingly
+            tokio::select! {
+                () = tokio::time::sleep(delay) => {},
+                () = self.status.saturation_changed() => {},
+            }
+            delay = match self.beat().await {
+                Ok(()) => {
+                    let connectivity = self.status.connectivity();
+                    if connectivity.report().state == ConnectionState::Disconnected {
+                        tracing::info!("reconnected to the control plane");
+                    }
+                    connectivity.connected();
+                    self.interval
+                }
+                Err(e) => {
+                    let failures = self.status.connectivity().failed(&e);
+                    let delay = backoff(failures, self.max_backoff);
+                    tracing::warn!(error = ?e, failures, "heartbeat failed, retrying in {delay:?}");
+                    delay
+                }
+            };
+        }
+    }
+
+    async fn beat(&mut self) -> anyhow::Result<()> {
+        let worker_url = format!(
+            "{}/workers/{}",
+            self.control_plane_address,
+            self.worker.id()
+        );
+        tracing::trace!("sending heartbeat to {}", worker_url);
+        let report = self.status.report();
+        let response = self
+            .client
+            .post(format!("{worker_url}/heartbeat"))
+            .json(&report)
+            .send()
+            .await?;
+        if response.status() != StatusCode::NOT_FOUND {
+            response.error_for_status()?;
+            return Ok(());
+        }
+        tracing::warn!("the control plane doesn't know this worker, registering it again");
+        self.worker.update_status(WorkerStatus::Available);
+        self.worker.update_report(report);
+        self.client
+            .put(&worker_url)
+            .json(&self.worker)
+            .send()
+            .await?
+            .error_for_status()?;
+        self.status.connectivity().reregistered();
+        Ok(())
+    }
+}
+
+/// Exponential backoff with jitter so that a fleet of workers doesn't reconnect to a restarted control plane at once
+fn backoff(failures: u32, max: Duration) -> Duration {
+    let backoff = INITIAL_BACKOFF
+        .saturating_mul(2u32.saturating_pow(failures.saturating_sub(1)))
+        .min(max);
+    backoff.mul_f64(0.5 + rand::random::<f64>() / 2.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_is_bounded() {
+        let max = Duration::from_secs(30);
+        for failures in 1..40 {
+            let delay = backoff(failures, max);
+            assert!(delay <= max, "{delay:?} after {failures} failures");
+            assert!(delay >= INITIAL_BACKOFF / 2);
+        }
+        assert!(backoff(1, max) <= INITIAL_BACKOFF);
+        assert!(backoff(20, max) >= max / 2);
+    }
+}
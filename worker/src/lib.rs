@@ -0,0 +1,11 @@
+pub mod cache;
+pub mod component;
+pub mod config;
+pub mod engine;
+pub mod executor;
+pub mod function;
+pub mod heartbeat;
+pub mod metrics;
+pub mod report;
+pub mod server;
+pub mod status;
@@ -1,168 +1,62 @@
-use std::{
-    path::PathBuf,
-    sync::{Arc, Mutex},
-    time::Duration,
-};
+use std::{path::PathBuf, time::Duration};
 
-use api::worker::{Worker, WorkerId, WorkerStatus};
-use axum::{routing::post, Router};
 use clap::Parser;
-use function::load_functions;
-use reqwest::Client;
-use serde_json::json;
-use wasmtime::{Config, Engine, Store};
-
-mod executor;
-mod function;
+use settings::Flags;
+use worker::{
+    config::{self, WorkerConfig},
+    metrics, server,
+};
 
 #[derive(clap::Parser)]
 struct Args {
-    #[clap(long, default_value = "http://localhost:3000")]
-    control_plane_address: String,
-    #[clap(long, default_value = "127.0.0.1:3001")]
-    address: String,
-    #[clap(long, default_value = "data/worker_id")]
-    worker_id_file: PathBuf,
-    #[clap(long, default_value = "functions-sample/")]
-    function_dir: PathBuf,
+    /// TOML configuration file, overridden by `WASI_FAAS_WORKER_*` environment variables and the flags
+    #[clap(long)]
+    config: Option<PathBuf>,
+    /// Print the effective configuration and exit
+    #[clap(long)]
+    print_config: bool,
+    #[clap(long)]
+    control_plane_address: Option<String>,
+    #[clap(long)]
+    address: Option<String>,
+    #[clap(long)]
+    worker_id_file: Option<String>,
+    #[clap(long)]
+    function_dir: Option<String>,
+    /// Directory holding compiled modules so they aren't recompiled on every start
+    #[clap(long)]
+    module_cache_dir: Option<String>,
+    /// Pre-allocate this many instance slots with the pooling allocator
+    #[clap(long)]
+    pooling_instances: Option<u32>,
+    /// Override any configuration key, e.g. `--set control_plane.heartbeat_interval_ms=1000`
+    #[clap(long = "set", short = 's', value_name = "KEY=VALUE")]
+    overrides: Vec<String>,
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    tokio::time::sleep(Duration::from_secs(1)).await;
-    tracing_subscriber::fmt::init();
-    let client = reqwest::Client::new();
-    let Args {
-        control_plane_address,
-        worker_id_file,
-        address,
-        function_dir,
-    } = Args::try_parse()?;
-    let worker_id =
-        match register_worker(&client, &worker_id_file, &control_plane_address, &address).await {
-            Ok(worker_id) => worker_id,
-            Err(e) => {
-                tracing::error!("worker not found");
-                tracing::warn!(
-                    "Clearing out the {} file to reset",
-                    worker_id_file.clone().display()
-                );
-                std::fs::remove_file(worker_id_file.clone())?;
-                register_worker(&client, &worker_id_file, &control_plane_address, &address).await?
-            }
-        };
-
-    let mut config = Config::new();
-    config.async_support(true);
-    let engine = Engine::new(&config)?;
-
-    let function_map = Arc::new(load_functions(&function_dir, &engine).await?);
-
-    let function_executor_api = Router::new()
-        .route("/hello", post(executor::execute_hello))
-        .route("/add", post(executor::execute_add))
-        .route("/sub", post(executor::execute_sub))
-        .route("/mul", post(executor::execute_mul))
-        .route("/div", post(executor::execute_div))
-        .with_state((function_map, engine));
-
-    let app = Router::new().nest("/execute", function_executor_api);
-
-    let listener = tokio::net::TcpListener::bind(address).await.unwrap();
-    tracing::info!("listening on {}", listener.local_addr().unwrap());
-
-    tokio::select! {
-        _ = heartbeat_loop(client.clone(), control_plane_address.clone(), worker_id) => {
-            tracing::info!("shutting down, heartbeat loop ended");
-        },
-        _ = axum::serve(listener, app) => {
-            tracing::info!("shutting down, server ended");
-        }
-    }
-    Ok(())
-}
-
-async fn heartbeat_loop(
-    client: Client,
-    control_plane_address: String,
-    worker_id: WorkerId,
-) -> anyhow::Result<()> {
-    loop {
-        tokio::time::sleep(Duration::from_secs(5)).await;
-        heartbeat(&client, &control_plane_address, &worker_id).await?;
+    let args = Args::try_parse()?;
+    let mut flags = Flags::default();
+    flags
+        .set("control_plane.address", args.control_plane_address)
+        .set("server.listen_address", args.address)
+        .set("storage.worker_id_file", args.worker_id_file)
+        .set("storage.function_dir", args.function_dir)
+        .set("storage.module_cache_dir", args.module_cache_dir)
+        .set("engine.pooling_instances", args.pooling_instances);
+    for pair in &args.overrides {
+        flags.set_pair(pair)?;
     }
-}
-
-async fn get_or_create_worker_id(
-    client: &Client,
-    worker_id_file: &PathBuf,
-    control_plane_address: &str,
-    address: &str,
-) -> anyhow::Result<WorkerId> {
-    match std::fs::read_to_string(worker_id_file.clone()) {
-        Ok(worker_id) => Ok(WorkerId::parse(&worker_id)?),
-        Err(_) => {
-            // create a new worker with the api
-            let worker = client
-                .post(format!("{control_plane_address}/workers"))
-                .json(&address)
-                .send()
-                .await?
-                .json::<Worker>()
-                .await?;
-            std::fs::write(worker_id_file.clone(), worker.id().to_string())?;
-            Ok(*worker.id())
-        }
+    let config: WorkerConfig = settings::load(args.config.as_deref(), config::ENV_PREFIX, flags)?;
+    if args.print_config {
+        print!("{}", settings::to_toml(&config)?);
+        return Ok(());
     }
-}
-
-/// Register the worker with the control plane and return the worker
-async fn register_worker(
-    client: &Client,
-    worker_id_file: &PathBuf,
-    control_plane_address: &str,
-    address: &str,
-) -> anyhow::Result<WorkerId> {
-    let worker_id =
-        get_or_create_worker_id(&client, &worker_id_file, &control_plane_address, &address).await?;
-
-    let worker_url = format!("{control_plane_address}/workers/{}", worker_id);
-
-    // update the worker status to available and the address to the current address
-    let current_worker: Option<Worker> =
-        client.get(worker_url.clone()).send().await?.json().await?;
 
-    tracing::info!("worker status: {:?}", current_worker);
-
-    if let Some(mut worker) = current_worker {
-        // set the worker status to available and update the address
-        worker.update_address(address.to_string().into());
-        worker.update_status(WorkerStatus::Available);
-
-        let worker = client
-            .patch(worker_url.clone())
-            .json(&json!(worker))
-            .send()
-            .await?
-            .json::<Worker>()
-            .await?;
-        Ok(*worker.id())
-    } else {
-        Err(anyhow::anyhow!("worker not found"))
-    }
-}
-
-async fn heartbeat(
-    client: &Client,
-    control_plane_address: &str,
-    worker_id: &WorkerId,
-) -> anyhow::Result<()> {
-    let worker_url = format!("{control_plane_address}/workers/{}", worker_id);
-    tracing::trace!("sending heartbeat to {}", worker_url);
-    client
-        .patch(worker_url.clone())
-        .send()
-        .await?
-        .error_for_status()?;
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    telemetry::init("worker")?;
+    server::run(config, metrics::install).await?;
+    telemetry::shutdown();
     Ok(())
 }
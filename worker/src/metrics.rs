@@ -0,0 +1,132 @@
+use std::time::Duration;
+
+use api::{types::ExitKind, worker::WorkerId};
+use axum::extract::State;
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+
+pub const INVOCATIONS: &str = "wasi_faas_worker_invocations_total";
+pub const EXECUTION_DURATION: &str = "wasi_faas_worker_execution_duration_seconds";
+pub const COLD_STARTS: &str = "wasi_faas_worker_cold_starts_total";
+pub const MODULE_COMPILE_DURATION: &str = "wasi_faas_worker_module_compile_duration_seconds";
+pub const MODULE_CACHE_LOOKUPS: &str = "wasi_faas_worker_module_cache_lookups_total";
+pub const FUEL_CONSUMED: &str = "wasi_faas_worker_fuel_consumed";
+pub const IN_FLIGHT: &str = "wasi_faas_worker_in_flight";
+pub const REJECTIONS: &str = "wasi_faas_worker_rejections_total";
+
+const DURATION_BUCKETS: [f64; 12] = [
+    0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 5.0,
+];
+const FUEL_BUCKETS: [f64; 8] = [1e3, 1e4, 1e5, 1e6, 1e7, 1e8, 1e9, 1e10];
+
+/// Install the global prometheus recorder, every metric recorded by this process is labelled with the worker id
+pub fn install(worker_id: &WorkerId) -> anyhow::Result<PrometheusHandle> {
+    Ok(builder(worker_id)?.install_recorder()?)
+}
+
+fn builder(worker_id: &WorkerId) -> anyhow::Result<PrometheusBuilder> {
+    Ok(PrometheusBuilder::new()
+        .add_global_label("worker", worker_id.to_string())
+        .set_buckets_for_metric(Matcher::Suffix("_seconds".to_string()), &DURATION_BUCKETS)?
+        .set_buckets_for_metric(Matcher::Full(FUEL_CONSUMED.to_string()), &FUEL_BUCKETS)?)
+}
+
+pub async fn render(State(handle): State<PrometheusHandle>) -> String {
+    handle.run_upkeep();
+    handle.render()
+}
+
+pub fn record_compile(function: &str, elapsed: Duration) {
+    metrics::histogram!(MODULE_COMPILE_DURATION, "function" => function.to_string())
+        .record(elapsed.as_secs_f64());
+}
+
+pub fn record_module_cache(function: &str, hit: bool) {
+    metrics::counter!(
+        MODULE_CACHE_LOOKUPS,
+        "function" => function.to_string(),
+        "result" => if hit { "hit" } else { "miss" },
+    )
+    .increment(1);
+}
+
+pub fn record_cold_start(function: &str) {
+    metrics::counter!(COLD_STARTS, "function" => function.to_string()).increment(1);
+}
+
+pub fn record_execution(function: &str, exit: &ExitKind, elapsed: Duration, fuel_consumed: u64) {
+    record_invocation(function, exit);
+    record_run(function, elapsed, fuel_consumed);
+}
+
+/// Count an execution by how it exited, as reported to the control plane
+pub fn record_invocation(function: &str, exit: &ExitKind) {
+    metrics::counter!(
+        INVOCATIONS,
+        "function" => function.to_string(),
+        "exit_kind" => exit_kind_label(exit),
+    )
+    .increment(1);
+}
+
+/// Record how long the guest ran and the fuel it consumed
+pub fn record_run(function: &str, elapsed: Duration, fuel_consumed: u64) {
+    metrics::histogram!(EXECUTION_DURATION, "function" => function.to_string())
+        .record(elapsed.as_secs_f64());
+    metrics::histogram!(FUEL_CONSUMED, "function" => function.to_string())
+        .record(fuel_consumed as f64);
+}
+
+pub fn record_in_flight(in_flight: u32) {
+    metrics::gauge!(IN_FLIGHT).set(in_flight);
+}
+
+/// An execution turned away because the worker was at capacity
+pub fn record_rejection(function: &str) {
+    metrics::counter!(REJECTIONS, "function" => function.to_string()).increment(1);
+}
+
+fn exit_kind_label(exit: &ExitKind) -> &'static str {
+    match exit {
+        ExitKind::Success => "success",
+        ExitKind::Failure { .. } => "failure",
+        ExitKind::TimeOut => "timeout",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use api::worker::Worker;
+
+    use super::*;
+
+    #[test]
+    fn renders_recorded_metrics() {
+        let worker = Worker::new("127.0.0.1:3001".to_string().into());
+        let id = worker.id();
+        let recorder = builder(id).unwrap().build_recorder();
+        let handle = recorder.handle();
+        metrics::with_local_recorder(&recorder, || {
+            record_execution("add", &ExitKind::Success, Duration::from_millis(2), 5_000);
+            record_invocation("add", &ExitKind::TimeOut);
+            record_module_cache("add", false);
+            record_in_flight(3);
+        });
+
+        let rendered = handle.render();
+        // every metric is labelled with the worker
+        assert!(rendered.contains(&format!(
+            r#"{INVOCATIONS}{{worker="{id}",function="add",exit_kind="success"}} 1"#
+        )));
+        assert!(rendered.contains(&format!(
+            r#"{INVOCATIONS}{{worker="{id}",function="add",exit_kind="timeout"}} 1"#
+        )));
+        assert!(rendered.contains(&format!(
+            r#"{MODULE_CACHE_LOOKUPS}{{worker="{id}",function="add",result="miss"}} 1"#
+        )));
+        assert!(rendered.contains(&format!(r#"{IN_FLIGHT}{{worker="{id}"}} 3"#)));
+        // fuel has buckets of its own rather than the duration ones
+        assert!(rendered.contains(&format!(
+            r#"{FUEL_CONSUMED}_bucket{{worker="{id}",function="add",le="10000"}} 1"#
+        )));
+    }
+}
@@ -0,0 +1,126 @@
+use std::sync::{Arc, Mutex};
+
+use api::{
+    function::execution::{ExecutionId, ExecutionResult, LogEntry, Output},
+    types::{ExitKind, TimeStamp},
+    worker::WorkerId,
+};
+use reqwest::Client;
+use tokio_util::task::TaskTracker;
+use tracing::Instrument;
+
+/// Log lines recorded on the host side while a function runs, shared with tasks spawned on its behalf
+#[derive(Clone, Default)]
+pub struct ExecutionLog {
+    entries: Arc<Mutex<Vec<LogEntry>>>,
+}
+
+impl ExecutionLog {
+    pub fn push(&self, message: String) {
+        tracing::info!("{message}");
+        self.entries.lock().unwrap().push(LogEntry::new(message));
+    }
+    pub fn take(&self) -> Vec<LogEntry> {
+        std::mem::take(&mut *self.entries.lock().unwrap())
+    }
+}
+
+/// Sends the results of executions dispatched by the gateway back to the control plane
+#[derive(Clone)]
+pub struct Reporter {
+    client: Client,
+    control_plane_address: String,
+    worker_id: WorkerId,
+    reports: TaskTracker,
+}
+
+impl Reporter {
+    pub fn new(client: Client, control_plane_address: String, worker_id: WorkerId) -> Self {
+        Reporter {
+            client,
+            control_plane_address,
+            worker_id,
+            reports: TaskTracker::new(),
+        }
+    }
+
+    /// Wait for the reports being sent, e.g. before deregistering once the worker has drained
+    pub async fn flush(&self) {
+        self.reports.close();
+        self.reports.wait().await;
+    }
+
+    /// Report the result in the background so that the caller's response isn't held up by the control plane
+    pub fn report(
+        &self,
+        execution: ExecutionId,
+        start_time: TimeStamp,
+        outcome: Result<Option<Output>, String>,
+        exit: ExitKind,
+        log: &ExecutionLog,
+    ) {
+        let result = ExecutionResult::new(start_time, outcome, exit, self.worker_id, log.take());
+        let url = format!(
+            "{}/executions/{execution}/result",
+            self.control_plane_address
+        );
+        let client = self.client.clone();
+        self.reports.spawn(
+            async move {
+                let response = client
+                    .put(url)
+                    .json(&result)
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status());
+                if let Err(e) = response {
+                    tracing::warn!(error = ?e, "failed to report execution result");
+                }
+            }
+            .in_current_span(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicU32, Ordering},
+        time::Duration,
+    };
+
+    use api::worker::Worker;
+    use axum::{extract::State, routing::put, Router};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn flush_waits_for_reports() {
+        let received = Arc::new(AtomicU32::new(0));
+        let complete = |State(received): State<Arc<AtomicU32>>| async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            received.fetch_add(1, Ordering::SeqCst);
+        };
+        let app = Router::new()
+            .route("/executions/:id/result", put(complete))
+            .with_state(received.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let worker = Worker::new("127.0.0.1:0".to_string().into());
+        let reporter = Reporter::new(Client::new(), address, *worker.id());
+        for _ in 0..2 {
+            let execution = ExecutionId::parse(&uuid::Uuid::new_v4().simple().to_string());
+            reporter.report(
+                execution.unwrap(),
+                TimeStamp::now(),
+                Ok(None),
+                ExitKind::Success,
+                &ExecutionLog::default(),
+            );
+        }
+        reporter.flush().await;
+        assert_eq!(received.load(Ordering::SeqCst), 2);
+    }
+}
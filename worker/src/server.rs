@@ -0,0 +1,293 @@
+use std::{path::Path, sync::Arc, time::Duration};
+
+use api::{
+    function::registration::Function,
+    worker::{Worker, WorkerId, WorkerStatus},
+};
+use axum::{
+    middleware,
+    routing::{any, get},
+    Router,
+};
+use metrics_exporter_prometheus::PrometheusHandle;
+use reqwest::Client;
+use serde_json::json;
+
+use crate::{
+    cache::ModuleCache,
+    config::WorkerConfig,
+    engine::{self, EngineOptions},
+    executor::{self, ExecutorState},
+    function::FunctionRegistry,
+    heartbeat::Heartbeat,
+    metrics,
+    report::Reporter,
+    status::{self, Status},
+};
+
+/// Register with the control plane and serve executions until a shutdown signal, then drain:
+/// the worker is marked as draining so that no new executions are sent to it, waits for the in-flight ones and the
+/// reports of their results up to the drain timeout and deregisters.
+///
+/// `install_metrics` is given the worker id once it is known, to install the recorder or reuse the one of the process.
+pub async fn run(
+    config: WorkerConfig,
+    install_metrics: impl FnOnce(&WorkerId) -> anyhow::Result<PrometheusHandle>,
+) -> anyhow::Result<()> {
+    // a worker which can't run anything would only turn every execution away
+    let capacity = config.capacity()?;
+    let client = settings::tls::client(
+        config.control_plane.ca.as_deref(),
+        Duration::from_millis(config.control_plane.timeout_ms),
+    )?;
+    let control_plane_address = config.control_plane.address;
+    let worker_id_file = Path::new(&config.storage.worker_id_file);
+    // the gateway reaches plain addresses over HTTP so the scheme is only registered with TLS
+    let address = match config.server.tls {
+        Some(_) => format!("https://{}", config.server.listen_address),
+        None => config.server.listen_address.clone(),
+    };
+    let worker =
+        match register_worker(&client, worker_id_file, &control_plane_address, &address).await {
+            Ok(worker) => worker,
+            Err(e) => {
+                tracing::error!(error = ?e, "worker not found");
+                tracing::warn!(
+                    "Clearing out the {} file to reset",
+                    worker_id_file.display()
+                );
+                std::fs::remove_file(worker_id_file)?;
+                register_worker(&client, worker_id_file, &control_plane_address, &address).await?
+            }
+        };
+    let worker_id = *worker.id();
+
+    let engine = engine::build_engine(EngineOptions {
+        pooling_instances: config.engine.pooling_instances,
+    })?;
+    let linkers = engine::build_linkers(&engine)?;
+    let module_cache = ModuleCache::open(Path::new(&config.storage.module_cache_dir), &engine)?;
+
+    let metrics_handle = install_metrics(&worker_id)?;
+
+    let registry = FunctionRegistry::new(
+        engine.clone(),
+        linkers,
+        module_cache,
+        client.clone(),
+        control_plane_address.clone(),
+        Path::new(&config.storage.function_dir),
+        config.limits.max_loaded_revisions,
+    )?;
+    let functions = fetch_functions(&client, &control_plane_address).await?;
+    tracing::info!("loaded functions: {:?}", registry.preload(functions).await);
+    let status = Status::new(Arc::new(registry), capacity);
+    let reporter = Reporter::new(client.clone(), control_plane_address.clone(), worker_id);
+
+    let function_executor_api = Router::new()
+        .route("/:function", any(executor::execute))
+        .route("/:function/*path", any(executor::execute))
+        .with_state(ExecutorState {
+            functions: status.functions().clone(),
+            engine,
+            reporter: reporter.clone(),
+            status: status.clone(),
+        });
+
+    let app = Router::new()
+        .nest("/execute", function_executor_api)
+        .route("/metrics", get(metrics::render))
+        .with_state(metrics_handle)
+        .merge(
+            Router::new()
+                .route("/healthz", get(status::healthz))
+                .route("/readyz", get(status::readyz))
+                .with_state(status.clone()),
+        )
+        .layer(middleware::from_fn(telemetry::propagate));
+
+    let listener = tokio::net::TcpListener::bind(&config.server.listen_address).await?;
+    tracing::info!(
+        "listening on {}://{}",
+        config.server.scheme(),
+        listener.local_addr()?
+    );
+
+    let (drain_tx, drain_rx) = tokio::sync::oneshot::channel::<()>();
+    let server = settings::tls::serve(listener, app, config.server.tls.as_ref(), async {
+        let _ = drain_rx.await;
+    });
+    tokio::pin!(server);
+    let heartbeat = tokio::spawn(
+        Heartbeat {
+            client: client.clone(),
+            control_plane_address: control_plane_address.clone(),
+            worker,
+            interval: Duration::from_millis(config.control_plane.heartbeat_interval_ms),
+            max_backoff: Duration::from_millis(config.control_plane.max_backoff_ms),
+            status: status.clone(),
+        }
+        .run(),
+    );
+    tokio::select! {
+        () = settings::shutdown_signal() => {
+            tracing::info!("received shutdown signal, draining");
+        },
+        result = &mut server => {
+            tracing::info!("shutting down, server ended");
+            heartbeat.abort();
+            return result;
+        }
+    }
+    // a re-registration would make the worker available again
+    heartbeat.abort();
+    status.start_draining();
+
+    if let Err(e) = update_status(
+        &client,
+        &control_plane_address,
+        &worker_id,
+        WorkerStatus::Draining,
+    )
+    .await
+    {
+        tracing::warn!(error = ?e, "failed to mark the worker as draining");
+    }
+    let _ = drain_tx.send(());
+    let drain_timeout = Duration::from_millis(config.shutdown.drain_timeout_ms);
+    let deadline = tokio::time::Instant::now() + drain_timeout;
+    match tokio::time::timeout_at(deadline, server).await {
+        Ok(Ok(())) => tracing::info!("in-flight executions finished"),
+        Ok(Err(e)) => tracing::error!(error = ?e, "server failed while draining"),
+        Err(_) => tracing::warn!(
+            "in-flight executions didn't finish within {drain_timeout:?}, abandoning them"
+        ),
+    }
+    // the results of the executions which finished are sent before the control plane forgets the worker
+    if tokio::time::timeout_at(deadline, reporter.flush())
+        .await
+        .is_err()
+    {
+        tracing::warn!("execution results weren't reported within {drain_timeout:?}");
+    }
+    match deregister(&client, &control_plane_address, &worker_id).await {
+        Ok(()) => {
+            // the next start registers a new worker rather than looking this one up
+            std::fs::remove_file(worker_id_file)?;
+            tracing::info!("deregistered worker {worker_id}");
+        }
+        Err(e) => tracing::warn!(error = ?e, "failed to deregister the worker"),
+    }
+    Ok(())
+}
+
+async fn get_or_create_worker_id(
+    client: &Client,
+    worker_id_file: &Path,
+    control_plane_address: &str,
+    address: &str,
+) -> anyhow::Result<WorkerId> {
+    match std::fs::read_to_string(worker_id_file) {
+        Ok(worker_id) => Ok(WorkerId::parse(&worker_id)?),
+        Err(_) => {
+            // create a new worker with the api
+            let worker = client
+                .post(format!("{control_plane_address}/workers"))
+                .json(&address)
+                .send()
+                .await?
+                .json::<Worker>()
+                .await?;
+            std::fs::write(worker_id_file, worker.id().to_string())?;
+            Ok(*worker.id())
+        }
+    }
+}
+
+/// Register the worker with the control plane and return the worker
+async fn register_worker(
+    client: &Client,
+    worker_id_file: &Path,
+    control_plane_address: &str,
+    address: &str,
+) -> anyhow::Result<Worker> {
+    let worker_id =
+        get_or_create_worker_id(client, worker_id_file, control_plane_address, address).await?;
+
+    let worker_url = format!("{control_plane_address}/workers/{}", worker_id);
+
+    // update the worker status to available and the address to the current address
+    let current_worker: Option<Worker> =
+        client.get(worker_url.clone()).send().await?.json().await?;
+
+    tracing::info!("worker status: {:?}", current_worker);
+
+    if let Some(mut worker) = current_worker {
+        // set the worker status to available and update the address
+        worker.update_address(address.to_string().into());
+        worker.update_status(WorkerStatus::Available);
+
+        let worker = client
+            .patch(worker_url.clone())
+            .json(&json!(worker))
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Worker>()
+            .await?;
+        Ok(worker)
+    } else {
+        Err(anyhow::anyhow!("worker not found"))
+    }
+}
+
+async fn update_status(
+    client: &Client,
+    control_plane_address: &str,
+    worker_id: &WorkerId,
+    status: WorkerStatus,
+) -> anyhow::Result<()> {
+    let worker_url = format!("{control_plane_address}/workers/{worker_id}");
+    let mut worker = client
+        .get(&worker_url)
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<Option<Worker>>()
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("worker not found"))?;
+    worker.update_status(status);
+    client
+        .patch(&worker_url)
+        .json(&worker)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+async fn deregister(
+    client: &Client,
+    control_plane_address: &str,
+    worker_id: &WorkerId,
+) -> anyhow::Result<()> {
+    client
+        .delete(format!("{control_plane_address}/workers/{worker_id}"))
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+async fn fetch_functions(
+    client: &Client,
+    control_plane_address: &str,
+) -> anyhow::Result<Vec<Function>> {
+    Ok(client
+        .get(format!("{control_plane_address}/functions"))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?)
+}
@@ -0,0 +1,214 @@
+use std::sync::{
+    atomic::{AtomicBool, AtomicU32, Ordering},
+    Arc,
+};
+
+use tokio::sync::Notify;
+
+use api::{function::registration::Runtime, worker::WorkerReport};
+use axum::{extract::State, http::StatusCode, Json};
+use serde::Serialize;
+
+use crate::{
+    function::FunctionRegistry,
+    heartbeat::{ConnectionState, Connectivity, ConnectivityReport},
+    metrics,
+};
+
+/// Every runtime can be executed by every worker for now
+const RUNTIMES: [Runtime; 3] = [Runtime::Wasm, Runtime::Component, Runtime::HttpHandler];
+
+/// The worker's live state, reported to the control plane with every heartbeat and locally on `/healthz` and `/readyz`
+#[derive(Clone)]
+pub struct Status {
+    functions: Arc<FunctionRegistry>,
+    /// How many executions run at once, the worker rejects executions beyond it
+    capacity: u32,
+    in_flight: Arc<AtomicU32>,
+    /// Notified when the worker becomes full or has capacity again
+    saturation: Arc<Notify>,
+    draining: Arc<AtomicBool>,
+    connectivity: Connectivity,
+}
+
+impl Status {
+    pub fn new(functions: Arc<FunctionRegistry>, capacity: u32) -> Self {
+        Status {
+            functions,
+            capacity,
+            in_flight: Arc::default(),
+            saturation: Arc::default(),
+            draining: Arc::default(),
+            connectivity: Connectivity::default(),
+        }
+    }
+    pub fn functions(&self) -> &Arc<FunctionRegistry> {
+        &self.functions
+    }
+    pub fn connectivity(&self) -> &Connectivity {
+        &self.connectivity
+    }
+    /// Count an execution as in flight until the returned guard is dropped, or `None` if the worker is full
+    pub fn try_enter(&self) -> Option<InFlight> {
+        let in_flight = 1 + self
+            .in_flight
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                (n < self.capacity).then_some(n + 1)
+            })
+            .ok()?;
+        metrics::record_in_flight(in_flight);
+        if in_flight == self.capacity {
+            self.saturation.notify_one();
+        }
+        Some(InFlight(self.clone()))
+    }
+    pub fn in_flight(&self) -> u32 {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+    /// Completes when the worker has become full or has capacity again since the last call
+    pub async fn saturation_changed(&self) {
+        self.saturation.notified().await;
+    }
+    pub fn start_draining(&self) {
+        self.draining.store(true, Ordering::SeqCst);
+    }
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::SeqCst)
+    }
+    pub fn report(&self) -> WorkerReport {
+        WorkerReport::new(
+            self.functions.loaded(),
+            self.in_flight(),
+            Some(self.capacity),
+            memory_bytes(),
+            env!("WASMTIME_VERSION").to_string(),
+            RUNTIMES.to_vec(),
+        )
+    }
+}
+
+pub struct InFlight(Status);
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        let status = &self.0;
+        let in_flight = status.in_flight.fetch_sub(1, Ordering::SeqCst) - 1;
+        metrics::record_in_flight(in_flight);
+        if in_flight + 1 == status.capacity {
+            status.saturation.notify_one();
+        }
+    }
+}
+
+/// Resident memory of the process, only known on Linux
+fn memory_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let kilobytes = status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))?
+        .trim()
+        .strip_suffix("kB")?
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+    Some(kilobytes * 1024)
+}
+
+#[derive(Serialize, Debug)]
+pub struct Health {
+    control_plane: ConnectivityReport,
+    in_flight: u32,
+    draining: bool,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Readiness {
+    ready: bool,
+    reasons: Vec<String>,
+}
+
+/// Liveness: the worker is up as long as it responds, whatever the state of its connection to the control plane
+#[tracing::instrument(skip(status))]
+pub async fn healthz(State(status): State<Status>) -> Json<Health> {
+    Json(Health {
+        control_plane: status.connectivity.report(),
+        in_flight: status.in_flight(),
+        draining: status.is_draining(),
+    })
+}
+
+/// Readiness: the worker takes executions when it is connected to the control plane and isn't draining
+#[tracing::instrument(skip(status))]
+pub async fn readyz(State(status): State<Status>) -> (StatusCode, Json<Readiness>) {
+    let mut reasons = Vec::new();
+    if status.is_draining() {
+        reasons.push("draining".to_string());
+    }
+    if status.connectivity.report().state() != ConnectionState::Connected {
+        reasons.push("disconnected from the control plane".to_string());
+    }
+    let ready = reasons.is_empty();
+    let code = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (code, Json(Readiness { ready, reasons }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::Path, time::Duration};
+
+    use reqwest::Client;
+
+    use super::*;
+    use crate::{
+        cache::ModuleCache,
+        engine::{self, EngineOptions},
+    };
+
+    fn status(dir: &Path, capacity: u32) -> Status {
+        let engine = engine::build_engine(EngineOptions {
+            pooling_instances: None,
+        })
+        .unwrap();
+        let registry = FunctionRegistry::new(
+            engine.clone(),
+            engine::build_linkers(&engine).unwrap(),
+            ModuleCache::open(&dir.join("cache"), &engine).unwrap(),
+            Client::new(),
+            "http://127.0.0.1:9".to_string(),
+            dir,
+            16,
+        )
+        .unwrap();
+        Status::new(Arc::new(registry), capacity)
+    }
+
+    #[tokio::test]
+    async fn turns_executions_away_at_capacity() {
+        let dir = std::env::temp_dir().join(format!("wasi-faas-status-{}", uuid::Uuid::new_v4()));
+        let status = status(&dir, 2);
+        let saturation_changed =
+            || tokio::time::timeout(Duration::from_secs(1), status.saturation_changed());
+
+        let first = status.try_enter().unwrap();
+        let second = status.try_enter().unwrap();
+        assert!(status.try_enter().is_none());
+        assert_eq!(status.in_flight(), 2);
+        assert!(status.report().is_full());
+        saturation_changed().await.unwrap();
+
+        drop(second);
+        assert_eq!(status.in_flight(), 1);
+        assert!(!status.report().is_full());
+        saturation_changed().await.unwrap();
+        let third = status.try_enter().unwrap();
+        saturation_changed().await.unwrap();
+
+        drop((first, third));
+        assert_eq!(status.in_flight(), 0);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}